@@ -0,0 +1,586 @@
+// menuorg API 客户端
+//
+// 所有接口共用一个带连接池的 reqwest::Client，每个请求单独设置超时。
+// 幂等的 GET 请求在网络错误、超时、429 和 5xx 时按指数退避 + 随机抖动重试。
+
+use log::{info, debug, warn};
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+use crate::OrderData;
+
+pub(crate) const DEFAULT_API_BASE_URL: &str = "https://api.menuorg.com/app/v1";
+
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(15);
+const ORDER_DETAIL_TIMEOUT: Duration = Duration::from_secs(10);
+const ORDER_LIST_TIMEOUT: Duration = Duration::from_secs(20);
+
+// ============= 请求/响应结构 =============
+
+// API统一响应格式
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct ApiResponse<T> {
+    pub status_code: i32,
+    #[serde(default)]
+    pub message: String,
+    pub data: Option<T>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct LoginBody<'a> {
+    pub username: &'a str,
+    pub password: &'a str, // 前端MD5加密后的密码
+    pub login_method: &'a str,
+    pub login_type: &'a str,
+    pub terminal: i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct LoginData {
+    pub token: String,
+    pub username: String,
+    pub user_id: String,
+    pub role_id: i32,
+    pub rd_id: i32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct OrderDetailQuery<'a> {
+    pub user_id: &'a str,
+    #[serde(rename = "id")]
+    pub order_id: &'a str,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct OrderListQuery<'a> {
+    pub user_id: &'a str,
+    pub rd_id: i32,
+    pub page: i32,
+    pub per_page: i32,
+}
+
+// 订单列表在 data.items 中。列表为空时接口可能不返回data，data或items格式不对时也按空列表处理
+fn order_list_items(data: Option<serde_json::Value>) -> Vec<serde_json::Value> {
+    match data {
+        Some(serde_json::Value::Object(mut data)) => match data.remove("items") {
+            Some(serde_json::Value::Array(items)) => items,
+            Some(serde_json::Value::Null) | None => Vec::new(),
+            Some(other) => {
+                warn!("⚠️ [ORDER_LIST] items字段不是数组，按空列表处理: {}", other);
+                Vec::new()
+            }
+        },
+        Some(serde_json::Value::Null) | None => Vec::new(),
+        Some(other) => {
+            warn!("⚠️ [ORDER_LIST] data字段不是对象，按空列表处理: {}", other);
+            Vec::new()
+        }
+    }
+}
+
+// ============= 错误类型 =============
+
+#[derive(Clone, Debug)]
+pub(crate) enum ApiError {
+    Timeout,                                     // 请求超时
+    Network(String),                             // 连接失败等网络错误
+    Unauthorized(String),                        // token过期或无效 (HTTP 401/403 或 status_code 401/403)
+    Http(u16),                                   // 其他HTTP错误状态码
+    Api { status_code: i64, message: String },   // 接口返回业务错误
+    Parse(String),                               // 响应格式错误
+    EmptyData,                                   // 接口成功但没有返回数据
+}
+
+impl ApiError {
+    // 是否值得重试 (仅用于幂等请求)
+    fn is_retryable(&self) -> bool {
+        match self {
+            ApiError::Timeout | ApiError::Network(_) => true,
+            ApiError::Http(status) => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+
+    // 服务器明确拒绝 (而不是网络问题)，例如账号密码错误
+    pub fn is_rejection(&self) -> bool {
+        matches!(self, ApiError::Unauthorized(_) | ApiError::Api { .. } | ApiError::EmptyData)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Timeout => write!(f, "请求超时"),
+            ApiError::Network(e) => write!(f, "网络请求失败: {}", e),
+            ApiError::Unauthorized(message) => write!(f, "登录已过期或无效: {}", message),
+            ApiError::Http(status) => write!(f, "API请求失败: HTTP {}", status),
+            ApiError::Api { status_code, message } => write!(f, "API返回错误 ({}): {}", status_code, message),
+            ApiError::Parse(e) => write!(f, "解析响应失败: {}", e),
+            ApiError::EmptyData => write!(f, "服务器返回数据为空"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            ApiError::Timeout
+        } else {
+            ApiError::Network(e.to_string())
+        }
+    }
+}
+
+// ============= 重试策略 =============
+
+#[derive(Clone, Debug)]
+pub(crate) struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    // 第attempt次失败后的等待时间: 指数退避，再加上最多50%的随机抖动
+    fn delay_for(&self, attempt: u32) -> Duration {
+        let exp = self.base_delay.saturating_mul(1u32 << attempt.min(16));
+        let capped = exp.min(self.max_delay);
+        let jitter_ms = rand::thread_rng().gen_range(0..=capped.as_millis() as u64 / 2);
+        capped + Duration::from_millis(jitter_ms)
+    }
+}
+
+// ============= 客户端 =============
+
+// 各接口的请求超时
+#[derive(Clone, Debug)]
+struct Timeouts {
+    login: Duration,
+    order_detail: Duration,
+    order_list: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            login: LOGIN_TIMEOUT,
+            order_detail: ORDER_DETAIL_TIMEOUT,
+            order_list: ORDER_LIST_TIMEOUT,
+        }
+    }
+}
+
+pub(crate) struct ApiClient {
+    http: reqwest::Client,
+    base_url: String,
+    retry: RetryPolicy,
+    timeouts: Timeouts,
+}
+
+static SHARED_CLIENT: Lazy<Result<ApiClient, ApiError>> = Lazy::new(|| {
+    let base_url = std::env::var("MENUORG_API_BASE_URL")
+        .unwrap_or_else(|_| DEFAULT_API_BASE_URL.to_string());
+    ApiClient::new(&base_url, RetryPolicy::default())
+});
+
+// 全局共享的API客户端 (HTTP客户端创建失败时返回错误，例如系统TLS初始化失败)
+pub(crate) fn shared() -> Result<&'static ApiClient, ApiError> {
+    SHARED_CLIENT.as_ref().map_err(Clone::clone)
+}
+
+impl ApiClient {
+    pub fn new(base_url: &str, retry: RetryPolicy) -> Result<Self, ApiError> {
+        let http = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .pool_idle_timeout(Duration::from_secs(90))
+            .pool_max_idle_per_host(4)
+            .build()
+            .map_err(|e| ApiError::Network(format!("创建HTTP客户端失败: {}", e)))?;
+
+        Ok(ApiClient {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            retry,
+            timeouts: Timeouts::default(),
+        })
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    // 登录 (POST 不自动重试)
+    pub async fn login(&self, username: &str, password: &str) -> Result<LoginData, ApiError> {
+        let body = LoginBody {
+            username,
+            password,
+            login_method: "password",
+            login_type: "platform",
+            terminal: 1,
+        };
+
+        info!("🔐 [LOGIN] 发送登录请求: {}", username);
+
+        let response = self.http
+            .post(format!("{}/login", self.base_url))
+            .timeout(self.timeouts.login)
+            .json(&body)
+            .send()
+            .await?;
+
+        let envelope = Self::read_envelope("LOGIN", response).await?;
+        Self::take_data(envelope)
+    }
+
+    // 获取单个订单详情
+    pub async fn get_order_detail(&self, token: &str, user_id: &str, order_id: &str) -> Result<OrderData, ApiError> {
+        let query = OrderDetailQuery { user_id, order_id };
+        info!("🔍 [API] 获取订单详情: {}", order_id);
+
+        let envelope = self
+            .get_with_retry("API", "/order/get_by_id", &query, token, self.timeouts.order_detail)
+            .await?;
+        let order: OrderData = Self::take_data(envelope)?;
+
+        info!("✅ [API] 订单解析成功: {}", order.order_id);
+        Ok(order)
+    }
+
+    // 获取订单列表
+    pub async fn get_order_list(&self, token: &str, query: &OrderListQuery<'_>) -> Result<Vec<OrderData>, ApiError> {
+        info!("🔍 [ORDER_LIST] 获取订单列表: 餐厅ID={}, 页码={}, 每页={}", query.rd_id, query.page, query.per_page);
+
+        let envelope = self
+            .get_with_retry("ORDER_LIST", "/order/get_by_rd_two", query, token, self.timeouts.order_list)
+            .await?;
+
+        let items = order_list_items(envelope.data);
+        let total = items.len();
+        let mut orders = Vec::with_capacity(total);
+        // 单条订单解析失败时跳过而不是整页失败
+        for (index, item) in items.into_iter().enumerate() {
+            match serde_json::from_value::<OrderData>(item.clone()) {
+                Ok(order) => orders.push(order),
+                Err(e) => {
+                    warn!("❌ [ORDER_LIST] 订单解析失败 {}/{}: {}", index + 1, total, e);
+                    debug!("❌ [ORDER_LIST] 原始订单数据: {}", item);
+                }
+            }
+        }
+
+        info!("✅ [ORDER_LIST] 成功解析 {} / {} 个订单", orders.len(), total);
+        Ok(orders)
+    }
+
+    // 带重试的GET请求，返回状态码为200的响应
+    async fn get_with_retry<Q: Serialize + ?Sized>(
+        &self,
+        tag: &str,
+        path: &str,
+        query: &Q,
+        token: &str,
+        timeout: Duration,
+    ) -> Result<ApiResponse<serde_json::Value>, ApiError> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut attempt = 0;
+
+        loop {
+            let result = async {
+                let response = self.http
+                    .get(&url)
+                    .query(query)
+                    .header("authorization", token)
+                    .timeout(timeout)
+                    .send()
+                    .await?;
+                Self::read_envelope(tag, response).await
+            }
+            .await;
+
+            match result {
+                Err(e) if e.is_retryable() && attempt + 1 < self.retry.max_attempts => {
+                    let delay = self.retry.delay_for(attempt);
+                    attempt += 1;
+                    warn!("⚠️ [{}] 请求失败: {}，{}ms 后第 {} 次重试", tag, e, delay.as_millis(), attempt);
+                    tokio::time::sleep(delay).await;
+                }
+                other => return other,
+            }
+        }
+    }
+
+    // 检查HTTP状态码和API状态码，返回统一响应
+    async fn read_envelope(tag: &str, response: reqwest::Response) -> Result<ApiResponse<serde_json::Value>, ApiError> {
+        let http_status = response.status();
+        debug!("📡 [{}] HTTP响应状态码: {}", tag, http_status);
+
+        if matches!(http_status.as_u16(), 401 | 403) {
+            return Err(ApiError::Unauthorized(format!("HTTP {}", http_status.as_u16())));
+        }
+        if !http_status.is_success() {
+            return Err(ApiError::Http(http_status.as_u16()));
+        }
+
+        let text = response.text().await?;
+        debug!("📄 [{}] API响应内容: {}", tag, text);
+
+        let envelope: ApiResponse<serde_json::Value> = serde_json::from_str(&text)
+            .map_err(|e| ApiError::Parse(e.to_string()))?;
+
+        match envelope.status_code {
+            200 => Ok(envelope),
+            401 | 403 => Err(ApiError::Unauthorized(envelope.message)),
+            status_code => Err(ApiError::Api {
+                status_code: status_code as i64,
+                message: if envelope.message.is_empty() { "未知错误".to_string() } else { envelope.message },
+            }),
+        }
+    }
+
+    fn take_data<T: DeserializeOwned>(envelope: ApiResponse<serde_json::Value>) -> Result<T, ApiError> {
+        match envelope.data {
+            Some(value) if value.is_object() => {
+                serde_json::from_value(value).map_err(|e| ApiError::Parse(e.to_string()))
+            }
+            _ => Err(ApiError::EmptyData),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use axum::routing::{get, post};
+    use axum::Router;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    // 模拟服务器的回复: HTTP状态码、响应内容、回复前等待的毫秒数
+    type Reply = (u16, String, u64);
+
+    // 在本地端口启动模拟API服务器，respond 按请求序号 (从0开始) 返回回复；返回地址和请求计数
+    async fn mock_server<F>(respond: F) -> (String, Arc<AtomicUsize>)
+    where
+        F: Fn(usize) -> Reply + Clone + Send + Sync + 'static,
+    {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let handler = move || {
+            let (status, body, delay_ms) = respond(counter.fetch_add(1, Ordering::SeqCst));
+            async move {
+                if delay_ms > 0 {
+                    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                }
+                (StatusCode::from_u16(status).unwrap(), body)
+            }
+        };
+        let app = Router::new()
+            .route("/login", post(handler.clone()))
+            .route("/order/get_by_id", get(handler.clone()))
+            .route("/order/get_by_rd_two", get(handler));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{}", addr), hits)
+    }
+
+    fn test_client(base_url: &str) -> ApiClient {
+        let retry = RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(10),
+            max_delay: Duration::from_millis(40),
+        };
+        let mut client = ApiClient::new(base_url, retry).unwrap();
+        client.timeouts = Timeouts {
+            login: Duration::from_millis(300),
+            order_detail: Duration::from_millis(300),
+            order_list: Duration::from_millis(300),
+        };
+        client
+    }
+
+    fn envelope(status_code: i32, data: serde_json::Value) -> String {
+        json!({ "status_code": status_code, "message": "mock", "data": data }).to_string()
+    }
+
+    fn order_json() -> serde_json::Value {
+        serde_json::to_value(crate::sample_test_order()).unwrap()
+    }
+
+    async fn fetch_order(base_url: &str) -> Result<OrderData, ApiError> {
+        test_client(base_url).get_order_detail("token", "6305000000012", "23410121749595834").await
+    }
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(300),
+        };
+        for _ in 0..20 {
+            let first = policy.delay_for(0);
+            assert!(first >= Duration::from_millis(100) && first <= Duration::from_millis(150));
+            let second = policy.delay_for(1);
+            assert!(second >= Duration::from_millis(200) && second <= Duration::from_millis(300));
+            let capped = policy.delay_for(10);
+            assert!(capped >= Duration::from_millis(300) && capped <= Duration::from_millis(450));
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_rate_limit_and_server_errors_then_succeeds() {
+        let (url, hits) = mock_server(|n| match n {
+            0 => (429, String::new(), 0),
+            1 => (503, String::new(), 0),
+            _ => (200, envelope(200, order_json()), 0),
+        })
+        .await;
+
+        let order = fetch_order(&url).await.unwrap();
+        assert_eq!(order.order_id, "23410121749595834");
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (url, hits) = mock_server(|_| (500, String::new(), 0)).await;
+
+        assert!(matches!(fetch_order(&url).await, Err(ApiError::Http(500))));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn http_unauthorized_is_mapped_and_not_retried() {
+        for status in [401, 403] {
+            let (url, hits) = mock_server(move |_| (status, String::new(), 0)).await;
+
+            let error = fetch_order(&url).await.unwrap_err();
+            assert!(matches!(error, ApiError::Unauthorized(_)), "{:?}", error);
+            assert!(error.is_rejection());
+            assert_eq!(hits.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn api_status_unauthorized_is_mapped() {
+        let (url, _) = mock_server(|_| (200, envelope(401, serde_json::Value::Null), 0)).await;
+
+        match fetch_order(&url).await {
+            Err(ApiError::Unauthorized(message)) => assert_eq!(message, "mock"),
+            other => panic!("unexpected result: {:?}", other.map(|o| o.order_id)),
+        }
+    }
+
+    #[tokio::test]
+    async fn business_errors_are_not_retried() {
+        let (url, hits) = mock_server(|_| (200, envelope(500, serde_json::Value::Null), 0)).await;
+
+        let error = fetch_order(&url).await.unwrap_err();
+        assert!(matches!(error, ApiError::Api { status_code: 500, .. }), "{:?}", error);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn timeouts_are_retried_then_reported() {
+        let (url, hits) = mock_server(|_| (200, envelope(200, order_json()), 2_000)).await;
+
+        assert!(matches!(fetch_order(&url).await, Err(ApiError::Timeout)));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn slow_first_response_recovers_on_retry() {
+        let (url, hits) = mock_server(|n| {
+            let delay = if n == 0 { 2_000 } else { 0 };
+            (200, envelope(200, order_json()), delay)
+        })
+        .await;
+
+        assert!(fetch_order(&url).await.is_ok());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn malformed_json_is_a_parse_error() {
+        let (url, hits) = mock_server(|_| (200, "<html>502 Bad Gateway</html>".to_string(), 0)).await;
+
+        assert!(matches!(fetch_order(&url).await, Err(ApiError::Parse(_))));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn unexpected_order_shape_is_a_parse_error() {
+        let (url, _) = mock_server(|_| (200, envelope(200, json!({ "order_id": 5 })), 0)).await;
+        assert!(matches!(fetch_order(&url).await, Err(ApiError::Parse(_))));
+
+        let (url, _) = mock_server(|_| (200, envelope(200, serde_json::Value::Null), 0)).await;
+        assert!(matches!(fetch_order(&url).await, Err(ApiError::EmptyData)));
+    }
+
+    #[tokio::test]
+    async fn order_list_skips_orders_that_fail_to_decode() {
+        let (url, _) = mock_server(|_| {
+            let items = json!({ "items": [order_json(), { "order_id": "broken" }] });
+            (200, envelope(200, items), 0)
+        })
+        .await;
+
+        let query = OrderListQuery { user_id: "6305000000012", rd_id: 341, page: 1, per_page: 20 };
+        let orders = test_client(&url).get_order_list("token", &query).await.unwrap();
+        assert_eq!(orders.len(), 1);
+
+        let (url, _) = mock_server(|_| (200, envelope(200, serde_json::Value::Null), 0)).await;
+        assert!(test_client(&url).get_order_list("token", &query).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_order_list_is_empty() {
+        assert_eq!(order_list_items(Some(json!({ "items": [1, 2] }))).len(), 2);
+        for data in [json!({ "items": {} }), json!({ "items": null }), json!({ "total": 0 }), json!([]), json!("")] {
+            assert!(order_list_items(Some(data.clone())).is_empty(), "{}", data);
+        }
+        assert!(order_list_items(None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn login_is_not_retried() {
+        let (url, hits) = mock_server(|_| (503, String::new(), 0)).await;
+
+        assert!(matches!(test_client(&url).login("shop01", "md5").await, Err(ApiError::Http(503))));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn login_returns_session_data() {
+        let (url, _) = mock_server(|_| {
+            let data = json!({ "token": "t", "username": "shop01", "user_id": "42", "role_id": 1, "rd_id": 341 });
+            (200, envelope(200, data), 0)
+        })
+        .await;
+
+        let data = test_client(&url).login("shop01", "md5").await.unwrap();
+        assert_eq!((data.user_id.as_str(), data.rd_id), ("42", 341));
+    }
+}
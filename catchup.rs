@@ -0,0 +1,426 @@
+// 漏单补打
+//
+// WebSocket 断线重连期间或电脑离线时推送的订单通知会丢失。
+// 每次重连成功后、以及断线期间定期调用订单列表接口，与本地保存的
+// "最后看到的订单时间" 和 "已打印订单台账" 对比，把未打印的订单送入正常打印流程。
+
+use log::{info, debug, warn, error};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::api_client::{self, ApiError, OrderListQuery};
+use crate::error::AppError;
+use crate::events::{AppContext, Ctx};
+use crate::order_changes;
+use crate::print_jobs::{JobPayload, JobStatus};
+use crate::OrderData;
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+// 每次补打最多翻页数和每页数量
+const MAX_PAGES: i32 = 5;
+const PER_PAGE: i32 = 20;
+// 前端收到推送通知后正在处理的订单，在这段时间内不补打，避免重复
+const NOTIFIED_GRACE_SECONDS: i64 = 300;
+// 台账保留天数
+const LEDGER_RETENTION_DAYS: i64 = 7;
+// 客户已收货 (9) 和订单完成 (10) 的订单不再补打
+const FINISHED_ORDER_STATUSES: &[i32] = &[9, 10];
+// 断线期间的轮询间隔
+pub(crate) const OFFLINE_POLL_INTERVAL_SECS: u64 = 60;
+
+static CATCH_UP_RUNNING: AtomicBool = AtomicBool::new(false);
+
+// 已打印订单台账 (保存在 order_ledger.json)
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub(crate) struct OrderLedger {
+    // 最后看到的订单创建时间
+    #[serde(default)]
+    pub last_seen_create_time: Option<String>,
+    // 已打印订单: order_id -> 打印时间
+    #[serde(default)]
+    pub printed: HashMap<String, String>,
+    // 已推送给前端的订单: order_id -> 通知时间 (不持久化)
+    #[serde(skip)]
+    notified: HashMap<String, chrono::NaiveDateTime>,
+}
+
+fn ledger_file_path() -> PathBuf {
+    crate::get_app_data_dir().join("order_ledger.json")
+}
+
+impl OrderLedger {
+    pub fn load() -> Self {
+        let path = ledger_file_path();
+        match fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<OrderLedger>(&content) {
+                Ok(ledger) => {
+                    info!("📒 [CATCH_UP] 已加载订单台账: {} 条已打印记录", ledger.printed.len());
+                    ledger
+                }
+                Err(e) => {
+                    warn!("⚠️ [CATCH_UP] 订单台账格式错误，重新创建: {}", e);
+                    OrderLedger::default()
+                }
+            },
+            Err(_) => OrderLedger::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let path = ledger_file_path();
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        match serde_json::to_string_pretty(self) {
+            Ok(content) => {
+                if let Err(e) = fs::write(&path, content) {
+                    error!("❌ [CATCH_UP] 保存订单台账失败: {}", e);
+                }
+            }
+            Err(e) => error!("❌ [CATCH_UP] 序列化订单台账失败: {}", e),
+        }
+    }
+
+    pub fn is_printed(&self, order_id: &str) -> bool {
+        self.printed.contains_key(order_id)
+    }
+
+    pub fn mark_printed(&mut self, order_id: &str) {
+        let now = chrono::Local::now().format(TIME_FORMAT).to_string();
+        self.printed.insert(order_id.to_string(), now);
+        self.prune();
+    }
+
+    pub fn mark_notified(&mut self, order_id: &str) {
+        self.notified.insert(order_id.to_string(), chrono::Local::now().naive_local());
+    }
+
+    fn recently_notified(&self, order_id: &str) -> bool {
+        let now = chrono::Local::now().naive_local();
+        self.notified
+            .get(order_id)
+            .map(|at| (now - *at).num_seconds() < NOTIFIED_GRACE_SECONDS)
+            .unwrap_or(false)
+    }
+
+    // 更新最后看到的订单时间 (只前进不后退)
+    pub fn note_seen(&mut self, create_time: &str) {
+        let newer = match (&self.last_seen_create_time, parse_time(create_time)) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(current), Some(time)) => parse_time(current).map(|c| time > c).unwrap_or(true),
+        };
+        if newer {
+            self.last_seen_create_time = Some(create_time.to_string());
+        }
+    }
+
+    // 删除超过保留天数的记录
+    fn prune(&mut self) {
+        let cutoff = chrono::Local::now().naive_local() - chrono::Duration::days(LEDGER_RETENTION_DAYS);
+        self.printed.retain(|_, printed_at| parse_time(printed_at).map(|t| t >= cutoff).unwrap_or(false));
+
+        let notified_cutoff = chrono::Local::now().naive_local() - chrono::Duration::seconds(NOTIFIED_GRACE_SECONDS);
+        self.notified.retain(|_, at| *at >= notified_cutoff);
+    }
+
+    // 补打的起始时间: 最后看到的订单时间，还没有记录时为 None (不补打)
+    fn catch_up_cutoff(&self) -> Option<chrono::NaiveDateTime> {
+        self.last_seen_create_time.as_deref().and_then(parse_time)
+    }
+
+    // 首次运行或升级后台账为空时，把现有订单全部当作已看到 (不打印)：
+    // 记录最新订单之后的一秒，没有订单时记录当前时间，之后的订单才会补打
+    fn set_baseline(&mut self, orders: &[OrderData]) {
+        let newest = orders.iter().filter_map(|o| parse_time(&o.create_time)).max();
+        let marker = match newest {
+            Some(time) => time + chrono::Duration::seconds(1),
+            None => chrono::Local::now().naive_local(),
+        };
+        self.last_seen_create_time = Some(marker.format(TIME_FORMAT).to_string());
+    }
+}
+
+fn parse_time(time_str: &str) -> Option<chrono::NaiveDateTime> {
+    chrono::NaiveDateTime::parse_from_str(time_str, TIME_FORMAT).ok()
+}
+
+// 实际的补打起始时间: 长时间离线后只补打最近 max_age_hours 小时内的订单
+fn effective_cutoff(
+    ledger_cutoff: chrono::NaiveDateTime,
+    now: chrono::NaiveDateTime,
+    max_age_hours: u64,
+) -> chrono::NaiveDateTime {
+    if max_age_hours == 0 {
+        return ledger_cutoff;
+    }
+    ledger_cutoff.max(now - chrono::Duration::hours(max_age_hours as i64))
+}
+
+fn is_finished(order: &OrderData) -> bool {
+    FINISHED_ORDER_STATUSES.contains(&order.order_status)
+}
+
+// 打印成功后记录到台账
+pub(crate) fn record_printed(ctx: &dyn AppContext, order: &OrderData) {
+    let state = ctx.app_state();
+    let mut ledger = state.order_ledger.lock().unwrap();
+    ledger.mark_printed(&order.order_id);
+    ledger.note_seen(&order.create_time);
+    ledger.save();
+}
+
+// 推送通知发送到前端时记录，前端处理期间补打会跳过该订单
+pub(crate) fn record_notified(ctx: &dyn AppContext, order_id: &str) {
+    let state = ctx.app_state();
+    state.order_ledger.lock().unwrap().mark_notified(order_id);
+}
+
+// 检查并补打漏掉的订单，返回补打的订单数量
+pub(crate) async fn catch_up_missed_orders(ctx: &Ctx, reason: &str) -> Result<usize, AppError> {
+    if CATCH_UP_RUNNING.swap(true, Ordering::SeqCst) {
+        debug!("🔄 [CATCH_UP] 已有补打任务在运行，跳过 ({})", reason);
+        return Ok(0);
+    }
+
+    let result = run_catch_up(ctx, reason).await;
+    CATCH_UP_RUNNING.store(false, Ordering::SeqCst);
+    result
+}
+
+// 台账中还没有最后看到的订单时间：只记录现有订单，不补打
+async fn record_baseline(ctx: &Ctx, session: &crate::session::StoredSession, token: String) -> Result<usize, AppError> {
+    let query = OrderListQuery {
+        user_id: &session.user_id,
+        rd_id: session.rd_id,
+        page: 1,
+        per_page: PER_PAGE,
+    };
+    let orders = match api_client::shared()?.get_order_list(&token, &query).await {
+        Ok(orders) => orders,
+        Err(ApiError::Unauthorized(_)) => {
+            let token = crate::handle_session_expired(ctx).await?;
+            api_client::shared()?.get_order_list(&token, &query).await?
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut ledger = ctx.app_state().order_ledger.lock().unwrap();
+    ledger.set_baseline(&orders);
+    ledger.save();
+    info!(
+        "📒 [CATCH_UP] 首次检查漏单：{} 个现有订单记为已看到，不补打；之后从 {} 开始补打",
+        orders.len(),
+        ledger.last_seen_create_time.as_deref().unwrap_or_default()
+    );
+    Ok(0)
+}
+
+async fn run_catch_up(ctx: &Ctx, reason: &str) -> Result<usize, AppError> {
+    let state = ctx.app_state();
+    let session = state.session.lock().unwrap().clone().ok_or(AppError::NotLoggedIn)?;
+
+    let mut token = session.token.clone();
+    let ledger_cutoff = match state.order_ledger.lock().unwrap().catch_up_cutoff() {
+        Some(cutoff) => cutoff,
+        None => return record_baseline(ctx, &session, token).await,
+    };
+    let max_age_hours = state.config.lock().unwrap().catch_up.max_age_hours;
+    let cutoff = effective_cutoff(ledger_cutoff, chrono::Local::now().naive_local(), max_age_hours);
+    if cutoff > ledger_cutoff {
+        warn!(
+            "⚠️ [CATCH_UP] 离线时间超过 {} 小时，{} 之前的订单不补打",
+            max_age_hours,
+            cutoff.format(TIME_FORMAT)
+        );
+    }
+    info!("🔄 [CATCH_UP] 开始检查漏单 ({})，起始时间: {}", reason, cutoff.format(TIME_FORMAT));
+
+    let mut candidates: Vec<OrderData> = Vec::new();
+    // 已打印过的订单，检查是否被取消或修改
+    let mut already_printed: Vec<OrderData> = Vec::new();
+    let mut reached_end = false;
+
+    for page in 1..=MAX_PAGES {
+        let query = OrderListQuery {
+            user_id: &session.user_id,
+            rd_id: session.rd_id,
+            page,
+            per_page: PER_PAGE,
+        };
+
+        let orders = match api_client::shared()?.get_order_list(&token, &query).await {
+            Ok(orders) => orders,
+            Err(ApiError::Unauthorized(_)) => {
+                token = crate::handle_session_expired(ctx).await?;
+                api_client::shared()?.get_order_list(&token, &query).await?
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let page_len = orders.len();
+        let mut reached_cutoff = false;
+
+        for order in orders {
+            let created = parse_time(&order.create_time);
+            if created.map(|c| c < cutoff).unwrap_or(false) {
+                reached_cutoff = true;
+            }
+
+            if state.order_ledger.lock().unwrap().is_printed(&order.order_id) {
+                already_printed.push(order);
+                continue;
+            }
+            match created {
+                Some(created) if created >= cutoff => candidates.push(order),
+                Some(_) => {}
+                None => warn!("⚠️ [CATCH_UP] 订单 {} 创建时间格式无法识别: {}", order.order_id, order.create_time),
+            }
+        }
+
+        if reached_cutoff || page_len < PER_PAGE as usize {
+            reached_end = true;
+            break;
+        }
+    }
+    if !reached_end {
+        warn!(
+            "⚠️ [CATCH_UP] 已检查 {} 页仍未到达起始时间 {}，更早的订单不会补打",
+            MAX_PAGES,
+            cutoff.format(TIME_FORMAT)
+        );
+    }
+
+    for order in &already_printed {
+        if let Err(e) = order_changes::handle_order_updated(ctx, order).await {
+            error!("❌ [CATCH_UP] 处理订单 {} 的变化失败: {}", order.order_id, e);
+        }
+    }
+
+    // 过滤已打印和前端正在处理的订单，按时间从早到晚补打
+    let missed: Vec<OrderData> = {
+        let ledger = state.order_ledger.lock().unwrap();
+        candidates
+            .into_iter()
+            .filter(|o| !ledger.is_printed(&o.order_id) && !ledger.recently_notified(&o.order_id))
+            .filter(|o| !order_changes::is_cancelled(o)) // 已取消的订单不补打
+            .filter(|o| !is_finished(o)) // 已收货或已完成的订单不补打
+            .collect()
+    };
+    let mut missed = missed;
+    missed.sort_by(|a, b| a.create_time.cmp(&b.create_time));
+
+    if missed.is_empty() {
+        info!("✅ [CATCH_UP] 没有漏单");
+        return Ok(0);
+    }
+
+    warn!("⚠️ [CATCH_UP] 发现 {} 个未打印的订单，开始补打", missed.len());
+    ctx.emit("orders-caught-up", serde_json::json!({
+        "count": missed.len(),
+        "order_ids": missed.iter().map(|o| o.order_id.clone()).collect::<Vec<_>>(),
+        "reason": reason
+    }));
+
+    let mut printed = 0;
+    for order in missed {
+        info!("🖨️ [CATCH_UP] 补打订单: {} (创建于 {})", order.order_id, order.create_time);
+        let order_id = order.order_id.clone();
+
+        // 经过打印队列，与推送的订单按顺序打印并按台账去重
+        let payload = JobPayload::Order { order: Box::new(order), force: false };
+        match state.print_jobs.submit_and_wait(payload, "catch-up").await {
+            Ok(JobStatus::Completed { .. }) => printed += 1,
+            Ok(JobStatus::Skipped { .. }) => debug!("🔄 [CATCH_UP] 订单 {} 已由其他来源打印", order_id),
+            Ok(status) => {
+                // 没有可用打印机时停止补打，下次检查会重试
+                let error = status.into_result().err().map(|e| e.to_string()).unwrap_or_default();
+                error!("❌ [CATCH_UP] 补打订单 {} 失败: {}", order_id, error);
+                break;
+            }
+            Err(e) => {
+                error!("❌ [CATCH_UP] 订单 {} 加入打印队列失败: {}", order_id, e);
+                break;
+            }
+        }
+    }
+
+    Ok(printed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order_at(order_id: &str, create_time: &str) -> OrderData {
+        let mut order = crate::sample_test_order();
+        order.order_id = order_id.to_string();
+        order.create_time = create_time.to_string();
+        order
+    }
+
+    #[test]
+    fn empty_ledger_does_not_catch_up() {
+        assert!(OrderLedger::default().catch_up_cutoff().is_none());
+    }
+
+    #[test]
+    fn baseline_starts_after_the_newest_existing_order() {
+        let mut ledger = OrderLedger::default();
+        ledger.set_baseline(&[
+            order_at("1", "2025-01-15 18:30:00"),
+            order_at("2", "2025-01-15 19:05:10"),
+            order_at("3", "bad time"),
+        ]);
+        assert_eq!(ledger.last_seen_create_time.as_deref(), Some("2025-01-15 19:05:11"));
+
+        // 现有订单都早于补打起始时间
+        let cutoff = ledger.catch_up_cutoff().unwrap();
+        assert!(parse_time("2025-01-15 19:05:10").unwrap() < cutoff);
+        assert!(parse_time("2025-01-15 19:05:11").unwrap() >= cutoff);
+    }
+
+    #[test]
+    fn baseline_without_orders_uses_now() {
+        let mut ledger = OrderLedger::default();
+        ledger.set_baseline(&[]);
+        let cutoff = ledger.catch_up_cutoff().unwrap();
+        assert!((chrono::Local::now().naive_local() - cutoff).num_seconds().abs() < 5);
+    }
+
+    #[test]
+    fn cutoff_is_limited_to_max_age() {
+        let now = parse_time("2025-01-20 12:00:00").unwrap();
+        let weekend_ago = parse_time("2025-01-18 09:00:00").unwrap();
+        let an_hour_ago = parse_time("2025-01-20 11:00:00").unwrap();
+
+        assert_eq!(effective_cutoff(weekend_ago, now, 6), parse_time("2025-01-20 06:00:00").unwrap());
+        assert_eq!(effective_cutoff(an_hour_ago, now, 6), an_hour_ago);
+        assert_eq!(effective_cutoff(weekend_ago, now, 0), weekend_ago);
+    }
+
+    #[test]
+    fn finished_orders_are_not_caught_up() {
+        let mut order = order_at("1", "2025-01-15 18:30:00");
+        for status in [9, 10] {
+            order.order_status = status;
+            assert!(is_finished(&order));
+        }
+        for status in [1, 2, 3, 6, 8] {
+            order.order_status = status;
+            assert!(!is_finished(&order));
+        }
+    }
+
+    #[test]
+    fn last_seen_only_moves_forward() {
+        let mut ledger = OrderLedger::default();
+        ledger.note_seen("2025-01-15 18:30:00");
+        ledger.note_seen("2025-01-15 18:00:00");
+        ledger.note_seen("not a time");
+        assert_eq!(ledger.last_seen_create_time.as_deref(), Some("2025-01-15 18:30:00"));
+    }
+}
@@ -0,0 +1,363 @@
+// 简繁体识别和转换
+//
+// 识别：逐字判断汉字 (覆盖 Unicode 全部 CJK 统一汉字区块、扩展区和兼容区) 属于简体专用、
+// 繁体专用还是两者通用：
+//   1. 简繁对照表 (chinese_variants.txt) 中写法不同的简体字为简体专用，对应的繁体字为繁体专用
+//   2. 对照表中没有的字，在 GB2312 汉字区但不在 Big5 中的按简体专用处理
+//   3. 其余的字 (那、餐、菜等) 两种写法相同，只计入通用字，不影响判断
+//
+// 转换：先按词组最长匹配 (处理面/麵、发/髮等一简对多繁)，再逐字转换。ESC/POS 命令都是
+// ASCII 字符，转换整张小票不会影响命令。
+//
+// 打印时的转换由打印机手动设置中的 script_conversion 决定，默认不转换。自动模式按发送时
+// 使用的编码选择：Big5 只能表示繁体字，转为繁体；GB2312 只能表示简体字，转为简体。
+
+use encoding_rs::{BIG5, GBK};
+use log::debug;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use crate::encoding_calibration;
+use crate::printer_profiles::{self, ResolvedProfile};
+
+const BUNDLED_VARIANTS: &str = include_str!("chinese_variants.txt");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Script {
+    Simplified,
+    Traditional,
+}
+
+// 打印时的简繁转换
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ScriptConversion {
+    #[default]
+    Off,
+    Auto, // 按发送编码: BIG5 转繁体，GB2312 转简体，其他编码不转换
+    Simplified,
+    Traditional,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct ChineseCharacterAnalysis {
+    pub character_type: String, // "NONE", "SYMBOLS_ONLY", "COMMON", "SIMPLIFIED", "TRADITIONAL", "MIXED"
+    pub simplified_count: i32,
+    pub traditional_count: i32,
+    pub symbol_count: i32,
+    pub total_chars: i32,
+    pub confidence: f64, // 简繁专用字中占多数的一方的比例
+    // 简繁写法相同的汉字数量
+    #[serde(default)]
+    pub common_count: i32,
+}
+
+struct VariantTables {
+    s2t_chars: HashMap<char, char>,
+    t2s_chars: HashMap<char, char>,
+    s2t_phrases: HashMap<String, String>,
+    t2s_phrases: HashMap<String, String>,
+    max_phrase_chars: usize,
+    simplified_only: HashSet<char>,
+    traditional_only: HashSet<char>,
+}
+
+static TABLES: Lazy<VariantTables> = Lazy::new(|| load_tables(BUNDLED_VARIANTS));
+
+fn load_tables(source: &str) -> VariantTables {
+    let mut tables = VariantTables {
+        s2t_chars: HashMap::new(),
+        t2s_chars: HashMap::new(),
+        s2t_phrases: HashMap::new(),
+        t2s_phrases: HashMap::new(),
+        max_phrase_chars: 0,
+        simplified_only: HashSet::new(),
+        traditional_only: HashSet::new(),
+    };
+    // 繁体字 -> (在简体条目中的位置, 简体字)，多个简体对应同一个繁体时优先默认写法
+    let mut reverse: HashMap<char, (usize, char)> = HashMap::new();
+    let mut simplified_keys: HashSet<char> = HashSet::new();
+
+    for line in source.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((simplified, traditional)) = line.split_once('\t') else {
+            continue;
+        };
+
+        if simplified.chars().count() > 1 {
+            tables.max_phrase_chars = tables.max_phrase_chars.max(simplified.chars().count());
+            tables.s2t_phrases.insert(simplified.to_string(), traditional.to_string());
+            tables.t2s_phrases.insert(traditional.to_string(), simplified.to_string());
+            continue;
+        }
+
+        let Some(s) = simplified.chars().next() else { continue };
+        let variants: Vec<char> = traditional.split_whitespace().filter_map(|t| t.chars().next()).collect();
+        let Some(&default) = variants.first() else { continue };
+        simplified_keys.insert(s);
+
+        if default != s {
+            tables.s2t_chars.insert(s, default);
+        }
+        if !variants.contains(&s) {
+            tables.simplified_only.insert(s);
+        }
+        for (index, &t) in variants.iter().enumerate() {
+            if t == s {
+                continue;
+            }
+            let candidate = (index, s);
+            reverse
+                .entry(t)
+                .and_modify(|current| {
+                    if candidate < *current {
+                        *current = candidate;
+                    }
+                })
+                .or_insert(candidate);
+            // 作为可选写法列出、又在 GB2312 中的字 (藉、徵等) 简体也在用，不算繁体专用
+            if index == 0 || !in_gb2312(t) {
+                tables.traditional_only.insert(t);
+            }
+        }
+    }
+
+    for (t, (_, s)) in reverse {
+        tables.t2s_chars.insert(t, s);
+    }
+    // 本身也有简体条目的字 (例如 干 既是 乾 的简体，也是自身的繁体) 不是繁体专用
+    tables.traditional_only.retain(|c| !simplified_keys.contains(c));
+
+    debug!(
+        "🈶 [SCRIPT] 简繁对照表: {} 个单字, {} 个词组",
+        tables.s2t_chars.len(),
+        tables.s2t_phrases.len()
+    );
+    tables
+}
+
+// CJK 统一汉字 (基本区、扩展A-I) 和兼容汉字
+fn is_cjk_ideograph(ch: char) -> bool {
+    matches!(ch as u32,
+        0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xF900..=0xFAFF
+        | 0x20000..=0x2A6DF
+        | 0x2A700..=0x2EE5F
+        | 0x2F800..=0x2FA1F
+        | 0x30000..=0x323AF)
+}
+
+// 中文标点、全角符号、部首和笔画
+fn is_cjk_symbol(ch: char) -> bool {
+    matches!(ch as u32,
+        0x2E80..=0x2FDF     // 部首
+        | 0x3000..=0x303F   // 中文标点
+        | 0x31C0..=0x31EF   // 笔画
+        | 0x3200..=0x33FF   // 带圈字符、兼容符号
+        | 0xFE10..=0xFE1F   // 竖排标点
+        | 0xFE30..=0xFE4F   // 兼容标点
+        | 0xFF00..=0xFFEF)  // 全角字符 (包括 ￥)
+        || matches!(ch, '€' | '※')
+}
+
+fn in_gb2312(ch: char) -> bool {
+    let mut buf = [0u8; 4];
+    let (bytes, _, had_errors) = GBK.encode(ch.encode_utf8(&mut buf));
+    // GB2312 汉字区：第一字节 0xB0-0xF7，第二字节 0xA1-0xFE
+    !had_errors && bytes.len() == 2 && (0xB0..=0xF7).contains(&bytes[0]) && bytes[1] >= 0xA1
+}
+
+// GB2312 字符集 (EUC-CN)：ASCII，或转为GBK后两个字节都在 0xA1-0xFE (GBK扩展的字符不在其中)
+pub(crate) fn in_gb2312_charset(ch: char) -> bool {
+    if ch.is_ascii() {
+        return true;
+    }
+    let mut buf = [0u8; 4];
+    let (bytes, _, had_errors) = GBK.encode(ch.encode_utf8(&mut buf));
+    !had_errors && bytes.len() == 2 && bytes[0] >= 0xA1 && bytes[1] >= 0xA1
+}
+
+fn in_big5(ch: char) -> bool {
+    let mut buf = [0u8; 4];
+    let (_, _, had_errors) = BIG5.encode(ch.encode_utf8(&mut buf));
+    !had_errors
+}
+
+fn classify(ch: char) -> Option<Script> {
+    let tables = &*TABLES;
+    if tables.simplified_only.contains(&ch) {
+        Some(Script::Simplified)
+    } else if tables.traditional_only.contains(&ch) {
+        Some(Script::Traditional)
+    } else if tables.t2s_chars.contains_key(&ch) || tables.s2t_chars.contains_key(&ch) {
+        None
+    } else if in_gb2312(ch) && !in_big5(ch) {
+        Some(Script::Simplified)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn analyze(text: &str) -> ChineseCharacterAnalysis {
+    let mut simplified_count = 0;
+    let mut traditional_count = 0;
+    let mut common_count = 0;
+    let mut symbol_count = 0;
+    let mut total_chars = 0;
+
+    for ch in text.chars() {
+        total_chars += 1;
+        if is_cjk_ideograph(ch) {
+            match classify(ch) {
+                Some(Script::Simplified) => simplified_count += 1,
+                Some(Script::Traditional) => traditional_count += 1,
+                None => common_count += 1,
+            }
+        } else if is_cjk_symbol(ch) {
+            symbol_count += 1;
+        }
+    }
+
+    let character_type = if simplified_count + traditional_count + common_count == 0 {
+        if symbol_count > 0 { "SYMBOLS_ONLY" } else { "NONE" }
+    } else if simplified_count == 0 && traditional_count == 0 {
+        "COMMON"
+    } else if simplified_count > traditional_count * 2 {
+        "SIMPLIFIED"
+    } else if traditional_count > simplified_count * 2 {
+        "TRADITIONAL"
+    } else {
+        "MIXED"
+    };
+
+    let distinctive = simplified_count + traditional_count;
+    let confidence = if distinctive > 0 {
+        simplified_count.max(traditional_count) as f64 / distinctive as f64
+    } else {
+        0.0
+    };
+
+    ChineseCharacterAnalysis {
+        character_type: character_type.to_string(),
+        simplified_count,
+        traditional_count,
+        symbol_count,
+        total_chars,
+        confidence,
+        common_count,
+    }
+}
+
+// 转换为指定的简繁体，ASCII (包括 ESC/POS 命令) 原样保留
+pub(crate) fn convert(text: &str, target: Script) -> String {
+    let tables = &*TABLES;
+    let (chars_map, phrases) = match target {
+        Script::Traditional => (&tables.s2t_chars, &tables.s2t_phrases),
+        Script::Simplified => (&tables.t2s_chars, &tables.t2s_phrases),
+    };
+
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len());
+    let mut i = 0;
+    'outer: while i < chars.len() {
+        if is_cjk_ideograph(chars[i]) {
+            let longest = tables.max_phrase_chars.min(chars.len() - i);
+            for len in (2..=longest).rev() {
+                let candidate: String = chars[i..i + len].iter().collect();
+                if let Some(converted) = phrases.get(&candidate) {
+                    result.push_str(converted);
+                    i += len;
+                    continue 'outer;
+                }
+            }
+        }
+        result.push(*chars_map.get(&chars[i]).unwrap_or(&chars[i]));
+        i += 1;
+    }
+    result
+}
+
+// 按设置和发送编码决定转换方向
+fn target_for(mode: ScriptConversion, encoding: &str) -> Option<Script> {
+    match mode {
+        ScriptConversion::Off => None,
+        ScriptConversion::Simplified => Some(Script::Simplified),
+        ScriptConversion::Traditional => Some(Script::Traditional),
+        ScriptConversion::Auto => match encoding.to_uppercase().as_str() {
+            "BIG5" => Some(Script::Traditional),
+            "GB2312" => Some(Script::Simplified),
+            _ => None,
+        },
+    }
+}
+
+// 按指定编码发送前的转换 (print_order_with_encoding)
+pub(crate) fn convert_for_encoding<'a>(resolved: &ResolvedProfile, encoding: &str, content: &'a str) -> Cow<'a, str> {
+    match target_for(resolved.script_conversion, encoding) {
+        Some(target) => Cow::Owned(convert(content, target)),
+        None => Cow::Borrowed(content),
+    }
+}
+
+// 打印小票前的转换：编码为校准确认的推荐编码，没有校准时为 UTF-8
+pub(crate) fn convert_for_printer<'a>(printer_name: &str, content: &'a str) -> Cow<'a, str> {
+    let resolved = printer_profiles::resolve(printer_name);
+    let encoding = encoding_calibration::calibrated_encoding(&resolved).unwrap_or("UTF8").to_string();
+    convert_for_encoding(&resolved, &encoding, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_characters_are_common() {
+        // 那、餐 简繁写法相同，不是简体专用字
+        let analysis = analyze("那餐");
+        assert_eq!(analysis.character_type, "COMMON");
+        assert_eq!((analysis.common_count, analysis.simplified_count, analysis.traditional_count), (2, 0, 0));
+    }
+
+    #[test]
+    fn menus_are_classified_by_script() {
+        let simplified = analyze("宫保鸡丁、炒面、牛肉汤");
+        assert_eq!(simplified.character_type, "SIMPLIFIED");
+        assert_eq!(simplified.traditional_count, 0);
+        assert_eq!(simplified.symbol_count, 2);
+
+        let traditional = analyze("宮保雞丁、炒麵、牛肉湯");
+        assert_eq!(traditional.character_type, "TRADITIONAL");
+        assert_eq!(traditional.simplified_count, 0);
+        assert_eq!(traditional.confidence, 1.0);
+    }
+
+    #[test]
+    fn convert_prefers_the_longest_phrase() {
+        assert_eq!(convert("炒面", Script::Traditional), "炒麵");
+        assert_eq!(convert("热干面", Script::Traditional), "熱乾麵");
+        assert_eq!(convert("干部", Script::Traditional), "幹部");
+        assert_eq!(convert("干扰", Script::Traditional), "干擾");
+        // 没有词组时使用默认写法
+        assert_eq!(convert("干炒牛河", Script::Traditional), "乾炒牛河");
+        assert_eq!(convert("幹部 乾炒", Script::Simplified), "干部 干炒");
+        // ESC/POS 命令原样保留
+        assert_eq!(convert("\x1B@炒面\n", Script::Traditional), "\x1B@炒麵\n");
+    }
+
+    #[test]
+    fn auto_conversion_follows_the_encoding() {
+        assert_eq!(target_for(ScriptConversion::Auto, "BIG5"), Some(Script::Traditional));
+        assert_eq!(target_for(ScriptConversion::Auto, "big5"), Some(Script::Traditional));
+        assert_eq!(target_for(ScriptConversion::Auto, "GB2312"), Some(Script::Simplified));
+        assert_eq!(target_for(ScriptConversion::Auto, "GBK"), None);
+        assert_eq!(target_for(ScriptConversion::Off, "BIG5"), None);
+        assert_eq!(target_for(ScriptConversion::Traditional, "UTF8"), Some(Script::Traditional));
+    }
+}
@@ -0,0 +1,217 @@
+// 应用配置 (保存在 config.json)
+//
+// 各功能的设置作为独立的段落保存，缺少的段落使用默认值，
+// 因此旧版本的配置文件可以直接被新版本读取。
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::AppError;
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct AppConfig {
+    pub heartbeat: HeartbeatSettings,
+    pub http_api: HttpApiSettings,
+    pub headless: HeadlessSettings,
+    pub export: ExportSettings,
+    pub printer_actions: PrinterActionSettings,
+    pub invoice: InvoiceSettings,
+    pub catch_up: CatchUpSettings,
+}
+
+// WebSocket心跳设置
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct HeartbeatSettings {
+    pub ping_interval_secs: u64,
+    // 超过这个时间没有收到任何数据 (包括Pong) 就认为连接已失效并强制重连
+    pub pong_timeout_secs: u64,
+    // 是否额外发送应用层心跳消息 (部分路由器会丢弃WebSocket控制帧)
+    pub app_heartbeat_enabled: bool,
+    pub app_heartbeat_message: String,
+}
+
+impl Default for HeartbeatSettings {
+    fn default() -> Self {
+        HeartbeatSettings {
+            ping_interval_secs: 30,
+            pong_timeout_secs: 90,
+            app_heartbeat_enabled: false,
+            app_heartbeat_message: r#"{"type":"ping"}"#.to_string(),
+        }
+    }
+}
+
+// 心跳间隔下限 (0 会让 tokio::time::interval 直接 panic)
+const MIN_PING_INTERVAL_SECS: u64 = 5;
+
+impl HeartbeatSettings {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.ping_interval_secs < MIN_PING_INTERVAL_SECS {
+            return Err(AppError::InvalidArgument(format!("心跳间隔不能小于{}秒", MIN_PING_INTERVAL_SECS)));
+        }
+        if self.pong_timeout_secs <= self.ping_interval_secs {
+            return Err(AppError::InvalidArgument("心跳超时时间必须大于心跳间隔".to_string()));
+        }
+        Ok(())
+    }
+}
+
+// 本地HTTP打印接口设置 (只监听 127.0.0.1)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct HttpApiSettings {
+    pub enabled: bool,
+    pub port: u16,
+    // 调用方需要在 Authorization: Bearer <token> 中携带，为空时启动时自动生成
+    pub token: String,
+}
+
+impl Default for HttpApiSettings {
+    fn default() -> Self {
+        HttpApiSettings {
+            enabled: false,
+            port: 17878,
+            token: String::new(),
+        }
+    }
+}
+
+// 无界面模式设置
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct HeadlessSettings {
+    // 使用的打印机，为空时使用系统默认打印机
+    pub printers: Vec<crate::PrinterConfig>,
+}
+
+// 订单导出设置
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct ExportSettings {
+    // 导出的列 (order_export::COLUMNS 中的名称)，为空时使用默认列
+    pub columns: Vec<String>,
+    // CSV/XLSX 每个菜品一行 (订单信息重复)，否则每个订单一行
+    pub one_row_per_item: bool,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        ExportSettings {
+            columns: Vec::new(),
+            one_row_per_item: true,
+        }
+    }
+}
+
+// 开钱箱/蜂鸣器规则
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct PrinterActionSettings {
+    pub rules: Vec<crate::printer_actions::ActionRule>,
+}
+
+impl Default for PrinterActionSettings {
+    fn default() -> Self {
+        PrinterActionSettings {
+            rules: crate::printer_actions::default_rules(),
+        }
+    }
+}
+
+// A4/Letter 发票 (非小票打印机和导出PDF)
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct InvoiceSettings {
+    pub paper: crate::invoice_pdf::PaperSize,
+    // 发票左上角的标志 (PNG/JPEG)，为空时不显示
+    pub logo_path: String,
+    // 中文字体文件 (TTF/TTC)，为空时按系统中常见的位置查找
+    pub font_path: String,
+}
+
+// 重连后补打漏单的设置
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct CatchUpSettings {
+    // 只补打最近这么多小时内创建的订单，0 表示不限制
+    pub max_age_hours: u64,
+}
+
+impl Default for CatchUpSettings {
+    fn default() -> Self {
+        CatchUpSettings { max_age_hours: 6 }
+    }
+}
+
+fn config_file_path() -> PathBuf {
+    crate::get_app_data_dir().join("config.json")
+}
+
+// 解析配置文件，手动修改后无效的段落恢复默认值
+fn parse_config(content: &str) -> Result<AppConfig, serde_json::Error> {
+    let mut config: AppConfig = serde_json::from_str(content)?;
+    if let Err(e) = config.heartbeat.validate() {
+        warn!("⚠️ [CONFIG] 心跳设置无效，使用默认值: {}", e);
+        config.heartbeat = HeartbeatSettings::default();
+    }
+    Ok(config)
+}
+
+pub(crate) fn load_config() -> AppConfig {
+    let path = config_file_path();
+    match fs::read_to_string(&path) {
+        Ok(content) => match parse_config(&content) {
+            Ok(config) => {
+                info!("⚙️ [CONFIG] 已加载配置: {}", path.display());
+                config
+            }
+            Err(e) => {
+                warn!("⚠️ [CONFIG] 配置文件格式错误，使用默认配置: {}", e);
+                AppConfig::default()
+            }
+        },
+        Err(_) => AppConfig::default(),
+    }
+}
+
+pub(crate) fn save_config(config: &AppConfig) -> Result<(), AppError> {
+    let path = config_file_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let content = serde_json::to_string_pretty(config)?;
+    fs::write(&path, content).map_err(|e| AppError::Config(format!("保存配置失败: {}", e)))?;
+    info!("💾 [CONFIG] 配置已保存: {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_heartbeat_falls_back_to_defaults() {
+        for heartbeat in [
+            r#"{"ping_interval_secs": 0}"#,
+            r#"{"ping_interval_secs": 4, "pong_timeout_secs": 90}"#,
+            r#"{"ping_interval_secs": 60, "pong_timeout_secs": 60}"#,
+        ] {
+            let config = parse_config(&format!(r#"{{"heartbeat": {}}}"#, heartbeat)).unwrap();
+            assert_eq!(config.heartbeat.ping_interval_secs, 30, "{}", heartbeat);
+            assert_eq!(config.heartbeat.pong_timeout_secs, 90, "{}", heartbeat);
+        }
+    }
+
+    #[test]
+    fn valid_heartbeat_is_kept() {
+        let config = parse_config(r#"{"heartbeat": {"ping_interval_secs": 10, "pong_timeout_secs": 25}}"#).unwrap();
+        assert_eq!(config.heartbeat.ping_interval_secs, 10);
+        assert_eq!(config.heartbeat.pong_timeout_secs, 25);
+        assert!(HeartbeatSettings::default().validate().is_ok());
+    }
+}
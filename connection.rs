@@ -0,0 +1,241 @@
+// WebSocket 连接管理
+//
+// AppState 中只保存一个 ConnectionManager，同一时间最多运行一个连接任务。
+// 重复登录或手动重连时会先通知旧任务退出，避免多个重连循环重复推送订单。
+
+use log::{info, warn};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+use crate::events::{AppContext, Ctx};
+
+// 连接状态
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub(crate) enum ConnectionState {
+    Stopped { reason: Option<String> },
+    Connecting { attempt: u32 },
+    Connected { since: String },
+    BackingOff { attempt: u32, delay_secs: u64, next_retry_at: String },
+}
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// 连接统计 (前端诊断页面使用)
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ConnectionStats {
+    pub state: ConnectionState,
+    pub connected_since: Option<String>,
+    pub uptime_secs: u64,
+    pub last_message_at: Option<String>,
+    pub last_pong_at: Option<String>,
+    pub idle_secs: Option<u64>,        // 距离最后一次收到任何数据的秒数
+    pub reconnect_count: u32,          // 首次连接之后又成功连接的次数
+    pub liveness_timeouts: u32,        // 因心跳超时强制重连的次数
+    pub messages_received: u64,
+}
+
+#[derive(Default)]
+struct StatsInner {
+    connected_at: Option<Instant>,
+    connected_since: Option<String>,
+    last_activity: Option<Instant>,
+    last_message_at: Option<String>,
+    last_pong_at: Option<String>,
+    connections: u32,
+    liveness_timeouts: u32,
+    messages_received: u64,
+}
+
+struct SharedState {
+    generation: u64, // 每启动一个新的连接任务加1，旧任务的状态更新会被忽略
+    state: ConnectionState,
+    stats: StatsInner,
+}
+
+// 连接任务用来上报状态
+#[derive(Clone)]
+pub(crate) struct StatusReporter {
+    generation: u64,
+    shared: Arc<Mutex<SharedState>>,
+    ctx: Ctx,
+}
+
+impl StatusReporter {
+    // 更新状态并通知前端；任务已被替换时返回false
+    pub fn set(&self, state: ConnectionState) -> bool {
+        {
+            let mut shared = self.shared.lock().unwrap();
+            if shared.generation != self.generation {
+                return false;
+            }
+            shared.state = state.clone();
+        }
+        self.ctx.emit("websocket-state", &state);
+        true
+    }
+
+    // 在当前任务未被替换时修改统计信息
+    fn update_stats(&self, f: impl FnOnce(&mut StatsInner)) {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.generation == self.generation {
+            f(&mut shared.stats);
+        }
+    }
+
+    // 连接建立
+    pub fn record_connected(&self) {
+        let now = chrono::Local::now().format(TIME_FORMAT).to_string();
+        self.update_stats(|stats| {
+            stats.connected_at = Some(Instant::now());
+            stats.connected_since = Some(now);
+            stats.last_activity = Some(Instant::now());
+            stats.connections += 1;
+        });
+    }
+
+    // 连接断开
+    pub fn record_disconnected(&self) {
+        self.update_stats(|stats| {
+            stats.connected_at = None;
+            stats.connected_since = None;
+        });
+    }
+
+    // 收到业务消息
+    pub fn record_message(&self) {
+        let now = chrono::Local::now().format(TIME_FORMAT).to_string();
+        self.update_stats(|stats| {
+            stats.last_activity = Some(Instant::now());
+            stats.last_message_at = Some(now);
+            stats.messages_received += 1;
+        });
+    }
+
+    // 收到Pong
+    pub fn record_pong(&self) {
+        let now = chrono::Local::now().format(TIME_FORMAT).to_string();
+        self.update_stats(|stats| {
+            stats.last_activity = Some(Instant::now());
+            stats.last_pong_at = Some(now);
+        });
+    }
+
+    // 收到服务器的Ping等其他数据，同样说明连接是活的
+    pub fn record_activity(&self) {
+        self.update_stats(|stats| stats.last_activity = Some(Instant::now()));
+    }
+
+    // 超过deadline没有收到任何数据时返回true并记录一次超时
+    pub fn check_liveness(&self, deadline: Duration) -> bool {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.generation != self.generation {
+            return false;
+        }
+        let idle = shared.stats.last_activity.map(|at| at.elapsed()).unwrap_or_default();
+        if idle <= deadline {
+            return false;
+        }
+        shared.stats.liveness_timeouts += 1;
+        warn!("💔 [WS] {} 秒没有收到任何数据 (超时 {} 秒)，连接可能已失效", idle.as_secs(), deadline.as_secs());
+        true
+    }
+}
+
+pub(crate) struct ConnectionManager {
+    shared: Arc<Mutex<SharedState>>,
+    shutdown: Mutex<Option<watch::Sender<bool>>>,
+}
+
+impl Default for ConnectionManager {
+    fn default() -> Self {
+        ConnectionManager {
+            shared: Arc::new(Mutex::new(SharedState {
+                generation: 0,
+                state: ConnectionState::Stopped { reason: None },
+                stats: StatsInner::default(),
+            })),
+            shutdown: Mutex::new(None),
+        }
+    }
+}
+
+impl ConnectionManager {
+    pub fn state(&self) -> ConnectionState {
+        self.shared.lock().unwrap().state.clone()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        matches!(self.state(), ConnectionState::Connected { .. })
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        let shared = self.shared.lock().unwrap();
+        let stats = &shared.stats;
+        ConnectionStats {
+            state: shared.state.clone(),
+            connected_since: stats.connected_since.clone(),
+            uptime_secs: stats.connected_at.map(|at| at.elapsed().as_secs()).unwrap_or(0),
+            last_message_at: stats.last_message_at.clone(),
+            last_pong_at: stats.last_pong_at.clone(),
+            idle_secs: stats.last_activity.map(|at| at.elapsed().as_secs()),
+            reconnect_count: stats.connections.saturating_sub(1),
+            liveness_timeouts: stats.liveness_timeouts,
+            messages_received: stats.messages_received,
+        }
+    }
+
+    // 启动连接任务，已有任务时先停止
+    pub fn start(&self, ws_url: String, ctx: Ctx) {
+        let mut shutdown = self.shutdown.lock().unwrap();
+        if let Some(previous) = shutdown.take() {
+            info!("🔌 [WS] 停止之前的连接任务");
+            let _ = previous.send(true);
+        }
+
+        let generation = {
+            let mut shared = self.shared.lock().unwrap();
+            shared.generation += 1;
+            shared.state = ConnectionState::Connecting { attempt: 1 };
+            shared.stats.connected_at = None;
+            shared.stats.connected_since = None;
+            shared.generation
+        };
+
+        let (tx, rx) = watch::channel(false);
+        *shutdown = Some(tx);
+
+        let reporter = StatusReporter {
+            generation,
+            shared: self.shared.clone(),
+            ctx: ctx.clone(),
+        };
+        info!("🔌 [WS] 启动连接任务 #{}", generation);
+        tauri::async_runtime::spawn(crate::run_websocket_connection(ws_url, reporter, rx, ctx));
+    }
+
+    // 停止连接任务，返回之前是否有任务在运行
+    pub fn stop(&self, ctx: &dyn AppContext, reason: &str) -> bool {
+        let previous = self.shutdown.lock().unwrap().take();
+        let was_running = previous.is_some();
+        if let Some(previous) = previous {
+            let _ = previous.send(true);
+        }
+
+        let state = ConnectionState::Stopped { reason: Some(reason.to_string()) };
+        {
+            let mut shared = self.shared.lock().unwrap();
+            shared.generation += 1;
+            shared.state = state.clone();
+            shared.stats.connected_at = None;
+            shared.stats.connected_since = None;
+        }
+        ctx.emit("websocket-state", &state);
+        ctx.emit("websocket-status", "disconnected");
+
+        info!("🔌 [WS] 连接已停止: {}", reason);
+        was_running
+    }
+}
@@ -0,0 +1,294 @@
+// 诊断包：把排查问题需要的信息打包成一个zip文件交给技术支持
+//
+//   system.json             版本、系统、应用数据目录
+//   config.json             当前配置 (令牌和密码已屏蔽)
+//   log_settings.json       日志设置
+//   connection_stats.json   WebSocket连接统计
+//   print_jobs.json         打印任务队列
+//   printers.json           系统打印机、匹配的配置和编码信息，以及已配置的打印机
+//   printers/<序号>_<名称>.txt  debug_printer 的输出
+//   receipts/*.txt          最近渲染的小票 (去掉控制命令后的文字，顾客信息和备注整段屏蔽)
+//   logs/*.log              最近几天的日志
+//
+// 所有文本在写入前都经过 redact() 脱敏。
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zip::write::FileOptions;
+
+use crate::error::AppError;
+use crate::{logging, redact, AppState};
+
+// 保留最近渲染的小票数量
+const RECENT_RECEIPTS: usize = 10;
+// 打包最近几天的日志
+const LOG_DAYS: u64 = 3;
+// 小票中顾客信息的标签 (format_table_row 放不下时值换到下一行并缩进)
+const CUSTOMER_LABELS: &[&str] = &["Customer:", "Phone:", "Address:", "Email:"];
+
+#[derive(Clone, Debug)]
+struct RenderedReceipt {
+    printer: String,
+    rendered_at: String,
+    success: bool,
+    content: String,
+}
+
+static RECEIPTS: Lazy<Mutex<VecDeque<RenderedReceipt>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+// 记录发送到打印机的小票内容
+pub(crate) fn record_receipt(printer: &str, content: &str, success: bool) {
+    let mut receipts = RECEIPTS.lock().unwrap();
+    if receipts.len() >= RECENT_RECEIPTS {
+        receipts.pop_front();
+    }
+    receipts.push_back(RenderedReceipt {
+        printer: printer.to_string(),
+        rendered_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        success,
+        content: content.to_string(),
+    });
+}
+
+#[derive(Serialize)]
+struct SystemPrinterInfo {
+    name: String,
+    is_default: bool,
+    profile: crate::printer_profiles::ResolvedProfile,
+    encoding: Option<crate::PrinterEncodingInfo>,
+}
+
+fn default_bundle_path() -> PathBuf {
+    crate::get_app_data_dir()
+        .join("diagnostics")
+        .join(format!("diagnostics_{}.zip", chrono::Local::now().format("%Y%m%d_%H%M%S")))
+}
+
+// 文件名只保留安全字符
+fn safe_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+// 屏蔽小票中的顾客信息：标签后的值和换行后的续行都替换为 ***，备注整段屏蔽
+// (redact() 只能识别标签所在的那一行)
+fn mask_customer_lines(text: &str) -> String {
+    let is_rule = |line: &str| {
+        let line = line.trim();
+        !line.is_empty() && line.chars().all(|c| c == '-' || c == '=')
+    };
+    // 下一个表格行 (如取消小票的 "Reason:")
+    let is_row = |line: &str| {
+        line.starts_with(|c: char| c.is_ascii_uppercase())
+            && line
+                .split_once(':')
+                .map(|(label, _)| label.len() <= 20 && label.chars().all(|c| c.is_ascii_alphabetic() || c == ' '))
+                .unwrap_or(false)
+    };
+
+    let mut lines = Vec::new();
+    let mut in_customer = false;
+    let mut in_notes = false;
+    for line in text.lines() {
+        if in_notes {
+            in_notes = !line.trim().is_empty();
+            lines.push(if in_notes { redact::MASK.to_string() } else { line.to_string() });
+            continue;
+        }
+        if let Some(label) = CUSTOMER_LABELS.iter().find(|label| line.starts_with(*label)) {
+            in_customer = true;
+            let has_value = !line[label.len()..].trim().is_empty();
+            lines.push(if has_value { format!("{} {}", label, redact::MASK) } else { label.to_string() });
+            continue;
+        }
+        if in_customer && !line.trim().is_empty() && !is_rule(line) && !is_row(line) {
+            lines.push(format!("  {}", redact::MASK));
+            continue;
+        }
+        in_customer = false;
+        in_notes = line.trim() == "Notes:";
+        lines.push(line.to_string());
+    }
+    lines.join("\n")
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, AppError> {
+    Ok(redact::redact(&serde_json::to_string_pretty(value)?))
+}
+
+// 配置中的令牌不打包
+fn masked_config(state: &AppState) -> Result<serde_json::Value, AppError> {
+    let mut config = serde_json::to_value(&*state.config.lock().unwrap())?;
+    if let Some(value) = config.get_mut("http_api").and_then(|s| s.get_mut("token")) {
+        if value.as_str().map(|v| !v.is_empty()).unwrap_or(false) {
+            *value = serde_json::json!("***");
+        }
+    }
+    Ok(config)
+}
+
+// 最近几天的日志 (压缩的旧日志解压后打包)
+fn recent_logs() -> Vec<(String, String)> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    logging::list_log_files()
+        .into_iter()
+        .filter(|f| now.saturating_sub(f.modified) <= LOG_DAYS * 24 * 3600)
+        .filter_map(|f| match crate::log_query::read_log_file(&f) {
+            Ok(content) => Some((f.name.trim_end_matches(".gz").to_string(), redact::redact(&content))),
+            Err(e) => {
+                warn!("⚠️ [DIAGNOSTICS] 读取日志 {} 失败: {}", f.name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+// 生成诊断包，返回文件路径
+pub(crate) async fn build_bundle(state: &AppState, path: Option<PathBuf>) -> Result<PathBuf, AppError> {
+    let path = path.unwrap_or_else(default_bundle_path);
+    let mut entries: Vec<(String, String)> = Vec::new();
+
+    entries.push(("system.json".to_string(), to_json(&serde_json::json!({
+        "app_version": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "generated_at": chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        "app_data_dir": crate::get_app_data_dir().to_string_lossy(),
+        "logged_in": state.session.lock().unwrap().is_some(),
+        "http_api_running": state.http_api.is_running(),
+    }))?));
+    entries.push(("config.json".to_string(), to_json(&masked_config(state)?)?));
+    entries.push(("log_settings.json".to_string(), to_json(&logging::load_log_settings())?));
+    entries.push(("connection_stats.json".to_string(), to_json(&state.connection.stats())?));
+    entries.push(("print_jobs.json".to_string(), to_json(&state.print_jobs.list())?));
+
+    // 打印机
+    let configured = state.printers.lock().unwrap().clone();
+    let system = crate::get_system_printers().unwrap_or_else(|e| {
+        warn!("⚠️ [DIAGNOSTICS] 获取系统打印机失败: {}", e);
+        Vec::new()
+    });
+    let mut printer_infos = Vec::new();
+    for (index, printer) in system.iter().enumerate() {
+        printer_infos.push(SystemPrinterInfo {
+            name: printer.name.clone(),
+            is_default: printer.is_default,
+            profile: crate::printer_profiles::resolve(&printer.name),
+            encoding: crate::get_printer_encoding_info(printer.name.clone()).await.ok(),
+        });
+
+        let debug_output = match crate::debug_printer(printer.name.clone()).await {
+            Ok(output) => output,
+            Err(e) => format!("debug_printer 失败: {}", e),
+        };
+        // 不同的打印机名称可能得到相同的文件名 (如 "XP 80" 和 "XP_80")，加上序号
+        entries.push((
+            format!("printers/{:02}_{}.txt", index + 1, safe_file_name(&printer.name)),
+            redact::redact(&debug_output),
+        ));
+    }
+    entries.push(("printers.json".to_string(), to_json(&serde_json::json!({
+        "system": printer_infos,
+        "configured": configured,
+    }))?));
+
+    // 最近渲染的小票
+    let receipts: Vec<RenderedReceipt> = RECEIPTS.lock().unwrap().iter().cloned().collect();
+    for (index, receipt) in receipts.iter().enumerate() {
+        let text = format!(
+            "printer: {}\nrendered_at: {}\nsuccess: {}\n\n{}",
+            receipt.printer,
+            receipt.rendered_at,
+            receipt.success,
+            mask_customer_lines(&crate::headless::strip_escpos(&receipt.content))
+        );
+        entries.push((
+            format!("receipts/{:02}_{}.txt", index + 1, safe_file_name(&receipt.printer)),
+            redact::redact(&text),
+        ));
+    }
+
+    for (name, content) in recent_logs() {
+        entries.push((format!("logs/{}", name), content));
+    }
+
+    write_zip(&path, &entries)?;
+    info!("🧰 [DIAGNOSTICS] 诊断包已生成: {} ({} 个文件)", path.display(), entries.len());
+    Ok(path)
+}
+
+fn write_zip(path: &Path, entries: &[(String, String)]) -> Result<(), AppError> {
+    let zip_error = |e: zip::result::ZipError| AppError::Io(format!("生成诊断包失败: {}", e));
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut zip = zip::ZipWriter::new(File::create(path)?);
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    for (name, content) in entries {
+        zip.start_file(name.as_str(), options).map_err(zip_error)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::receipt_layout::ReceiptLayout;
+    use crate::test_support;
+
+    fn rendered(content: &str) -> String {
+        mask_customer_lines(&crate::headless::strip_escpos(content))
+    }
+
+    #[test]
+    fn wrapped_customer_lines_are_masked() {
+        let mut order = test_support::order("D1");
+        order.delivery_style = 1;
+        order.recipient_address = "1234 Very Long Boulevard Name\nApt 2B, Springfield Gardens".to_string();
+        let mut printer = test_support::printer(test_support::TEST_PRINTER);
+        printer.width = 58;
+
+        let text = rendered(&crate::generate_print_content(&order, &ReceiptLayout::for_printer(&printer)).unwrap());
+
+        for secret in ["Zhang", "Boulevard", "Springfield", "123-4567", "john.smith", "doorbell"] {
+            assert!(!text.contains(secret), "{} 没有屏蔽:\n{}", secret, text);
+        }
+        assert!(text.contains("Address:"));
+        assert!(text.contains("Payment:"));
+        assert!(text.contains("Mapo Tofu"));
+        assert!(text.contains("Thank you for your order!"));
+    }
+
+    #[test]
+    fn rows_after_customer_are_kept() {
+        let order = test_support::order("D2");
+        let printer = test_support::printer(test_support::TEST_PRINTER);
+        let content = crate::order_changes::generate_void_content(&order, Some("Out of stock"), &ReceiptLayout::for_printer(&printer));
+
+        let text = rendered(&content);
+        assert!(!text.contains("Zhang"));
+        assert!(text.contains("Out of stock"));
+        assert!(text.contains("DO NOT PREPARE"));
+    }
+
+    #[test]
+    fn printer_file_names_are_sanitized() {
+        assert_eq!(safe_file_name("XP 80/C"), "XP_80_C");
+        assert_eq!(safe_file_name("小票-1"), "小票-1");
+    }
+}
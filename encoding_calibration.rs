@@ -0,0 +1,424 @@
+// 编码兼容性：离线分析和打印校准
+//
+// 离线分析：逐个字符检查订单中的文字能否用候选编码表示，不需要打印机。
+//
+// 校准：在一张小票上按编号打印各候选编码的样字 (每个编码先复位打印机并发送该编码的选择命令)，
+// 操作员对照屏幕上的参考文字确认哪些编号打印正确。确认的编码保存到该打印机的手动配置
+// (printer_overrides.json)，之后打印该打印机的小票时按推荐编码转码后发送。
+// 没有校准过的打印机仍然直接发送UTF-8文本。
+
+use encoding_rs::Encoding;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use crate::chinese_script;
+use crate::error::AppError;
+use crate::printer_profiles::{self, PrinterProfile, ResolvedProfile};
+use crate::OrderData;
+
+// 没有指定时参与测试的编码 (打印机配置中的编码排在前面)
+const CANDIDATE_ENCODINGS: &[&str] = &["UTF8", "GBK", "GB18030", "BIG5"];
+
+// 校准样字：简体、繁体和常用符号，每个编码只打印它能表示的字符
+const SAMPLE_GLYPHS: &str = "中文打印测试 宫保鸡丁 繁體餐廳點心 ￥€";
+
+// 每台打印机最近一次打印的校准页，确认时按编号对应编码
+static SHEETS: Lazy<Mutex<HashMap<String, CalibrationSheet>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct CalibrationSample {
+    pub number: u32,
+    pub encoding: String,
+    pub text: String, // 参考文字，前端显示给操作员对照
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct CalibrationSheet {
+    pub printer_name: String,
+    pub printed_at: String,
+    pub samples: Vec<CalibrationSample>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct UnsupportedChar {
+    pub ch: String,
+    pub code_point: String, // U+XXXX
+    pub count: usize,
+    pub fields: Vec<String>, // 出现在哪些字段
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct EncodingCoverage {
+    pub encoding: String,
+    pub known: bool, // 是否是可以转码的编码，未知编码不做分析
+    pub total_chars: usize,
+    pub coverage: f64, // 可表示的字符比例
+    pub unsupported: Vec<UnsupportedChar>,
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim().to_uppercase().replace(['-', '_', ' '], "")
+}
+
+// 编码名称 (打印机配置中的写法) 对应的编码
+pub(crate) fn lookup(name: &str) -> Option<&'static Encoding> {
+    match normalize_name(name).as_str() {
+        "UTF8" => Some(encoding_rs::UTF_8),
+        "GBK" | "GB2312" | "CP936" => Some(encoding_rs::GBK),
+        "GB18030" => Some(encoding_rs::GB18030),
+        "BIG5" => Some(encoding_rs::BIG5),
+        "SHIFTJIS" | "SJIS" => Some(encoding_rs::SHIFT_JIS),
+        "EUCKR" => Some(encoding_rs::EUC_KR),
+        "WPC1252" | "CP1252" | "WINDOWS1252" => Some(encoding_rs::WINDOWS_1252),
+        _ => Encoding::for_label(name.trim().as_bytes()),
+    }
+}
+
+// GB2312 按GBK转码，但只有GB2312字符集中的字符能打印出来
+fn is_gb2312(name: &str) -> bool {
+    normalize_name(name) == "GB2312"
+}
+
+fn can_encode(encoding: &'static Encoding, gb2312: bool, ch: char) -> bool {
+    if gb2312 {
+        return chinese_script::in_gb2312_charset(ch);
+    }
+    if encoding == encoding_rs::UTF_8 {
+        return true;
+    }
+    let mut buf = [0u8; 4];
+    let (_, _, had_errors) = encoding.encode(ch.encode_utf8(&mut buf));
+    !had_errors
+}
+
+// 转码，不能表示的字符替换为 '?' (而不是 encoding_rs 默认的 &#NNNN;)
+fn encode_lossy(encoding: &'static Encoding, gb2312: bool, text: &str) -> Vec<u8> {
+    if encoding == encoding_rs::UTF_8 {
+        return text.as_bytes().to_vec();
+    }
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut buf = [0u8; 4];
+    for ch in text.chars() {
+        let (encoded, _, had_errors) = encoding.encode(ch.encode_utf8(&mut buf));
+        if had_errors || (gb2312 && !chinese_script::in_gb2312_charset(ch)) {
+            bytes.push(b'?');
+        } else {
+            bytes.extend_from_slice(&encoded);
+        }
+    }
+    bytes
+}
+
+// 候选编码：指定的编码，或者打印机配置中的编码加上常用编码，去掉重复和无法转码的编码
+pub(crate) fn candidate_encodings(profile: &PrinterProfile, requested: Option<Vec<String>>) -> Vec<String> {
+    let names: Vec<String> = match requested {
+        Some(list) if !list.is_empty() => list,
+        _ => profile
+            .encodings()
+            .into_iter()
+            .chain(CANDIDATE_ENCODINGS.iter().map(|e| e.to_string()))
+            .collect(),
+    };
+
+    let mut result: Vec<String> = Vec::new();
+    for name in names {
+        if result.iter().any(|e| e.eq_ignore_ascii_case(&name)) {
+            continue;
+        }
+        if lookup(&name).is_none() {
+            warn!("⚠️ [ENCODING] 无法转码的编码，跳过: {}", name);
+            continue;
+        }
+        result.push(name);
+    }
+    result
+}
+
+// ============= 离线分析 =============
+
+// 订单中会打印出来的文字 (字段名, 内容)
+fn order_texts(order: &OrderData) -> Vec<(String, String)> {
+    let mut texts = vec![
+        ("rd_name".to_string(), order.rd_name.clone()),
+        ("recipient_name".to_string(), order.recipient_name.clone()),
+        ("recipient_address".to_string(), order.recipient_address.clone()),
+        ("order_notes".to_string(), order.order_notes.clone()),
+    ];
+    for (index, item) in order.dishes_array.iter().enumerate() {
+        texts.push((format!("dishes_array[{}].dishes_name", index), item.dishes_name.clone()));
+        texts.push((format!("dishes_array[{}].dishes_describe", index), item.dishes_describe.clone()));
+        texts.push((format!("dishes_array[{}].remark", index), item.remark.clone()));
+    }
+    texts.retain(|(_, text)| !text.is_empty());
+    texts
+}
+
+pub(crate) fn analyze_texts(texts: &[(String, String)], encodings: &[String]) -> Vec<EncodingCoverage> {
+    let total_chars = texts
+        .iter()
+        .map(|(_, text)| text.chars().filter(|c| !c.is_whitespace()).count())
+        .sum::<usize>();
+
+    encodings
+        .iter()
+        .map(|name| {
+            let Some(encoding) = lookup(name) else {
+                return EncodingCoverage {
+                    encoding: name.clone(),
+                    known: false,
+                    total_chars,
+                    coverage: 0.0,
+                    unsupported: Vec::new(),
+                };
+            };
+            let gb2312 = is_gb2312(name);
+
+            let mut unsupported: BTreeMap<char, UnsupportedChar> = BTreeMap::new();
+            let mut unsupported_count = 0;
+            for (field, text) in texts {
+                for ch in text.chars().filter(|c| !c.is_whitespace()) {
+                    if can_encode(encoding, gb2312, ch) {
+                        continue;
+                    }
+                    unsupported_count += 1;
+                    let entry = unsupported.entry(ch).or_insert_with(|| UnsupportedChar {
+                        ch: ch.to_string(),
+                        code_point: format!("U+{:04X}", ch as u32),
+                        count: 0,
+                        fields: Vec::new(),
+                    });
+                    entry.count += 1;
+                    if !entry.fields.contains(field) {
+                        entry.fields.push(field.clone());
+                    }
+                }
+            }
+
+            EncodingCoverage {
+                encoding: name.clone(),
+                known: true,
+                total_chars,
+                coverage: if total_chars > 0 {
+                    (total_chars - unsupported_count) as f64 / total_chars as f64
+                } else {
+                    1.0
+                },
+                unsupported: unsupported.into_values().collect(),
+            }
+        })
+        .collect()
+}
+
+// 分析订单在各候选编码下无法表示的字符
+pub(crate) fn analyze_order(order: &OrderData, printer_name: Option<&str>, encodings: Option<Vec<String>>) -> Vec<EncodingCoverage> {
+    let profile = printer_name
+        .map(|name| printer_profiles::resolve(name).profile)
+        .unwrap_or_default();
+    analyze_texts(&order_texts(order), &candidate_encodings(&profile, encodings))
+}
+
+// ============= 转码发送 =============
+
+// 按编码生成发送给打印机的字节：开头和每个 ESC @ 之后插入该编码的选择命令
+// (ESC @ 会把代码页复位，多联打印时每一联都以 ESC @ 开头)
+pub(crate) fn encode_receipt(profile: &PrinterProfile, encoding_name: &str, content: &str) -> Result<Vec<u8>, AppError> {
+    let encoding = lookup(encoding_name).ok_or_else(|| AppError::Encoding(format!("不支持的编码: {}", encoding_name)))?;
+    let gb2312 = is_gb2312(encoding_name);
+    let select = profile.select_sequence(encoding_name);
+
+    let mut bytes = Vec::with_capacity(content.len() + select.len());
+    for (index, part) in content.split("\x1B@").enumerate() {
+        if index > 0 {
+            bytes.extend_from_slice(b"\x1B@");
+        } else if content.starts_with("\x1B@") {
+            // 以 ESC @ 开头：选择命令放在 ESC @ 之后
+            continue;
+        }
+        bytes.extend_from_slice(select.as_bytes());
+        bytes.extend(encode_lossy(encoding, gb2312, part));
+    }
+    Ok(bytes)
+}
+
+// 校准确认过的推荐编码，没有校准过或推荐编码没有通过确认时为 None
+pub(crate) fn calibrated_encoding(resolved: &ResolvedProfile) -> Option<&str> {
+    let encoding = &resolved.profile.default_encoding;
+    let verified = resolved.verified_encodings.as_ref()?;
+    verified
+        .iter()
+        .any(|e| e.eq_ignore_ascii_case(encoding))
+        .then_some(encoding.as_str())
+}
+
+// 校准过的打印机按推荐编码转码；没有校准过时返回 None，直接发送UTF-8文本
+pub(crate) fn encode_for_printer(printer_name: &str, content: &str) -> Option<Vec<u8>> {
+    let resolved = printer_profiles::resolve(printer_name);
+    let encoding = calibrated_encoding(&resolved)?;
+    match encode_receipt(&resolved.profile, encoding, content) {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            warn!("⚠️ [ENCODING] 打印机 {} 的编码 {} 无法转码，按UTF-8发送: {}", printer_name, encoding, e);
+            None
+        }
+    }
+}
+
+// ============= 校准 =============
+
+fn sample_text(encoding: &'static Encoding, gb2312: bool) -> String {
+    SAMPLE_GLYPHS
+        .chars()
+        .filter(|&ch| ch == ' ' || can_encode(encoding, gb2312, ch))
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn is_cjk(ch: char) -> bool {
+    ('\u{4E00}'..='\u{9FFF}').contains(&ch)
+}
+
+// 生成校准页，返回校准页信息和要发送的字节
+pub(crate) fn build_sheet(printer_name: &str, encodings: Option<Vec<String>>) -> Result<(CalibrationSheet, Vec<u8>), AppError> {
+    let profile = printer_profiles::resolve(printer_name).profile;
+    let encodings = candidate_encodings(&profile, encodings);
+    if encodings.is_empty() {
+        return Err(AppError::InvalidArgument("没有可以测试的编码".to_string()));
+    }
+
+    let mut samples = Vec::new();
+    let mut bytes: Vec<u8> = Vec::new();
+    bytes.extend_from_slice(b"\x1B@\x1B\x45\x01ENCODING CALIBRATION\x1B\x45\x00\n");
+    bytes.extend_from_slice(b"Mark the numbers that print correctly\n");
+    bytes.extend_from_slice(b"------------------------------\n");
+
+    for (index, name) in encodings.iter().enumerate() {
+        let encoding = lookup(name).expect("候选编码已检查");
+        let number = index as u32 + 1;
+        let gb2312 = is_gb2312(name);
+        let text = sample_text(encoding, gb2312);
+
+        // 每个样字前复位，避免上一个编码的模式影响下一个
+        bytes.extend_from_slice(b"\x1B@");
+        bytes.extend_from_slice(format!("[{}] {}\n", number, name).as_bytes());
+        bytes.extend_from_slice(profile.select_sequence(name).as_bytes());
+        bytes.extend(encode_lossy(encoding, gb2312, &text));
+        bytes.extend_from_slice(b"\n\n");
+
+        samples.push(CalibrationSample {
+            number,
+            encoding: name.clone(),
+            text,
+        });
+    }
+    bytes.extend_from_slice(b"\x1B@\n\n\n\n\x1D\x56\x00");
+
+    let sheet = CalibrationSheet {
+        printer_name: printer_name.to_string(),
+        printed_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        samples,
+    };
+    SHEETS.lock().unwrap().insert(printer_name.to_string(), sheet.clone());
+    Ok((sheet, bytes))
+}
+
+// 记录操作员确认打印正确的编号，保存到打印机配置
+pub(crate) fn confirm(printer_name: &str, correct_numbers: &[u32]) -> Result<ResolvedProfile, AppError> {
+    let sheet = SHEETS
+        .lock()
+        .unwrap()
+        .get(printer_name)
+        .cloned()
+        .ok_or_else(|| AppError::InvalidArgument(format!("打印机 {} 还没有打印校准页", printer_name)))?;
+
+    if let Some(number) = correct_numbers.iter().find(|n| !sheet.samples.iter().any(|s| s.number == **n)) {
+        return Err(AppError::InvalidArgument(format!("校准页上没有编号 {}", number)));
+    }
+
+    let verified: Vec<&CalibrationSample> = sheet
+        .samples
+        .iter()
+        .filter(|s| correct_numbers.contains(&s.number))
+        .collect();
+
+    let current = printer_profiles::resolve(printer_name);
+    let mut manual = printer_profiles::override_for(printer_name).unwrap_or_default();
+
+    // 当前推荐编码通过确认时保持不变，否则使用第一个确认的编码
+    let recommended = verified
+        .iter()
+        .find(|s| s.encoding.eq_ignore_ascii_case(&current.profile.default_encoding))
+        .or_else(|| verified.first())
+        .map(|s| s.encoding.clone());
+
+    manual.verified_encodings = Some(verified.iter().map(|s| s.encoding.clone()).collect());
+    manual.calibrated_at = Some(chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+    manual.cjk = Some(verified.iter().any(|s| s.text.chars().any(is_cjk)));
+    if let Some(encoding) = &recommended {
+        manual.default_encoding = Some(encoding.clone());
+    }
+
+    let resolved = printer_profiles::set_override(printer_name, Some(manual))?;
+    SHEETS.lock().unwrap().remove(printer_name);
+
+    match &recommended {
+        Some(encoding) => info!("✅ [ENCODING] 打印机 {} 校准完成，确认可用 {} 个编码，推荐 {}", printer_name, verified.len(), encoding),
+        None => warn!("⚠️ [ENCODING] 打印机 {} 校准完成，没有编码打印正确", printer_name),
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gbk_profile() -> PrinterProfile {
+        PrinterProfile {
+            code_pages: vec![printer_profiles::CodePage {
+                encoding: "GBK".to_string(),
+                select_command: "1C 26".to_string(),
+            }],
+            default_encoding: "GBK".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn select_command_follows_every_reset() {
+        let job = "\x1B@第一联\x1D\x56\x01\x1B@第二联";
+        let bytes = encode_receipt(&gbk_profile(), "GBK", job).unwrap();
+
+        let mut expected = b"\x1B@\x1C&".to_vec();
+        expected.extend(encode_lossy(lookup("GBK").unwrap(), false, "第一联\x1D\x56\x01"));
+        expected.extend_from_slice(b"\x1B@\x1C&");
+        expected.extend(encode_lossy(lookup("GBK").unwrap(), false, "第二联"));
+        assert_eq!(bytes, expected);
+    }
+
+    #[test]
+    fn select_command_is_prepended_without_reset() {
+        let bytes = encode_receipt(&gbk_profile(), "GBK", "ABC").unwrap();
+        assert_eq!(bytes, b"\x1C&ABC");
+
+        let bytes = encode_receipt(&gbk_profile(), "GBK", "").unwrap();
+        assert_eq!(bytes, b"\x1C&");
+    }
+
+    #[test]
+    fn gb2312_coverage_excludes_gbk_only_characters() {
+        // 镕、喆 只在GBK中，€ 在GBK中是单字节 0x80
+        let texts = vec![("dishes_name".to_string(), "那餐 ￥12 镕喆€".to_string())];
+        let coverage = analyze_texts(&texts, &["GBK".to_string(), "GB2312".to_string()]);
+
+        assert!(coverage[0].unsupported.is_empty());
+        let missing: Vec<&str> = coverage[1].unsupported.iter().map(|c| c.ch.as_str()).collect();
+        assert_eq!(missing, vec!["€", "喆", "镕"]);
+
+        assert!(!sample_text(encoding_rs::GBK, true).contains('€'));
+        assert_eq!(encode_lossy(encoding_rs::GBK, true, "镕A"), b"?A");
+    }
+}
@@ -0,0 +1,151 @@
+// 命令和事件使用的统一错误类型
+//
+// 序列化为 {code, message, details}，前端根据稳定的 code 做本地化和分支处理，
+// 不再需要匹配错误字符串。
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+use crate::api_client::ApiError;
+
+#[derive(Clone, Debug)]
+pub(crate) enum AppError {
+    // 打印机
+    PrinterNotFound(String),
+    NoPrintersDetected,
+    NoEnabledPrinters,
+    PrinterQuery(String),
+    PrintFailed { printer: String, reason: String },
+    // 网络/API
+    Network(String),
+    Timeout,
+    Api { status_code: i64, message: String },
+    // 登录
+    AuthExpired,
+    AuthFailed(String),
+    NotLoggedIn,
+    // 数据
+    Parse(String),
+    Encoding(String),
+    // 配置和本地文件
+    Config(String),
+    InvalidArgument(String),
+    Io(String),
+    Internal(String),
+}
+
+impl AppError {
+    // 稳定的错误代码，前端据此本地化，修改前需要同步前端
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::PrinterNotFound(_) => "PRINTER_NOT_FOUND",
+            AppError::NoPrintersDetected => "PRINTER_NONE_DETECTED",
+            AppError::NoEnabledPrinters => "PRINTER_NONE_ENABLED",
+            AppError::PrinterQuery(_) => "PRINTER_QUERY_FAILED",
+            AppError::PrintFailed { .. } => "PRINT_FAILED",
+            AppError::Network(_) => "NETWORK_ERROR",
+            AppError::Timeout => "NETWORK_TIMEOUT",
+            AppError::Api { .. } => "API_ERROR",
+            AppError::AuthExpired => "AUTH_EXPIRED",
+            AppError::AuthFailed(_) => "AUTH_FAILED",
+            AppError::NotLoggedIn => "AUTH_NOT_LOGGED_IN",
+            AppError::Parse(_) => "PARSE_ERROR",
+            AppError::Encoding(_) => "ENCODING_ERROR",
+            AppError::Config(_) => "CONFIG_ERROR",
+            AppError::InvalidArgument(_) => "INVALID_ARGUMENT",
+            AppError::Io(_) => "IO_ERROR",
+            AppError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    // 附加的结构化信息
+    pub fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            AppError::PrinterNotFound(printer) => Some(serde_json::json!({ "printer": printer })),
+            AppError::PrintFailed { printer, reason } => Some(serde_json::json!({
+                "printer": printer,
+                "reason": reason
+            })),
+            AppError::Api { status_code, .. } => Some(serde_json::json!({ "status_code": status_code })),
+            _ => None,
+        }
+    }
+
+    pub fn print_failed(printer: &str, reason: impl Into<String>) -> Self {
+        AppError::PrintFailed {
+            printer: printer.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::PrinterNotFound(printer) => write!(f, "未找到打印机: {}", printer),
+            AppError::NoPrintersDetected => write!(f, "未检测到任何打印机。请确保打印机已正确安装并连接。"),
+            AppError::NoEnabledPrinters => write!(f, "没有启用的打印机"),
+            AppError::PrinterQuery(e) => write!(f, "查询系统打印机失败: {}", e),
+            AppError::PrintFailed { printer, reason } => write!(f, "打印到 {} 失败: {}", printer, reason),
+            AppError::Network(e) => write!(f, "网络请求失败: {}", e),
+            AppError::Timeout => write!(f, "请求超时"),
+            AppError::Api { status_code, message } => write!(f, "服务器返回错误 ({}): {}", status_code, message),
+            AppError::AuthExpired => write!(f, "登录已过期，请重新登录"),
+            AppError::AuthFailed(message) => write!(f, "登录失败: {}", message),
+            AppError::NotLoggedIn => write!(f, "尚未登录"),
+            AppError::Parse(e) => write!(f, "数据解析失败: {}", e),
+            AppError::Encoding(e) => write!(f, "编码处理失败: {}", e),
+            AppError::Config(e) => write!(f, "配置错误: {}", e),
+            AppError::InvalidArgument(e) => write!(f, "参数错误: {}", e),
+            AppError::Io(e) => write!(f, "文件操作失败: {}", e),
+            AppError::Internal(e) => write!(f, "内部错误: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("details", &self.details())?;
+        state.end()
+    }
+}
+
+impl From<ApiError> for AppError {
+    fn from(e: ApiError) -> Self {
+        match e {
+            ApiError::Timeout => AppError::Timeout,
+            ApiError::Network(e) => AppError::Network(e),
+            ApiError::Unauthorized(_) => AppError::AuthExpired,
+            ApiError::Http(status) => AppError::Api {
+                status_code: status as i64,
+                message: format!("HTTP {}", status),
+            },
+            ApiError::Api { status_code, message } => AppError::Api { status_code, message },
+            ApiError::Parse(e) => AppError::Parse(e),
+            ApiError::EmptyData => AppError::Parse("服务器返回数据为空".to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::Io(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::Parse(e.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for AppError {
+    fn from(e: rusqlite::Error) -> Self {
+        AppError::Io(format!("订单历史数据库错误: {}", e))
+    }
+}
@@ -0,0 +1,53 @@
+// 事件输出
+//
+// 连接、打印、补打等核心逻辑通过 AppContext 访问应用状态和发送事件，不直接依赖 tauri::Window。
+// 图形界面模式下由 Window 实现 (事件发送到前端)，无界面模式下由 headless::HeadlessContext 实现
+// (事件写入日志和状态文件)。
+
+use log::warn;
+use serde::Serialize;
+use std::sync::Arc;
+use tauri::{Manager, Window};
+
+use crate::AppState;
+
+// 接收事件
+pub(crate) trait EventSink: Send + Sync {
+    fn emit_value(&self, event: &str, payload: serde_json::Value);
+}
+
+// 核心逻辑运行所需的上下文：应用状态 + 事件输出
+pub(crate) trait AppContext: EventSink {
+    fn app_state(&self) -> &AppState;
+}
+
+// 可以在后台任务之间共享的上下文
+pub(crate) type Ctx = Arc<dyn AppContext>;
+
+impl dyn AppContext {
+    pub fn emit<S: Serialize>(&self, event: &str, payload: S) {
+        match serde_json::to_value(payload) {
+            Ok(value) => self.emit_value(event, value),
+            Err(e) => warn!("⚠️ [EVENT] 事件 {} 序列化失败: {}", event, e),
+        }
+    }
+}
+
+impl EventSink for Window {
+    fn emit_value(&self, event: &str, payload: serde_json::Value) {
+        if let Err(e) = Window::emit(self, event, payload) {
+            warn!("⚠️ [EVENT] 发送事件 {} 到前端失败: {}", event, e);
+        }
+    }
+}
+
+impl AppContext for Window {
+    fn app_state(&self) -> &AppState {
+        Manager::state::<AppState>(self).inner()
+    }
+}
+
+// 图形界面模式：命令和事件共用主窗口
+pub(crate) fn window_context(window: &Window) -> Ctx {
+    Arc::new(window.clone())
+}
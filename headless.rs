@@ -0,0 +1,550 @@
+// 无界面模式 (后台服务和命令行)
+//
+//   order-print-client login <账号>               登录并加密保存会话 (从标准输入读取密码的MD5值)
+//   order-print-client daemon                    使用保存的会话保持WebSocket连接并自动打印
+//   order-print-client printers                  列出系统打印机
+//   order-print-client test-print [打印机名称]    打印测试页
+//   order-print-client preview <order.json> [--width 58|76|80|112] [--font a|b] [--columns N] [--font-size 0|1|2]
+//   order-print-client print <order.json> [--force]
+//
+// 没有窗口时事件写入日志，后台服务的运行状态定期写入 daemon_status.json。
+// 打印机在 config.json 的 headless 段落中配置；登录会话和图形界面共用加密保存的 session.dat。
+
+use log::{info, debug, warn, error};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+use crate::api_client;
+use crate::connection::ConnectionStats;
+use crate::error::AppError;
+use crate::events::{AppContext, Ctx, EventSink};
+use crate::print_jobs::{JobPayload, JobStatus};
+use crate::receipt_layout::{PrinterFont, ReceiptLayout};
+use crate::{AppState, OrderData, PrinterConfig};
+
+// 状态文件刷新间隔
+const STATUS_INTERVAL_SECS: u64 = 30;
+
+pub(crate) enum CliCommand {
+    Login { username: String },
+    Daemon,
+    Printers,
+    TestPrint { printer: Option<String> },
+    Preview { path: PathBuf, width: i32, font_size: i32, font: PrinterFont, columns: Option<usize> },
+    Print { path: PathBuf, force: bool },
+    Help,
+    Invalid(String),
+}
+
+// 解析命令行参数；不是命令行模式时返回 None (启动图形界面)
+pub(crate) fn parse_args(args: &[String]) -> Option<CliCommand> {
+    let command = args.get(1)?;
+    let rest = &args[2..];
+
+    let command = match command.as_str() {
+        "login" => match rest.first() {
+            None => CliCommand::Invalid("login 需要账号".to_string()),
+            Some(username) => CliCommand::Login { username: username.clone() },
+        },
+        "daemon" => CliCommand::Daemon,
+        "printers" => CliCommand::Printers,
+        "test-print" => CliCommand::TestPrint { printer: rest.first().cloned() },
+        "preview" => match rest.first() {
+            None => CliCommand::Invalid("preview 需要订单JSON文件路径".to_string()),
+            Some(path) => {
+                let width = match flag_value(rest, "--width").map(|v| v.parse::<i32>()) {
+                    None => 80,
+                    Some(Ok(width)) if (40..=120).contains(&width) => width,
+                    Some(_) => return Some(CliCommand::Invalid("--width 应为 40-120 之间的纸宽 (mm)".to_string())),
+                };
+                let font_size = match flag_value(rest, "--font-size").map(|v| v.parse::<i32>()) {
+                    None => 0,
+                    Some(Ok(size)) if (0..=2).contains(&size) => size,
+                    Some(_) => return Some(CliCommand::Invalid("--font-size 只能是 0、1 或 2".to_string())),
+                };
+                let font = match flag_value(rest, "--font").map(|v| v.to_lowercase()) {
+                    None => PrinterFont::A,
+                    Some(font) if font == "a" => PrinterFont::A,
+                    Some(font) if font == "b" => PrinterFont::B,
+                    Some(_) => return Some(CliCommand::Invalid("--font 只能是 a 或 b".to_string())),
+                };
+                let columns = match flag_value(rest, "--columns").map(|v| v.parse::<usize>()) {
+                    None => None,
+                    Some(Ok(columns)) if (16..=120).contains(&columns) => Some(columns),
+                    Some(_) => return Some(CliCommand::Invalid("--columns 应为 16-120".to_string())),
+                };
+                CliCommand::Preview { path: PathBuf::from(path), width, font_size, font, columns }
+            }
+        },
+        "print" => match rest.first() {
+            None => CliCommand::Invalid("print 需要订单JSON文件路径".to_string()),
+            Some(path) => CliCommand::Print {
+                path: PathBuf::from(path),
+                force: rest.iter().any(|a| a == "--force"),
+            },
+        },
+        "help" | "--help" | "-h" => CliCommand::Help,
+        _ => return None,
+    };
+    Some(command)
+}
+
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .map(|v| v.as_str())
+}
+
+fn print_usage() {
+    println!("用法:");
+    println!("  order-print-client login <账号>               登录并加密保存会话 (从标准输入读取密码的MD5值)");
+    println!("  order-print-client daemon                    使用保存的会话保持WebSocket连接并自动打印");
+    println!("  order-print-client printers                  列出系统打印机");
+    println!("  order-print-client test-print [打印机名称]    打印测试页");
+    println!("  order-print-client preview <order.json> [--width 58|76|80|112] [--font a|b] [--columns N] [--font-size 0|1|2]");
+    println!("  order-print-client print <order.json> [--force]");
+}
+
+// Windows发布版没有控制台窗口，命令行模式下连接到启动它的控制台以便输出
+#[cfg(target_os = "windows")]
+pub(crate) fn attach_console() {
+    use winapi::um::wincon::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn attach_console() {}
+
+// 执行命令，返回进程退出码
+pub(crate) fn run(command: CliCommand) -> i32 {
+    // 一次性命令只输出警告和错误，避免日志混入命令输出
+    if !matches!(command, CliCommand::Daemon) {
+        log::set_max_level(log::LevelFilter::Warn);
+    }
+
+    let result = match command {
+        CliCommand::Login { username } => login(&username),
+        CliCommand::Daemon => run_daemon(),
+        CliCommand::Printers => list_printers(),
+        CliCommand::TestPrint { printer } => test_print(printer),
+        CliCommand::Preview { path, width, font_size, font, columns } => preview(&path, width, font_size, font, columns),
+        CliCommand::Print { path, force } => print_file(&path, force),
+        CliCommand::Help => {
+            print_usage();
+            return 0;
+        }
+        CliCommand::Invalid(message) => {
+            eprintln!("❌ {}", message);
+            print_usage();
+            return 2;
+        }
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            1
+        }
+    }
+}
+
+// ============= 无界面上下文 =============
+
+#[derive(Clone, Debug, Default, Serialize)]
+struct DaemonStatus {
+    pid: u32,
+    running: bool,
+    started_at: String,
+    updated_at: String,
+    logged_in: bool,
+    connection: Option<serde_json::Value>,
+    connection_stats: Option<ConnectionStats>,
+    printed_orders: u64,
+    print_errors: u64,
+    last_order_id: Option<String>,
+    last_error: Option<serde_json::Value>,
+    last_event: Option<String>,
+}
+
+pub(crate) struct HeadlessContext {
+    state: AppState,
+    status: Mutex<DaemonStatus>,
+    // 一次性命令不写状态文件
+    status_path: Option<PathBuf>,
+    // 新订单通知 (图形界面模式下由前端获取详情并打印)
+    orders: Option<mpsc::UnboundedSender<String>>,
+}
+
+impl HeadlessContext {
+    fn new(status_path: Option<PathBuf>, orders: Option<mpsc::UnboundedSender<String>>) -> Self {
+        HeadlessContext {
+            state: AppState::default(),
+            status: Mutex::new(DaemonStatus {
+                pid: std::process::id(),
+                running: true,
+                started_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                ..Default::default()
+            }),
+            status_path,
+            orders,
+        }
+    }
+
+    fn record_event(&self, event: &str, payload: &serde_json::Value) {
+        let mut status = self.status.lock().unwrap();
+        status.last_event = Some(event.to_string());
+        match event {
+            "websocket-state" => status.connection = Some(payload.clone()),
+            "print-success" => {
+                status.printed_orders += 1;
+                status.last_order_id = payload["order_id"].as_str().map(str::to_string);
+            }
+            "print-error" | "printer-print-error" => {
+                status.print_errors += 1;
+                status.last_error = Some(payload.clone());
+            }
+            "session-expired" | "http-api-error" | "order-error" => status.last_error = Some(payload.clone()),
+            _ => {}
+        }
+    }
+
+    // 写入状态文件 (先写临时文件再改名，读取方不会读到写了一半的文件)
+    fn write_status(&self) {
+        let Some(path) = &self.status_path else { return };
+
+        let status = {
+            let mut status = self.status.lock().unwrap();
+            status.updated_at = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+            status.logged_in = self.state.session.lock().unwrap().is_some();
+            status.connection_stats = Some(self.state.connection.stats());
+            status.clone()
+        };
+
+        let tmp_path = path.with_extension("json.tmp");
+        let result = serde_json::to_string_pretty(&status)
+            .map_err(|e| e.to_string())
+            .and_then(|content| fs::write(&tmp_path, content).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&tmp_path, path).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("⚠️ [DAEMON] 写入状态文件失败: {}", e);
+        }
+    }
+
+    fn mark_stopped(&self) {
+        self.status.lock().unwrap().running = false;
+        self.write_status();
+    }
+}
+
+impl EventSink for HeadlessContext {
+    fn emit_value(&self, event: &str, payload: serde_json::Value) {
+        match event {
+            "websocket-status" | "printer-print-success" => debug!("📣 [DAEMON] {}: {}", event, payload),
+            "print-error" | "printer-print-error" | "session-expired" | "order-error" | "http-api-error" => {
+                warn!("⚠️ [DAEMON] {}: {}", event, payload)
+            }
+            _ => info!("📣 [DAEMON] {}: {}", event, payload),
+        }
+
+        if event == "new-order-notification" {
+            if let (Some(orders), Some(order_id)) = (&self.orders, payload["order_id"].as_str()) {
+                let _ = orders.send(order_id.to_string());
+            }
+        }
+
+        self.record_event(event, &payload);
+        if event == "websocket-state" || event.starts_with("print") || event.starts_with("session") {
+            self.write_status();
+        }
+    }
+}
+
+impl AppContext for HeadlessContext {
+    fn app_state(&self) -> &AppState {
+        &self.state
+    }
+}
+
+fn status_file_path() -> PathBuf {
+    crate::get_app_data_dir().join("daemon_status.json")
+}
+
+// 使用 config.json 中配置的打印机，没有配置时使用系统默认打印机
+fn configure_printers(state: &AppState) -> Vec<PrinterConfig> {
+    let configured = state.config.lock().unwrap().headless.printers.clone();
+    let printers = if !configured.is_empty() {
+        configured
+    } else {
+        match crate::get_system_printers() {
+            Ok(system) => system
+                .into_iter()
+                .filter(|p| p.is_default)
+                .map(|mut p| {
+                    p.is_enabled = true;
+                    p
+                })
+                .collect(),
+            Err(e) => {
+                error!("❌ [DAEMON] 获取系统打印机失败: {}", e);
+                Vec::new()
+            }
+        }
+    };
+
+    for printer in &printers {
+        info!("🖨️ [DAEMON] 使用打印机: {} ({}mm, 启用: {})", printer.name, printer.width, printer.is_enabled);
+    }
+    *state.printers.lock().unwrap() = printers.clone();
+    printers
+}
+
+fn one_shot_context() -> Ctx {
+    let ctx: Ctx = Arc::new(HeadlessContext::new(None, None));
+    crate::load_persisted_state(ctx.app_state());
+    configure_printers(ctx.app_state());
+    ctx
+}
+
+fn read_order_file(path: &Path) -> Result<OrderData, AppError> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| AppError::Io(format!("读取 {} 失败: {}", path.display(), e)))?;
+    Ok(serde_json::from_str::<OrderData>(&content)?)
+}
+
+// ============= 后台服务 =============
+
+// 登录并加密保存会话 (密码MD5一起加密保存，会话过期时自动重新登录)
+async fn login_and_save(username: &str, password_md5: &str) -> Result<crate::session::StoredSession, AppError> {
+    let data = api_client::shared()?
+        .login(username, password_md5)
+        .await
+        .map_err(|e| if e.is_rejection() { AppError::AuthFailed(e.to_string()) } else { e.into() })?;
+
+    let stored = crate::session::StoredSession::from_login(&data, username, Some(password_md5.to_string()));
+    crate::session::save_session(&stored).map_err(AppError::Io)?;
+    info!("✅ [DAEMON] 登录成功: {}", data.username);
+    Ok(stored)
+}
+
+// 会话由 start_background_services 从 session.dat 恢复，没有保存的会话时无法启动
+fn ensure_logged_in(ctx: &Ctx) -> Result<(), AppError> {
+    if ctx.app_state().session.lock().unwrap().is_some() {
+        return Ok(());
+    }
+    Err(AppError::Config(
+        "没有保存的登录会话，请先运行 order-print-client login <账号>，或在图形界面中勾选记住登录后登录".to_string(),
+    ))
+}
+
+// 收到新订单通知后获取详情并放入打印队列 (已打印的订单会被跳过)
+async fn print_notified_order(ctx: &Ctx, order_id: &str) {
+    match crate::fetch_order(ctx, order_id).await {
+        Ok(order) => {
+            let payload = JobPayload::Order { order: Box::new(order), force: false };
+            if let Err(e) = ctx.app_state().print_jobs.submit(payload, "websocket") {
+                error!("❌ [DAEMON] 订单 {} 加入打印队列失败: {}", order_id, e);
+            }
+        }
+        Err(e) => error!("❌ [DAEMON] 获取订单 {} 详情失败: {}", order_id, e),
+    }
+}
+
+fn run_daemon() -> Result<(), AppError> {
+    tauri::async_runtime::block_on(async {
+        let (orders_tx, mut orders_rx) = mpsc::unbounded_channel::<String>();
+        let headless = Arc::new(HeadlessContext::new(Some(status_file_path()), Some(orders_tx)));
+        let ctx: Ctx = headless.clone();
+        let state = ctx.app_state();
+
+        info!("🚀 [DAEMON] 无界面模式启动，状态文件: {}", status_file_path().display());
+        crate::load_persisted_state(state);
+        if configure_printers(state).is_empty() {
+            warn!("⚠️ [DAEMON] 没有可用的打印机，收到的订单将无法打印");
+        }
+
+        crate::start_background_services(ctx.clone());
+        if let Err(e) = ensure_logged_in(&ctx) {
+            error!("❌ [DAEMON] 登录失败: {}", e);
+            headless.mark_stopped();
+            return Err(e);
+        }
+        headless.write_status();
+
+        let shutdown = tokio::signal::ctrl_c();
+        tokio::pin!(shutdown);
+        let mut status_interval = tokio::time::interval(tokio::time::Duration::from_secs(STATUS_INTERVAL_SECS));
+
+        loop {
+            tokio::select! {
+                Some(order_id) = orders_rx.recv() => print_notified_order(&ctx, &order_id).await,
+                _ = status_interval.tick() => headless.write_status(),
+                _ = &mut shutdown => {
+                    info!("👋 [DAEMON] 收到退出信号");
+                    break;
+                }
+            }
+        }
+
+        state.connection.stop(ctx.as_ref(), "服务已停止");
+        state.http_api.stop();
+        headless.mark_stopped();
+        Ok(())
+    })
+}
+
+// ============= 一次性命令 =============
+
+fn login(username: &str) -> Result<(), AppError> {
+    eprint!("密码MD5: ");
+    let mut password_md5 = String::new();
+    std::io::stdin()
+        .read_line(&mut password_md5)
+        .map_err(|e| AppError::Io(format!("读取密码失败: {}", e)))?;
+    let password_md5 = password_md5.trim();
+    if password_md5.is_empty() {
+        return Err(AppError::InvalidArgument("密码不能为空".to_string()));
+    }
+
+    let stored = tauri::async_runtime::block_on(login_and_save(username, password_md5))?;
+    println!("已登录 {}，会话已加密保存，可以运行 order-print-client daemon", stored.username);
+    Ok(())
+}
+
+fn list_printers() -> Result<(), AppError> {
+    let printers = crate::get_system_printers().map_err(AppError::PrinterQuery)?;
+    if printers.is_empty() {
+        return Err(AppError::NoPrintersDetected);
+    }
+    for printer in printers {
+        println!(
+            "{}\t{}mm{}",
+            printer.name,
+            printer.width,
+            if printer.is_default { "\t(默认)" } else { "" }
+        );
+    }
+    Ok(())
+}
+
+fn test_print(printer_name: Option<String>) -> Result<(), AppError> {
+    let ctx = one_shot_context();
+    let printers = ctx.app_state().printers.lock().unwrap().clone();
+
+    let printer = match printer_name {
+        Some(name) => printers
+            .into_iter()
+            .find(|p| p.name == name)
+            .unwrap_or_else(|| PrinterConfig {
+                width: crate::layout_width(&crate::printer_profiles::resolve(&name).profile),
+                name,
+                is_default: false,
+                is_enabled: true,
+                font_size: 0,
+                section_sizes: Default::default(),
+                font: Default::default(),
+                dots_per_line: 0,
+                columns: 0,
+                role: Default::default(),
+                copies: Default::default(),
+            }),
+        None => printers.into_iter().find(|p| p.is_enabled).ok_or(AppError::NoEnabledPrinters)?,
+    };
+
+    let content = crate::generate_print_content(&crate::sample_test_order(), &ReceiptLayout::for_printer(&printer))?;
+    tauri::async_runtime::block_on(crate::send_to_printer(&printer.name, &content))?;
+    println!("✅ 测试页已发送到 {}", printer.name);
+    Ok(())
+}
+
+fn preview(path: &Path, width: i32, font_size: i32, font: PrinterFont, columns: Option<usize>) -> Result<(), AppError> {
+    let order = read_order_file(path)?;
+    let layout = ReceiptLayout::new(width, font_size).with_font(font, columns);
+    let content = crate::generate_print_content(&order, &layout)?;
+    println!("{}", strip_escpos(&content));
+    Ok(())
+}
+
+fn print_file(path: &Path, force: bool) -> Result<(), AppError> {
+    let order = read_order_file(path)?;
+    let order_id = order.order_id.clone();
+    let ctx = one_shot_context();
+    let queue = &ctx.app_state().print_jobs;
+    queue.start_worker(ctx.clone());
+
+    // 和其他来源一样经过打印队列，已打印过的订单由队列按台账跳过
+    let payload = JobPayload::Order { order: Box::new(order), force };
+    match tauri::async_runtime::block_on(queue.submit_and_wait(payload, "cli"))? {
+        JobStatus::Skipped { .. } => println!("⏭️ 订单 {} 已打印过，使用 --force 重新打印", order_id),
+        status => {
+            let printed = status.into_result()?;
+            println!("✅ 订单 {} 已打印到 {} 台打印机", order_id, printed);
+        }
+    }
+    Ok(())
+}
+
+// 去掉ESC/POS控制命令，只保留文字 (用于预览和诊断包)
+pub(crate) fn strip_escpos(content: &str) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut chars = content.chars();
+
+    while let Some(c) = chars.next() {
+        let prefix = match c {
+            '\x1B' | '\x1C' | '\x1D' => c,
+            _ => {
+                output.push(c);
+                continue;
+            }
+        };
+        let Some(command) = chars.next() else { break };
+
+        // 各命令后面的参数字节数
+        let params = match (prefix, command) {
+            ('\x1B', '@') | ('\x1C', '&') | ('\x1C', '.') => 0,
+            ('\x1B', 'p') => 3,
+            // ESC B n t：蜂鸣器 (次数、时长)
+            ('\x1B', 'B') => 2,
+            // GS V m [n]：m 为 65/66 (A/B) 等走纸后切纸的形式还有一个走纸距离参数
+            ('\x1D', 'V') => match chars.next() {
+                Some('A' | 'B' | 'a' | 'b' | 'g' | 'h') => 1,
+                _ => 0,
+            },
+            // ESC ( fn pL pH ... / GS ( / FS (：参数长度由 pL pH 指定 (如 ESC ( A 蜂鸣器)
+            (_, '(') => {
+                chars.next();
+                let low = chars.next().map(|c| c as usize).unwrap_or(0);
+                let high = chars.next().map(|c| c as usize).unwrap_or(0);
+                low + high * 256
+            }
+            _ => 1,
+        };
+        for _ in 0..params {
+            chars.next();
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_escpos_keeps_only_text() {
+        assert_eq!(strip_escpos("\x1B@\x1C&\x1B\x45\x01Bold\x1B\x45\x00\n"), "Bold\n");
+        // GS V 0 / GS V 1 只有一个参数，GS V 65/66 n 有两个
+        assert_eq!(strip_escpos("A\x1D\x56\x00B\x1D\x56\x01C"), "ABC");
+        assert_eq!(strip_escpos("A\x1D\x56\x42\x03B\x1D\x56\x41\x10C"), "ABC");
+        // 钱箱和蜂鸣器
+        assert_eq!(strip_escpos("A\x1B\x70\x00\x19\x78B\x1B\x42\x03\x02C"), "ABC");
+        assert_eq!(strip_escpos("A\x1B\x28\x41\x03\x00\x61\x64\x01B"), "AB");
+    }
+}
@@ -0,0 +1,396 @@
+// 本地HTTP打印接口
+//
+// 店内POS系统和Electron版本无法调用Tauri命令，可以通过这个接口提交打印任务。
+// 只监听 127.0.0.1，所有请求 (除 /v1/health) 都需要携带 Authorization: Bearer <token>。
+//
+//   GET  /v1/health          健康检查
+//   GET  /v1/printers        已配置的打印机
+//   POST /v1/jobs/order      提交订单 {"order": OrderData, "force": false}
+//   POST /v1/jobs/raw        提交原始ESC/POS数据 {"printer": "...", "data": "<base64>"}
+//   GET  /v1/jobs            最近的任务
+//   GET  /v1/jobs/:id        查询任务状态
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::Engine;
+use log::{info, warn, error};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+use crate::config::HttpApiSettings;
+use crate::error::AppError;
+use crate::events::Ctx;
+use crate::print_jobs::JobPayload;
+use crate::OrderData;
+
+const JOB_SOURCE: &str = "http-api";
+
+#[derive(Clone)]
+struct ApiContext {
+    ctx: Ctx,
+    token: Arc<String>,
+}
+
+#[derive(Deserialize)]
+struct SubmitOrderBody {
+    order: OrderData,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Deserialize)]
+struct SubmitRawBody {
+    printer: String,
+    data: String, // base64编码的ESC/POS数据
+}
+
+// 生成新的访问令牌
+pub(crate) fn generate_token() -> String {
+    use rand::Rng;
+    let bytes: [u8; 24] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[derive(Default)]
+pub(crate) struct HttpApiServer {
+    shutdown: Mutex<Option<watch::Sender<bool>>>,
+}
+
+impl HttpApiServer {
+    pub fn is_running(&self) -> bool {
+        self.shutdown.lock().unwrap().is_some()
+    }
+
+    // 启动服务，已在运行时先停止 (修改端口或令牌后重启)
+    pub fn start(&self, ctx: Ctx, settings: &HttpApiSettings) {
+        self.stop();
+
+        let (tx, mut rx) = watch::channel(false);
+        *self.shutdown.lock().unwrap() = Some(tx);
+
+        let context = ApiContext {
+            ctx: ctx.clone(),
+            token: Arc::new(settings.token.clone()),
+        };
+        let app = Router::new()
+            .route("/v1/health", get(health))
+            .route("/v1/printers", get(list_printers))
+            .route("/v1/jobs", get(list_jobs))
+            .route("/v1/jobs/order", post(submit_order))
+            .route("/v1/jobs/raw", post(submit_raw))
+            .route("/v1/jobs/:id", get(get_job))
+            .with_state(context);
+
+        let addr = SocketAddr::from(([127, 0, 0, 1], settings.port));
+        tauri::async_runtime::spawn(async move {
+            let listener = match tokio::net::TcpListener::bind(addr).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    error!("❌ [HTTP_API] 监听 {} 失败: {}", addr, e);
+                    ctx.emit("http-api-error", AppError::Config(format!("本地打印接口端口 {} 无法使用: {}", addr.port(), e)));
+                    return;
+                }
+            };
+            info!("🌐 [HTTP_API] 本地打印接口已启动: http://{}", addr);
+
+            let shutdown = async move {
+                let _ = rx.changed().await;
+            };
+            if let Err(e) = axum::serve(listener, app).with_graceful_shutdown(shutdown).await {
+                error!("❌ [HTTP_API] 服务异常退出: {}", e);
+            }
+            info!("🌐 [HTTP_API] 本地打印接口已停止");
+        });
+    }
+
+    pub fn stop(&self) {
+        if let Some(tx) = self.shutdown.lock().unwrap().take() {
+            let _ = tx.send(true);
+        }
+    }
+}
+
+// ============= 处理函数 =============
+
+fn error_response(status: StatusCode, error: AppError) -> Response {
+    (status, Json(error)).into_response()
+}
+
+// 检查访问令牌 (固定时间比较)
+fn authorize(context: &ApiContext, headers: &HeaderMap) -> Result<(), Response> {
+    let provided = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+
+    let expected = context.token.as_bytes();
+    let matches = !expected.is_empty()
+        && provided.len() == expected.len()
+        && provided.bytes().zip(expected.iter()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0;
+
+    if matches {
+        Ok(())
+    } else {
+        warn!("🚫 [HTTP_API] 拒绝未授权的请求");
+        Err(error_response(StatusCode::UNAUTHORIZED, AppError::AuthFailed("访问令牌无效".to_string())))
+    }
+}
+
+fn json_body<T>(body: Result<Json<T>, JsonRejection>) -> Result<T, Response> {
+    body.map(|Json(body)| body).map_err(|rejection| {
+        error_response(StatusCode::BAD_REQUEST, AppError::InvalidArgument(rejection.body_text()))
+    })
+}
+
+async fn health() -> Response {
+    Json(serde_json::json!({ "status": "ok" })).into_response()
+}
+
+async fn list_printers(State(context): State<ApiContext>, headers: HeaderMap) -> Response {
+    if let Err(response) = authorize(&context, &headers) {
+        return response;
+    }
+    let printers = context.ctx.app_state().printers.lock().unwrap().clone();
+    Json(printers).into_response()
+}
+
+async fn list_jobs(State(context): State<ApiContext>, headers: HeaderMap) -> Response {
+    if let Err(response) = authorize(&context, &headers) {
+        return response;
+    }
+    Json(context.ctx.app_state().print_jobs.list()).into_response()
+}
+
+async fn get_job(State(context): State<ApiContext>, headers: HeaderMap, Path(job_id): Path<String>) -> Response {
+    if let Err(response) = authorize(&context, &headers) {
+        return response;
+    }
+    match context.ctx.app_state().print_jobs.get(&job_id) {
+        Some(job) => Json(job).into_response(),
+        None => error_response(StatusCode::NOT_FOUND, AppError::InvalidArgument(format!("任务不存在: {}", job_id))),
+    }
+}
+
+async fn submit_order(
+    State(context): State<ApiContext>,
+    headers: HeaderMap,
+    body: Result<Json<SubmitOrderBody>, JsonRejection>,
+) -> Response {
+    if let Err(response) = authorize(&context, &headers) {
+        return response;
+    }
+    let body = match json_body(body) {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    info!("🌐 [HTTP_API] 收到订单打印请求: {}", body.order.order_id);
+    let payload = JobPayload::Order { order: Box::new(body.order), force: body.force };
+    match context.ctx.app_state().print_jobs.submit(payload, JOB_SOURCE) {
+        Ok(job) => (StatusCode::ACCEPTED, Json(job)).into_response(),
+        Err(e) => error_response(StatusCode::SERVICE_UNAVAILABLE, e),
+    }
+}
+
+async fn submit_raw(
+    State(context): State<ApiContext>,
+    headers: HeaderMap,
+    body: Result<Json<SubmitRawBody>, JsonRejection>,
+) -> Response {
+    if let Err(response) = authorize(&context, &headers) {
+        return response;
+    }
+    let body = match json_body(body) {
+        Ok(body) => body,
+        Err(response) => return response,
+    };
+
+    // 只允许发送到已配置的打印机
+    let state = context.ctx.app_state();
+    if !state.printers.lock().unwrap().iter().any(|p| p.name == body.printer) {
+        return error_response(StatusCode::NOT_FOUND, AppError::PrinterNotFound(body.printer));
+    }
+
+    let data = match base64::engine::general_purpose::STANDARD.decode(body.data.trim()) {
+        Ok(data) if !data.is_empty() => data,
+        Ok(_) => return error_response(StatusCode::BAD_REQUEST, AppError::InvalidArgument("打印数据为空".to_string())),
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, AppError::InvalidArgument(format!("data 不是有效的base64: {}", e))),
+    };
+
+    info!("🌐 [HTTP_API] 收到原始数据打印请求: {} ({} 字节)", body.printer, data.len());
+    let payload = JobPayload::Raw { printer: body.printer, data };
+    match state.print_jobs.submit(payload, JOB_SOURCE) {
+        Ok(job) => (StatusCode::ACCEPTED, Json(job)).into_response(),
+        Err(e) => error_response(StatusCode::SERVICE_UNAVAILABLE, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, TestContext};
+    use std::time::Duration;
+
+    const TOKEN: &str = "test-token";
+
+    struct TestServer {
+        context: Arc<TestContext>,
+        server: HttpApiServer,
+        base: String,
+        client: reqwest::Client,
+    }
+
+    impl Drop for TestServer {
+        fn drop(&mut self) {
+            self.server.stop();
+        }
+    }
+
+    impl TestServer {
+        async fn start() -> Self {
+            let context = TestContext::new();
+            let ctx = context.ctx();
+            ctx.app_state().print_jobs.start_worker(ctx.clone());
+
+            // 先占用一个空闲端口再释放，交给服务监听
+            let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+            let server = HttpApiServer::default();
+            server.start(ctx, &HttpApiSettings { enabled: true, port, token: TOKEN.to_string() });
+
+            let test_server = TestServer {
+                context,
+                server,
+                base: format!("http://127.0.0.1:{}", port),
+                client: reqwest::Client::new(),
+            };
+            for _ in 0..100 {
+                if test_server.client.get(test_server.url("/v1/health")).send().await.is_ok() {
+                    return test_server;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("本地打印接口没有启动");
+        }
+
+        fn url(&self, path: &str) -> String {
+            format!("{}{}", self.base, path)
+        }
+
+        async fn submit_order(&self, order_id: &str) -> reqwest::Response {
+            self.client
+                .post(self.url("/v1/jobs/order"))
+                .bearer_auth(TOKEN)
+                .json(&serde_json::json!({ "order": test_support::order(order_id) }))
+                .send()
+                .await
+                .unwrap()
+        }
+
+        // 轮询任务状态直到不再是 queued/printing
+        async fn wait_for_job(&self, job_id: &str) -> serde_json::Value {
+            for _ in 0..100 {
+                let job: serde_json::Value = self
+                    .client
+                    .get(self.url(&format!("/v1/jobs/{}", job_id)))
+                    .bearer_auth(TOKEN)
+                    .send()
+                    .await
+                    .unwrap()
+                    .json()
+                    .await
+                    .unwrap();
+                if job["status"] != "queued" && job["status"] != "printing" {
+                    return job;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("任务 {} 没有完成", job_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_or_wrong_token() {
+        let server = TestServer::start().await;
+
+        let health = server.client.get(server.url("/v1/health")).send().await.unwrap();
+        assert_eq!(health.status(), StatusCode::OK);
+
+        let missing = server.client.get(server.url("/v1/jobs")).send().await.unwrap();
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+
+        let wrong = server.client.get(server.url("/v1/jobs")).bearer_auth("wrong-token").send().await.unwrap();
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+
+        let submit = server
+            .client
+            .post(server.url("/v1/jobs/order"))
+            .json(&serde_json::json!({ "order": test_support::order("API-0") }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(submit.status(), StatusCode::UNAUTHORIZED);
+        assert!(server.context.ctx().app_state().print_jobs.list().is_empty());
+    }
+
+    #[test]
+    fn empty_token_rejects_everything() {
+        let context = ApiContext { ctx: TestContext::new().ctx(), token: Arc::new(String::new()) };
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer ".parse().unwrap());
+        assert!(authorize(&context, &headers).is_err());
+        assert!(authorize(&context, &HeaderMap::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn submitted_order_completes() {
+        let server = TestServer::start().await;
+
+        let response = server.submit_order("API-1").await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let job: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(job["order_id"], "API-1");
+        assert_eq!(job["source"], JOB_SOURCE);
+
+        let job = server.wait_for_job(job["id"].as_str().unwrap()).await;
+        assert_eq!(job["status"], "completed", "{}", job);
+        assert_eq!(job["printers"], 1);
+        assert!(server.context.ctx().app_state().order_ledger.lock().unwrap().is_printed("API-1"));
+    }
+
+    #[tokio::test]
+    async fn already_printed_order_is_skipped() {
+        let server = TestServer::start().await;
+        server.context.ctx().app_state().order_ledger.lock().unwrap().mark_printed("API-2");
+
+        let job: serde_json::Value = server.submit_order("API-2").await.json().await.unwrap();
+        let job = server.wait_for_job(job["id"].as_str().unwrap()).await;
+
+        assert_eq!(job["status"], "skipped", "{}", job);
+        assert_eq!(job["reason"], "already_printed");
+    }
+
+    #[tokio::test]
+    async fn invalid_json_is_bad_request() {
+        let server = TestServer::start().await;
+
+        let response = server
+            .client
+            .post(server.url("/v1/jobs/order"))
+            .bearer_auth(TOKEN)
+            .header("content-type", "application/json")
+            .body("{\"order\": 42}")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(server.context.ctx().app_state().print_jobs.list().is_empty());
+    }
+}
@@ -0,0 +1,573 @@
+// A4/Letter 发票 PDF
+//
+// 小票打印机收到的是 ESC/POS 命令流，激光/喷墨打印机和 Microsoft Print to PDF、WPS PDF
+// 等虚拟打印机会把命令当作文字打出来。打印机配置库中 thermal 为 false 的打印机改为打印
+// 这里生成的发票：标志、店铺和顾客信息、菜品表格和费用合计，菜品多时自动分页。
+//
+// 中文需要嵌入字体：依次尝试 invoice.font_path 和系统中常见的中文字体 (只嵌入用到的字形)，
+// 都找不到时使用 PDF 内置的 Helvetica，中文无法显示。文字宽度按显示宽度估算
+// (中文占两列)，用于右对齐金额和菜名折行。
+
+use log::{info, warn};
+use printpdf::{
+    BuiltinFont, Color, Greyscale, Image, ImageTransform, IndirectFontRef, Line, Mm, PdfDocument,
+    PdfDocumentReference, PdfLayerReference, Point, Rect,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::InvoiceSettings;
+use crate::error::AppError;
+use crate::sales_report::{format_cents, parse_cents};
+use crate::OrderData;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PaperSize {
+    #[default]
+    A4,
+    Letter,
+}
+
+impl PaperSize {
+    // 宽、高 (毫米)
+    fn dimensions(self) -> (f32, f32) {
+        match self {
+            PaperSize::A4 => (210.0, 297.0),
+            PaperSize::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+// 常见的中文字体位置 (Windows、macOS、Linux)，单个字体文件排在字体集 (TTC) 前面
+const FONT_CANDIDATES: &[&str] = &[
+    "C:\\Windows\\Fonts\\Deng.ttf",
+    "C:\\Windows\\Fonts\\simhei.ttf",
+    "C:\\Windows\\Fonts\\msyh.ttc",
+    "C:\\Windows\\Fonts\\simsun.ttc",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "/System/Library/Fonts/PingFang.ttc",
+    "/usr/share/fonts/truetype/droid/DroidSansFallbackFull.ttf",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+];
+
+// 临时发票文件保留的小时数 (足够系统PDF程序完成打印)
+const SPOOL_RETENTION_HOURS: u64 = 24;
+
+const MARGIN: f32 = 18.0;
+const FOOTER_HEIGHT: f32 = 14.0;
+const PT_TO_MM: f32 = 0.3528;
+// 每个显示列的宽度 (字号的倍数)，数字在常见字体中约为 0.55 个字号
+const COLUMN_EM: f32 = 0.55;
+
+const BODY_SIZE: f32 = 9.5;
+const SMALL_SIZE: f32 = 8.0;
+
+struct Fonts {
+    regular: IndirectFontRef,
+    bold: IndirectFontRef,
+}
+
+// 页面和当前书写位置 (y 为距页面底部的毫米数)
+struct Canvas<'a> {
+    doc: &'a PdfDocumentReference,
+    layer: PdfLayerReference,
+    fonts: Fonts,
+    width: f32,
+    height: f32,
+    y: f32,
+    pages: usize,
+}
+
+fn text_width(text: &str, size: f32) -> f32 {
+    crate::display_width(text) as f32 * size * COLUMN_EM * PT_TO_MM
+}
+
+// 按宽度折行，优先在空格处断开；中文可以在任意字之间断开
+fn wrap(text: &str, max_width: f32, size: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for ch in paragraph.chars() {
+            line.push(ch);
+            if text_width(&line, size) <= max_width {
+                continue;
+            }
+            line.pop();
+            let carry = match line.rfind(' ') {
+                Some(index) if index > 0 => {
+                    let rest = line[index + 1..].to_string();
+                    line.truncate(index);
+                    rest
+                }
+                _ => String::new(),
+            };
+            lines.push(std::mem::take(&mut line));
+            line = carry;
+            line.push(ch);
+        }
+        lines.push(line);
+    }
+    if lines.is_empty() {
+        lines.push(String::new());
+    }
+    lines
+}
+
+fn money(value: &str) -> String {
+    let cents = parse_cents(value);
+    if cents < 0 {
+        format!("-${}", format_cents(-cents))
+    } else {
+        format!("${}", format_cents(cents))
+    }
+}
+
+fn grey(level: f32) -> Color {
+    Color::Greyscale(Greyscale::new(level, None))
+}
+
+fn load_fonts(doc: &PdfDocumentReference, settings: &InvoiceSettings) -> Result<Fonts, AppError> {
+    let configured = Some(settings.font_path.as_str()).filter(|p| !p.trim().is_empty());
+    for path in configured.into_iter().chain(FONT_CANDIDATES.iter().copied()) {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(_) => continue,
+        };
+        match doc.add_external_font(bytes.as_slice()) {
+            Ok(font) => {
+                info!("🧾 [INVOICE] 使用字体: {}", path);
+                return Ok(Fonts { regular: font.clone(), bold: font });
+            }
+            Err(e) => warn!("⚠️ [INVOICE] 字体 {} 无法使用: {}", path, e),
+        }
+    }
+
+    warn!("⚠️ [INVOICE] 没有找到中文字体，使用内置字体 (中文无法显示)，可以在 invoice.font_path 中指定");
+    let builtin = |font| doc.add_builtin_font(font).map_err(|e| AppError::Internal(format!("加载内置字体失败: {}", e)));
+    Ok(Fonts {
+        regular: builtin(BuiltinFont::Helvetica)?,
+        bold: builtin(BuiltinFont::HelveticaBold)?,
+    })
+}
+
+impl<'a> Canvas<'a> {
+    fn text(&self, text: &str, size: f32, x: f32, bold: bool) {
+        let font = if bold { &self.fonts.bold } else { &self.fonts.regular };
+        self.layer.use_text(text, size, Mm(x), Mm(self.y), font);
+    }
+
+    fn text_right(&self, text: &str, size: f32, right: f32, bold: bool) {
+        self.text(text, size, right - text_width(text, size), bold);
+    }
+
+    fn rule(&self, x1: f32, x2: f32, thickness: f32) {
+        self.layer.set_outline_color(grey(0.6));
+        self.layer.set_outline_thickness(thickness);
+        self.layer.add_line(Line {
+            points: vec![(Point::new(Mm(x1), Mm(self.y)), false), (Point::new(Mm(x2), Mm(self.y)), false)],
+            is_closed: false,
+        });
+    }
+
+    fn shade(&self, x1: f32, x2: f32, top: f32, bottom: f32) {
+        self.layer.set_fill_color(grey(0.9));
+        self.layer.add_rect(Rect::new(Mm(x1), Mm(bottom), Mm(x2), Mm(top)));
+        self.layer.set_fill_color(grey(0.0));
+    }
+
+    fn right_edge(&self) -> f32 {
+        self.width - MARGIN
+    }
+
+    fn footer(&self) {
+        let layer = &self.layer;
+        layer.set_fill_color(grey(0.4));
+        let label = format!("Page {}", self.pages);
+        let x = self.right_edge() - text_width(&label, SMALL_SIZE);
+        layer.use_text(label, SMALL_SIZE, Mm(x), Mm(MARGIN - 6.0), &self.fonts.regular);
+        layer.set_fill_color(grey(0.0));
+    }
+
+    // 剩余空间不够 height 时换页，返回是否换了页
+    fn ensure(&mut self, height: f32) -> bool {
+        if self.y - height >= MARGIN + FOOTER_HEIGHT {
+            return false;
+        }
+        self.footer();
+        let (page, layer) = self.doc.add_page(Mm(self.width), Mm(self.height), "Layer 1");
+        self.layer = self.doc.get_page(page).get_layer(layer);
+        self.pages += 1;
+        self.y = self.height - MARGIN;
+        true
+    }
+}
+
+// 标志图片缩放到最高 18mm、最宽 50mm，返回占用的宽度
+fn draw_logo(canvas: &Canvas, path: &str, top: f32) -> f32 {
+    if path.trim().is_empty() {
+        return 0.0;
+    }
+    let image = match printpdf::image_crate::open(path) {
+        Ok(image) => image,
+        Err(e) => {
+            warn!("⚠️ [INVOICE] 标志图片无法读取 {}: {}", path, e);
+            return 0.0;
+        }
+    };
+    let (px_width, px_height) = (image.width().max(1) as f32, image.height().max(1) as f32);
+    let dpi = (px_height * 25.4 / 18.0).max(px_width * 25.4 / 50.0);
+    let (width, height) = (px_width * 25.4 / dpi, px_height * 25.4 / dpi);
+
+    Image::from_dynamic_image(&image).add_to_layer(
+        canvas.layer.clone(),
+        ImageTransform {
+            translate_x: Some(Mm(MARGIN)),
+            translate_y: Some(Mm(top - height)),
+            dpi: Some(dpi),
+            ..Default::default()
+        },
+    );
+    width + 5.0
+}
+
+fn draw_header(canvas: &mut Canvas, order: &OrderData, settings: &InvoiceSettings) {
+    let top = canvas.y;
+    let left = MARGIN + draw_logo(canvas, &settings.logo_path, top);
+    let right = canvas.right_edge();
+
+    // 店铺信息 (左)
+    canvas.y = top - 6.0;
+    for (index, line) in wrap(&order.rd_name, right - left - 70.0, 15.0).iter().enumerate() {
+        if index > 0 {
+            canvas.y -= 6.5;
+        }
+        canvas.text(line, 15.0, left, true);
+    }
+    for line in [&order.rd_address, &order.rd_phone] {
+        if !line.is_empty() {
+            canvas.y -= 5.0;
+            canvas.text(line, BODY_SIZE, left, false);
+        }
+    }
+    let shop_bottom = canvas.y;
+
+    // 发票信息 (右)
+    canvas.y = top - 7.0;
+    canvas.text_right("INVOICE", 20.0, right, true);
+    let serial = if order.serial_num > 0 {
+        format!("#{:03}", order.serial_num)
+    } else {
+        format!("#{}", crate::get_order_serial(order))
+    };
+    let details = [
+        ("Order #", order.order_id.clone()),
+        ("Serial", serial),
+        ("Date", crate::format_order_time(&order.create_time)),
+        ("Type", crate::get_order_type_text(order).to_string()),
+        ("Payment", crate::get_payment_method_text(order.paystyle).to_string()),
+    ];
+    canvas.y -= 3.0;
+    for (label, value) in details {
+        canvas.y -= 4.8;
+        canvas.text_right(&value, BODY_SIZE, right, false);
+        canvas.text_right(&format!("{}:", label), BODY_SIZE, right - 45.0, true);
+    }
+
+    canvas.y = canvas.y.min(shop_bottom).min(top - 20.0) - 6.0;
+    canvas.rule(MARGIN, right, 0.8);
+    canvas.y -= 7.0;
+
+    // 顾客信息和取餐/送达时间
+    let column = MARGIN + (right - MARGIN) / 2.0;
+    let block_top = canvas.y;
+    canvas.text("BILL TO", SMALL_SIZE, MARGIN, true);
+    let mut customer: Vec<String> = vec![order.recipient_name.clone(), order.recipient_phone.clone()];
+    if !order.user_email.is_empty() {
+        customer.push(order.user_email.clone());
+    }
+    if order.delivery_style == 1 && !order.recipient_address.is_empty() {
+        customer.extend(wrap(&order.recipient_address, column - MARGIN - 5.0, BODY_SIZE));
+    }
+    for line in customer.iter().filter(|l| !l.is_empty()) {
+        canvas.y -= 4.8;
+        canvas.text(line, BODY_SIZE, MARGIN, false);
+    }
+    let customer_bottom = canvas.y;
+
+    canvas.y = block_top;
+    let (time_label, distance) = if order.delivery_style == 1 {
+        let distance = Some(&order.recipient_distance).filter(|d| !d.is_empty() && d.as_str() != "0.00");
+        ("DELIVERY TIME", distance)
+    } else {
+        ("PICKUP TIME", None)
+    };
+    canvas.text(time_label, SMALL_SIZE, column, true);
+    canvas.y -= 4.8;
+    canvas.text(&crate::format_delivery_time(&order.delivery_time), BODY_SIZE, column, false);
+    if let Some(distance) = distance {
+        canvas.y -= 4.8;
+        canvas.text(&format!("{} miles", distance), BODY_SIZE, column, false);
+    }
+
+    canvas.y = canvas.y.min(customer_bottom) - 9.0;
+}
+
+// 表格列：菜名 (左)、数量、单价、金额 (右对齐的右边界)
+struct Columns {
+    qty: f32,
+    unit: f32,
+    amount: f32,
+}
+
+fn table_columns(canvas: &Canvas) -> Columns {
+    let right = canvas.right_edge();
+    Columns {
+        qty: right - 62.0,
+        unit: right - 30.0,
+        amount: right,
+    }
+}
+
+fn draw_table_header(canvas: &mut Canvas, columns: &Columns) {
+    canvas.shade(MARGIN, columns.amount, canvas.y + 4.5, canvas.y - 2.5);
+    canvas.text("Item", BODY_SIZE, MARGIN + 2.0, true);
+    canvas.text_right("Qty", BODY_SIZE, columns.qty, true);
+    canvas.text_right("Unit Price", BODY_SIZE, columns.unit, true);
+    canvas.text_right("Amount", BODY_SIZE, columns.amount - 2.0, true);
+    canvas.y -= 8.0;
+}
+
+fn draw_items(canvas: &mut Canvas, order: &OrderData) {
+    let columns = table_columns(canvas);
+    let name_width = columns.qty - MARGIN - 20.0;
+    draw_table_header(canvas, &columns);
+
+    for item in &order.dishes_array {
+        let mut lines: Vec<(String, f32)> = wrap(&item.dishes_name, name_width, BODY_SIZE)
+            .into_iter()
+            .map(|line| (line, BODY_SIZE))
+            .collect();
+        if !item.dishes_describe.is_empty() {
+            lines.extend(wrap(&format!("+ {}", item.dishes_describe), name_width, SMALL_SIZE).into_iter().map(|l| (l, SMALL_SIZE)));
+        }
+        if !item.remark.is_empty() {
+            lines.extend(wrap(&format!("Note: {}", item.remark), name_width, SMALL_SIZE).into_iter().map(|l| (l, SMALL_SIZE)));
+        }
+
+        let height = lines.len() as f32 * 4.6 + 3.0;
+        if canvas.ensure(height) {
+            draw_table_header(canvas, &columns);
+        }
+
+        canvas.text_right(&item.amount.to_string(), BODY_SIZE, columns.qty, false);
+        canvas.text_right(&money(&item.unit_price), BODY_SIZE, columns.unit, false);
+        canvas.text_right(&money(&item.price), BODY_SIZE, columns.amount - 2.0, false);
+        for (index, (line, size)) in lines.iter().enumerate() {
+            if index > 0 {
+                canvas.y -= 4.6;
+            }
+            if *size < BODY_SIZE {
+                canvas.layer.set_fill_color(grey(0.35));
+                canvas.text(line, *size, MARGIN + 5.0, false);
+                canvas.layer.set_fill_color(grey(0.0));
+            } else {
+                canvas.text(line, *size, MARGIN + 2.0, false);
+            }
+        }
+        canvas.y -= 3.0;
+        canvas.rule(MARGIN, columns.amount, 0.3);
+        canvas.y -= 5.0;
+    }
+}
+
+fn draw_totals(canvas: &mut Canvas, order: &OrderData) {
+    let rate_label = |label: &str, rate: &str| {
+        let rate: f64 = rate.parse().unwrap_or(0.0);
+        if rate > 0.0 {
+            format!("{} ({:.1}%)", label, rate * 100.0)
+        } else {
+            label.to_string()
+        }
+    };
+    let negative = |value: &str| format!("-{}", value.trim());
+
+    let mut rows: Vec<(String, String)> = vec![("Subtotal".to_string(), money(&order.sub_total))];
+    let optional = [
+        ("Discount".to_string(), negative(&order.discount_total), &order.discount_total),
+        ("Exemption".to_string(), negative(&order.exemption), &order.exemption),
+        (rate_label("Tax", &order.tax_rate), order.tax_fee.clone(), &order.tax_fee),
+        ("Delivery Fee".to_string(), order.delivery_fee.clone(), &order.delivery_fee),
+        ("Retail Delivery Fee".to_string(), order.retail_delivery_fee.clone(), &order.retail_delivery_fee),
+        (rate_label("Service Fee", &order.convenience_rate), order.convenience_fee.clone(), &order.convenience_fee),
+        ("Tip".to_string(), order.tip_fee.clone(), &order.tip_fee),
+    ];
+    for (label, value, raw) in optional {
+        if parse_cents(raw) > 0 {
+            rows.push((label, money(&value)));
+        }
+    }
+
+    canvas.ensure(rows.len() as f32 * 5.5 + 16.0);
+    let right = canvas.right_edge() - 2.0;
+    let label_x = right - 75.0;
+    canvas.y -= 2.0;
+    for (label, value) in rows {
+        canvas.text(&label, BODY_SIZE, label_x, false);
+        canvas.text_right(&value, BODY_SIZE, right, false);
+        canvas.y -= 5.5;
+    }
+    canvas.y += 2.0;
+    canvas.rule(label_x, right + 2.0, 0.8);
+    canvas.y -= 7.0;
+    canvas.text("TOTAL", 12.0, label_x, true);
+    canvas.text_right(&money(&order.total), 12.0, right, true);
+    canvas.y -= 12.0;
+}
+
+fn draw_notes(canvas: &mut Canvas, order: &OrderData) {
+    if !order.order_notes.is_empty() {
+        let lines = wrap(&order.order_notes, canvas.right_edge() - MARGIN, BODY_SIZE);
+        canvas.ensure(lines.len() as f32 * 4.8 + 8.0);
+        canvas.text("NOTES", SMALL_SIZE, MARGIN, true);
+        for line in lines {
+            canvas.y -= 4.8;
+            canvas.text(&line, BODY_SIZE, MARGIN, false);
+        }
+        canvas.y -= 10.0;
+    }
+
+    canvas.ensure(8.0);
+    let message = "Thank you for your order!";
+    canvas.text(message, BODY_SIZE, (canvas.width - text_width(message, BODY_SIZE)) / 2.0, false);
+}
+
+// 生成订单的发票 PDF
+pub(crate) fn render(order: &OrderData, settings: &InvoiceSettings) -> Result<Vec<u8>, AppError> {
+    let (width, height) = settings.paper.dimensions();
+    let title = format!("Invoice {}", order.order_id);
+    let (doc, page, layer) = PdfDocument::new(&title, Mm(width), Mm(height), "Layer 1");
+    let fonts = load_fonts(&doc, settings)?;
+
+    let mut canvas = Canvas {
+        layer: doc.get_page(page).get_layer(layer),
+        doc: &doc,
+        fonts,
+        width,
+        height,
+        y: height - MARGIN,
+        pages: 1,
+    };
+    draw_header(&mut canvas, order, settings);
+    draw_items(&mut canvas, order);
+    draw_totals(&mut canvas, order);
+    draw_notes(&mut canvas, order);
+    canvas.footer();
+    let pages = canvas.pages;
+    drop(canvas);
+
+    let bytes = doc
+        .save_to_bytes()
+        .map_err(|e| AppError::Internal(format!("生成发票PDF失败: {}", e)))?;
+    info!("🧾 [INVOICE] 订单 {} 的发票已生成: {} 页, {} 字节", order.order_id, pages, bytes.len());
+    Ok(bytes)
+}
+
+// 生成发票并写入文件
+pub(crate) fn save(order: &OrderData, settings: &InvoiceSettings, path: &Path) -> Result<(), AppError> {
+    let bytes = render(order, settings)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, bytes).map_err(|e| AppError::Io(format!("写入 {} 失败: {}", path.display(), e)))?;
+    Ok(())
+}
+
+fn spool_dir() -> PathBuf {
+    crate::get_app_data_dir().join("invoices")
+}
+
+fn file_name_part(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+// 打印用的临时发票文件。同一订单同时发给多台打印机、或上一次的文件还被PDF程序占用时
+// 不能共用文件，文件名包含打印机名称和生成时间
+pub(crate) fn spool_path(order: &OrderData, printer_name: &str) -> PathBuf {
+    spool_dir().join(format!(
+        "invoice_{}_{}_{}.pdf",
+        file_name_part(&order.order_id),
+        file_name_part(printer_name),
+        chrono::Local::now().format("%H%M%S%3f")
+    ))
+}
+
+// 删除过期的临时发票文件。printto 由系统关联的PDF程序在后台读取文件，打印后不能立即删除
+pub(crate) fn prune_spool() {
+    prune_dir(&spool_dir(), Duration::from_secs(SPOOL_RETENTION_HOURS * 60 * 60));
+}
+
+fn prune_dir(dir: &Path, retention: Duration) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    let mut removed = 0;
+
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .map(|age| age > retention)
+            .unwrap_or(false);
+        if expired && fs::remove_file(entry.path()).is_ok() {
+            removed += 1;
+        }
+    }
+
+    if removed > 0 {
+        info!("🧹 [INVOICE] 已删除 {} 个过期的临时发票文件", removed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    #[test]
+    fn prune_removes_only_expired_invoices() {
+        let dir = crate::get_app_data_dir().join("invoice_prune_test");
+        fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("invoice_old.pdf");
+        let fresh = dir.join("invoice_fresh.pdf");
+        fs::write(&old, b"%PDF").unwrap();
+        fs::write(&fresh, b"%PDF").unwrap();
+        let two_days_ago = SystemTime::now() - Duration::from_secs(48 * 60 * 60);
+        fs::File::options().write(true).open(&old).unwrap().set_modified(two_days_ago).unwrap();
+
+        prune_dir(&dir, Duration::from_secs(SPOOL_RETENTION_HOURS * 60 * 60));
+
+        assert!(!old.exists());
+        assert!(fresh.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn each_printer_gets_its_own_spool_file() {
+        let order = crate::sample_test_order();
+        let pdf = spool_path(&order, "Microsoft Print to PDF");
+        let office = spool_path(&order, "\\\\server\\HP LaserJet");
+
+        assert_ne!(pdf, office);
+        assert_eq!(pdf.parent(), Some(spool_dir().as_path()));
+        assert_eq!(office.parent(), Some(spool_dir().as_path()));
+        let name = office.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with("invoice_23410121749595834___server_HP_LaserJet_"), "{}", name);
+    }
+}
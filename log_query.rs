@@ -0,0 +1,356 @@
+// 日志查询：跨所有保留的日志文件 (包括压缩的旧日志) 分页搜索，返回结构化记录
+//
+// 日志行格式: 2025-01-15 18:30:00.123[target][LEVEL] 消息
+// 消息中的 [PRINTER]、[ORDER_LIST] 等标签单独提取出来用于过滤。
+// 没有时间前缀的行 (多行消息) 归入上一条记录。
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+
+use crate::error::AppError;
+use crate::logging::{self, LogFileInfo};
+
+const DEFAULT_LIMIT: usize = 200;
+const MAX_LIMIT: usize = 2000;
+
+static LINE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?s)^(\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}(?:\.\d+)?)\[([^\]]*)\]\[([A-Z]+)\] ?(.*)$").unwrap()
+});
+
+static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[([A-Z][A-Z0-9_]*)\]").unwrap());
+
+// 文件名中的日期和轮转编号: print_client_YYYYMMDD[.N].log[.gz]
+static FILE_NAME_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^print_client_(\d{8})(?:\.(\d+))?\.log(?:\.gz)?$").unwrap()
+});
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct LogRecord {
+    pub timestamp: String,
+    pub target: String,
+    pub level: String,
+    pub tags: Vec<String>,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct LogQuery {
+    pub date_from: Option<String>, // YYYY-MM-DD (包含)
+    pub date_to: Option<String>,   // YYYY-MM-DD (包含)
+    pub level: Option<String>,     // 最低级别，例如 warn 返回 WARN 和 ERROR
+    pub tag: Option<String>,       // 例如 PRINTER 或 [PRINTER]
+    pub text: Option<String>,      // 在消息和target中搜索 (不区分大小写)，可以是订单号
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct LogQueryResult {
+    pub records: Vec<LogRecord>, // 从新到旧
+    pub offset: usize,
+    pub has_more: bool,
+    pub files_searched: usize,
+}
+
+// 解析一行日志，不是记录开头时返回 None
+pub(crate) fn parse_line(line: &str) -> Option<LogRecord> {
+    let caps = LINE_RE.captures(line)?;
+    let message = caps[4].to_string();
+    Some(LogRecord {
+        timestamp: caps[1].to_string(),
+        target: caps[2].to_string(),
+        level: caps[3].to_string(),
+        tags: TAG_RE.captures_iter(&message).map(|c| c[1].to_string()).collect(),
+        message,
+    })
+}
+
+fn parse_records(content: &str) -> Vec<LogRecord> {
+    let mut records: Vec<LogRecord> = Vec::new();
+    for line in content.lines() {
+        match parse_line(line) {
+            Some(record) => records.push(record),
+            None => {
+                if let Some(last) = records.last_mut() {
+                    last.message.push('\n');
+                    last.message.push_str(line);
+                }
+            }
+        }
+    }
+    records
+}
+
+pub(crate) fn read_log_file(file: &LogFileInfo) -> std::io::Result<String> {
+    let mut bytes = Vec::new();
+    if file.compressed {
+        flate2::read::GzDecoder::new(File::open(&file.path)?).read_to_end(&mut bytes)?;
+    } else {
+        File::open(&file.path)?.read_to_end(&mut bytes)?;
+    }
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// 文件排序键: (日期, 编号)，当前文件 (没有编号) 是当天最新的
+fn file_sort_key(file: &LogFileInfo) -> (String, u64) {
+    match FILE_NAME_RE.captures(&file.name) {
+        Some(caps) => (
+            caps[1].to_string(),
+            caps.get(2).and_then(|n| n.as_str().parse().ok()).unwrap_or(u64::MAX),
+        ),
+        None => (String::new(), 0),
+    }
+}
+
+fn level_rank(level: &str) -> Option<log::Level> {
+    match level.trim_matches(|c| c == '[' || c == ']').to_uppercase().as_str() {
+        "ERROR" => Some(log::Level::Error),
+        "WARN" => Some(log::Level::Warn),
+        "INFO" => Some(log::Level::Info),
+        "DEBUG" => Some(log::Level::Debug),
+        "TRACE" => Some(log::Level::Trace),
+        _ => None,
+    }
+}
+
+fn compact_date(value: &str) -> Result<String, AppError> {
+    chrono::NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map(|d| d.format("%Y%m%d").to_string())
+        .map_err(|_| AppError::InvalidArgument(format!("日期格式应为 YYYY-MM-DD: {}", value)))
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+// 级别、标签和文字过滤条件
+struct RecordFilter {
+    min_level: Option<log::Level>,
+    tag: Option<String>,
+    text: Option<String>,
+}
+
+impl RecordFilter {
+    fn from_query(query: &LogQuery) -> Result<Self, AppError> {
+        let min_level = match non_empty(&query.level) {
+            Some(level) => Some(level_rank(level).ok_or_else(|| AppError::InvalidArgument(format!("无效的日志级别: {}", level)))?),
+            None => None,
+        };
+        Ok(RecordFilter {
+            min_level,
+            tag: non_empty(&query.tag).map(|t| t.trim_matches(|c| c == '[' || c == ']').to_uppercase()),
+            text: non_empty(&query.text).map(|t| t.to_lowercase()),
+        })
+    }
+
+    fn matches(&self, record: &LogRecord) -> bool {
+        if let Some(min_level) = self.min_level {
+            if level_rank(&record.level).map(|l| l > min_level).unwrap_or(true) {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            if !record.tags.iter().any(|t| t == tag) {
+                return false;
+            }
+        }
+        if let Some(text) = &self.text {
+            if !record.message.to_lowercase().contains(text) && !record.target.to_lowercase().contains(text) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// 按日期范围筛选日志文件，从最新的文件开始
+fn newest_first(files: Vec<LogFileInfo>, date_from: Option<&str>, date_to: Option<&str>) -> Vec<LogFileInfo> {
+    let mut files: Vec<(String, u64, LogFileInfo)> = files
+        .into_iter()
+        .map(|f| {
+            let (date, index) = file_sort_key(&f);
+            (date, index, f)
+        })
+        .filter(|(date, _, _)| {
+            date_from.map(|from| date.as_str() >= from).unwrap_or(true)
+                && date_to.map(|to| date.as_str() <= to).unwrap_or(true)
+        })
+        .collect();
+    files.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+    files.into_iter().map(|(_, _, f)| f).collect()
+}
+
+// 跳过 offset 条匹配的记录后取一页，返回 (记录, 是否还有下一页)
+fn collect_page(
+    records: impl Iterator<Item = LogRecord>,
+    filter: &RecordFilter,
+    offset: usize,
+    limit: usize,
+) -> (Vec<LogRecord>, bool) {
+    // 多取一条用于判断是否还有下一页
+    let mut page: Vec<LogRecord> = records.filter(|r| filter.matches(r)).skip(offset).take(limit + 1).collect();
+    let has_more = page.len() > limit;
+    page.truncate(limit);
+    (page, has_more)
+}
+
+pub(crate) fn query_logs(query: &LogQuery) -> Result<LogQueryResult, AppError> {
+    let date_from = non_empty(&query.date_from).map(compact_date).transpose()?;
+    let date_to = non_empty(&query.date_to).map(compact_date).transpose()?;
+    let filter = RecordFilter::from_query(query)?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let files = newest_first(logging::list_log_files(), date_from.as_deref(), date_to.as_deref());
+    let mut files_searched = 0;
+
+    // 按需读取文件，取满一页后不再读取更早的文件
+    let records = files
+        .iter()
+        .filter_map(|file| match read_log_file(file) {
+            Ok(content) => {
+                files_searched += 1;
+                Some(content)
+            }
+            Err(e) => {
+                log::warn!("⚠️ [LOG] 读取日志文件 {} 失败: {}", file.name, e);
+                None
+            }
+        })
+        .flat_map(|content| parse_records(&content).into_iter().rev());
+    let (records, has_more) = collect_page(records, &filter, query.offset, limit);
+
+    Ok(LogQueryResult {
+        records,
+        offset: query.offset,
+        has_more,
+        files_searched,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "\
+2026-10-01 09:00:00.100[order_print_client][INFO] 🚀 [STARTUP] 应用启动
+2026-10-01 09:00:05.200[order_print_client][WARN] ⚠️ [PRINTER] 打印机 XP-80 响应慢
+2026-10-01 09:01:00.300[order_print_client::api][ERROR] ❌ [ORDER_LIST] 获取订单列表失败: 超时
+响应内容:
+{\"code\": 500}
+2026-10-01 09:02:00.400[order_print_client][DEBUG] 🔍 [PRINTER] 订单 A-1001 已发送
+2026-10-01 09:03:00[order_print_client][INFO] ✅ [PRINTER] 订单 A-1002 打印完成";
+
+    fn file(name: &str) -> LogFileInfo {
+        LogFileInfo {
+            name: name.to_string(),
+            path: name.to_string(),
+            size: 0,
+            modified: 0,
+            current: false,
+            compressed: name.ends_with(".gz"),
+        }
+    }
+
+    fn filter(level: Option<&str>, tag: Option<&str>, text: Option<&str>) -> RecordFilter {
+        RecordFilter::from_query(&LogQuery {
+            level: level.map(String::from),
+            tag: tag.map(String::from),
+            text: text.map(String::from),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn messages(records: &[LogRecord]) -> Vec<&str> {
+        records.iter().map(|r| r.message.as_str()).collect()
+    }
+
+    #[test]
+    fn parse_line_splits_fields_and_tags() {
+        let record = parse_line("2026-10-01 09:00:05.200[order_print_client][WARN] ⚠️ [PRINTER] 打印机 [XP-80] 响应慢").unwrap();
+        assert_eq!(record.timestamp, "2026-10-01 09:00:05.200");
+        assert_eq!(record.target, "order_print_client");
+        assert_eq!(record.level, "WARN");
+        assert_eq!(record.tags, ["PRINTER"]);
+        assert_eq!(record.message, "⚠️ [PRINTER] 打印机 [XP-80] 响应慢");
+
+        assert!(parse_line("响应内容:").is_none());
+        assert!(parse_line("2026-10-01[order_print_client][INFO] 没有时间").is_none());
+    }
+
+    #[test]
+    fn continuation_lines_join_the_previous_record() {
+        let records = parse_records(&format!("孤立的一行\n{}", LOG));
+        assert_eq!(records.len(), 5);
+        assert_eq!(records[2].message, "❌ [ORDER_LIST] 获取订单列表失败: 超时\n响应内容:\n{\"code\": 500}");
+        assert_eq!(records[4].timestamp, "2026-10-01 09:03:00");
+    }
+
+    #[test]
+    fn filters_by_level_tag_and_text() {
+        let records = parse_records(LOG);
+        let matching = |f: RecordFilter| -> Vec<String> {
+            records.iter().filter(|r| f.matches(r)).map(|r| r.timestamp[11..19].to_string()).collect()
+        };
+
+        assert_eq!(matching(filter(Some("warn"), None, None)), ["09:00:05", "09:01:00"]);
+        assert_eq!(matching(filter(Some("INFO"), Some("[printer]"), None)), ["09:00:05", "09:03:00"]);
+        assert_eq!(matching(filter(None, None, Some("a-1001"))), ["09:02:00"]);
+        // 文字也在 target 和多行消息的后续行中查找
+        assert_eq!(matching(filter(None, None, Some("::API"))), ["09:01:00"]);
+        assert_eq!(matching(filter(None, None, Some("\"code\""))), ["09:01:00"]);
+        assert!(RecordFilter::from_query(&LogQuery { level: Some("verbose".into()), ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn files_are_searched_newest_first() {
+        let files = vec![
+            file("print_client_20261001.1.log.gz"),
+            file("print_client_20261002.log"),
+            file("print_client_20261001.log"),
+            file("print_client_20261001.2.log.gz"),
+            file("print_client_20260930.log.gz"),
+        ];
+        let names = |files: Vec<LogFileInfo>| files.into_iter().map(|f| f.name).collect::<Vec<_>>();
+
+        assert_eq!(
+            names(newest_first(files.clone(), None, None)),
+            [
+                "print_client_20261002.log",
+                "print_client_20261001.log",
+                "print_client_20261001.2.log.gz",
+                "print_client_20261001.1.log.gz",
+                "print_client_20260930.log.gz",
+            ]
+        );
+        assert_eq!(
+            names(newest_first(files, Some("20261001"), Some("20261001"))),
+            [
+                "print_client_20261001.log",
+                "print_client_20261001.2.log.gz",
+                "print_client_20261001.1.log.gz",
+            ]
+        );
+    }
+
+    #[test]
+    fn pages_use_offset_and_report_more() {
+        let newest_first = || parse_records(LOG).into_iter().rev();
+        let printer = filter(None, Some("PRINTER"), None);
+
+        let (page, has_more) = collect_page(newest_first(), &printer, 0, 2);
+        assert_eq!(messages(&page), ["✅ [PRINTER] 订单 A-1002 打印完成", "🔍 [PRINTER] 订单 A-1001 已发送"]);
+        assert!(has_more);
+
+        let (page, has_more) = collect_page(newest_first(), &printer, 2, 2);
+        assert_eq!(messages(&page), ["⚠️ [PRINTER] 打印机 XP-80 响应慢"]);
+        assert!(!has_more);
+
+        let (page, has_more) = collect_page(newest_first(), &printer, 3, 2);
+        assert!(page.is_empty() && !has_more);
+    }
+}
@@ -0,0 +1,424 @@
+// 日志系统：按日期和大小轮转、保留策略、压缩旧日志、按模块设置级别
+//
+// 当前日志文件始终是 logs/print_client_YYYYMMDD.log (按写入时的日期，跨过零点后自动切换)。
+// 超过大小上限时当前文件改名为 print_client_YYYYMMDD.N.log，然后继续写新的文件。
+// 轮转后在后台线程压缩旧文件 (.log.gz)，并按保留天数和总大小删除最旧的文件。
+//
+// 写日志时持有文件锁，这里的代码不能调用 log 宏 (会死锁)，只有后台线程可以。
+
+use log::{info, warn, LevelFilter, Metadata};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, RwLock};
+use tokio::sync::mpsc;
+
+use crate::error::AppError;
+use crate::log_query::{self, LogRecord};
+use crate::redact;
+
+const LOG_FILE_PREFIX: &str = "print_client_";
+
+// 日志设置 (保存在 log_settings.json)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct LogSettings {
+    pub level: String, // error / warn / info / debug / trace
+    pub redaction: redact::RedactionLevel,
+    // 按模块 (日志target前缀) 设置级别，例如 {"tungstenite": "warn", "order_print_client::connection": "debug"}
+    pub targets: BTreeMap<String, String>,
+    // 单个文件超过这个大小后轮转
+    pub max_file_size_mb: u64,
+    // 保留天数，0 表示不按天数删除
+    pub retention_days: u32,
+    // 所有日志文件的总大小上限，0 表示不限制
+    pub max_total_size_mb: u64,
+    // 是否压缩轮转后的旧日志
+    pub compress: bool,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        LogSettings {
+            level: "info".to_string(),
+            redaction: redact::RedactionLevel::Partial,
+            targets: BTreeMap::new(),
+            max_file_size_mb: 10,
+            retention_days: 14,
+            max_total_size_mb: 200,
+            compress: true,
+        }
+    }
+}
+
+// 日志文件信息 (日志页面和诊断使用)
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct LogFileInfo {
+    pub name: String,
+    pub path: String,
+    pub size: u64,
+    pub modified: u64,
+    pub current: bool,
+    pub compressed: bool,
+}
+
+pub(crate) fn log_dir() -> PathBuf {
+    crate::get_app_data_dir().join("logs")
+}
+
+fn log_settings_path() -> PathBuf {
+    crate::get_app_data_dir().join("log_settings.json")
+}
+
+fn dated_log_file(date: chrono::NaiveDate) -> PathBuf {
+    log_dir().join(format!("{}{}.log", LOG_FILE_PREFIX, date.format("%Y%m%d")))
+}
+
+pub(crate) fn load_log_settings() -> LogSettings {
+    fs::read_to_string(log_settings_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub(crate) fn parse_log_level(level: &str) -> Option<LevelFilter> {
+    match level.to_lowercase().as_str() {
+        "off" => Some(LevelFilter::Off),
+        "error" => Some(LevelFilter::Error),
+        "warn" => Some(LevelFilter::Warn),
+        "info" => Some(LevelFilter::Info),
+        "debug" => Some(LevelFilter::Debug),
+        "trace" => Some(LevelFilter::Trace),
+        _ => None,
+    }
+}
+
+// ============= 级别过滤 =============
+
+struct LevelConfig {
+    default: LevelFilter,
+    // 按前缀长度从长到短排列，匹配最具体的设置
+    targets: Vec<(String, LevelFilter)>,
+}
+
+static LEVELS: Lazy<RwLock<LevelConfig>> = Lazy::new(|| {
+    RwLock::new(LevelConfig { default: LevelFilter::Info, targets: Vec::new() })
+});
+
+fn enabled(metadata: &Metadata) -> bool {
+    let levels = LEVELS.read().unwrap();
+    let level = levels
+        .targets
+        .iter()
+        .find(|(prefix, _)| metadata.target().starts_with(prefix.as_str()))
+        .map(|(_, level)| *level)
+        .unwrap_or(levels.default);
+    metadata.level() <= level
+}
+
+// ============= 轮转写入 =============
+
+struct LogFileState {
+    date: chrono::NaiveDate,
+    path: PathBuf,
+    file: Option<File>,
+    size: u64,
+    max_file_size: u64,
+}
+
+static LOG_FILE: Lazy<Mutex<LogFileState>> = Lazy::new(|| {
+    let date = chrono::Local::now().date_naive();
+    Mutex::new(LogFileState {
+        date,
+        path: dated_log_file(date),
+        file: None,
+        size: 0,
+        max_file_size: LogSettings::default().max_file_size_mb * 1024 * 1024,
+    })
+});
+
+fn open_append(path: &Path) -> std::io::Result<(File, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata().map(|m| m.len()).unwrap_or(0);
+    Ok((file, size))
+}
+
+// 当天的下一个轮转文件名 print_client_YYYYMMDD.N.log (已压缩的编号也要跳过)
+fn next_rotated_path(date: chrono::NaiveDate) -> PathBuf {
+    let dir = log_dir();
+    (1..)
+        .map(|n| dir.join(format!("{}{}.{}.log", LOG_FILE_PREFIX, date.format("%Y%m%d"), n)))
+        .find(|path| !path.exists() && !path.with_extension("log.gz").exists())
+        .unwrap()
+}
+
+impl LogFileState {
+    // 写入前检查日期和大小，需要时切换文件；返回是否发生了轮转
+    fn prepare(&mut self, incoming: u64) -> bool {
+        let today = chrono::Local::now().date_naive();
+        let mut rotated = false;
+
+        if today != self.date {
+            self.date = today;
+            self.path = dated_log_file(today);
+            self.file = None;
+            rotated = true;
+        } else if self.file.is_some() && self.max_file_size > 0 && self.size + incoming > self.max_file_size {
+            self.file = None;
+            let _ = fs::rename(&self.path, next_rotated_path(self.date));
+            rotated = true;
+        }
+
+        if self.file.is_none() {
+            if let Ok((file, size)) = open_append(&self.path) {
+                self.file = Some(file);
+                self.size = size;
+            }
+        }
+        rotated
+    }
+
+    fn write_line(&mut self, line: &str) {
+        let rotated = self.prepare(line.len() as u64 + 1);
+        if let Some(file) = self.file.as_mut() {
+            if writeln!(file, "{}", line).is_ok() {
+                self.size += line.len() as u64 + 1;
+            }
+        }
+        if rotated {
+            spawn_maintenance();
+        }
+    }
+}
+
+// 实时日志：日志页面打开时把新的日志行推送到界面
+static STREAMING: AtomicBool = AtomicBool::new(false);
+static STREAM_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<LogRecord>>>> = Lazy::new(|| Mutex::new(None));
+
+fn write_log_line(line: &str) {
+    LOG_FILE.lock().unwrap().write_line(line);
+
+    if STREAMING.load(Ordering::Relaxed) {
+        if let (Some(record), Some(sender)) = (log_query::parse_line(line), STREAM_SENDER.lock().unwrap().as_ref()) {
+            let _ = sender.send(record);
+        }
+    }
+}
+
+// 订阅新的日志行 (只保留最后一个订阅者)
+pub(crate) fn subscribe() -> mpsc::UnboundedReceiver<LogRecord> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    *STREAM_SENDER.lock().unwrap() = Some(tx);
+    rx
+}
+
+pub(crate) fn set_streaming(enabled: bool) {
+    STREAMING.store(enabled, Ordering::Relaxed);
+}
+
+// 当前正在写入的日志文件 (跨过零点后第一次写入时切换到新日期)
+pub(crate) fn current_log_file() -> PathBuf {
+    LOG_FILE.lock().unwrap().path.clone()
+}
+
+// ============= 初始化和设置 =============
+
+// 日志格式和级别过滤 (所有消息写入前先经过脱敏处理)，输出由调用方添加
+pub(crate) fn base_dispatch() -> fern::Dispatch {
+    fern::Dispatch::new()
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}[{}][{}] {}",
+                chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
+                record.target(),
+                record.level(),
+                redact::redact(&message.to_string())
+            ))
+        })
+        .level(LevelFilter::Trace)
+        .filter(enabled)
+}
+
+pub(crate) fn init_logger() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    fs::create_dir_all(log_dir())?;
+
+    let settings = load_log_settings();
+    apply_log_settings(&settings);
+    LOG_FILE.lock().unwrap().prepare(0);
+
+    base_dispatch()
+        .chain(std::io::stdout()) // 同时输出到控制台
+        .chain(fern::Output::call(|record| write_log_line(&record.args().to_string()))) // 输出到文件
+        .apply()?;
+
+    // apply() 会重置最大级别，重新应用一次
+    apply_log_settings(&settings);
+
+    let log_file = current_log_file();
+    info!("🚀 订单打印客户端启动");
+    info!("📝 日志文件位置: {}", log_file.display());
+    info!("📝 日志级别: {}, 脱敏程度: {:?}, 模块级别: {:?}", settings.level, settings.redaction, settings.targets);
+
+    spawn_maintenance();
+    Ok(log_file)
+}
+
+pub(crate) fn validate_log_settings(settings: &LogSettings) -> Result<(), AppError> {
+    let invalid = |level: &str| {
+        AppError::InvalidArgument(format!("无效的日志级别: {} (可选: off/error/warn/info/debug/trace)", level))
+    };
+    parse_log_level(&settings.level).ok_or_else(|| invalid(&settings.level))?;
+    for level in settings.targets.values() {
+        parse_log_level(level).ok_or_else(|| invalid(level))?;
+    }
+    if settings.max_file_size_mb == 0 {
+        return Err(AppError::InvalidArgument("单个日志文件大小上限不能为0".to_string()));
+    }
+    Ok(())
+}
+
+// 立即生效 (级别、脱敏程度、轮转大小)
+pub(crate) fn apply_log_settings(settings: &LogSettings) {
+    let default = parse_log_level(&settings.level).unwrap_or(LevelFilter::Info);
+    let mut targets: Vec<(String, LevelFilter)> = settings
+        .targets
+        .iter()
+        .filter_map(|(target, level)| parse_log_level(level).map(|level| (target.clone(), level)))
+        .collect();
+    targets.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+
+    // 全局最大级别取所有设置中最详细的一个，具体过滤由 enabled() 完成
+    let max_level = targets.iter().map(|(_, level)| *level).chain(std::iter::once(default)).max().unwrap_or(default);
+    *LEVELS.write().unwrap() = LevelConfig { default, targets };
+    log::set_max_level(max_level);
+
+    redact::set_redaction_level(settings.redaction);
+    LOG_FILE.lock().unwrap().max_file_size = settings.max_file_size_mb * 1024 * 1024;
+}
+
+pub(crate) fn save_log_settings(settings: &LogSettings) -> Result<(), AppError> {
+    let content = serde_json::to_string_pretty(settings)?;
+    fs::write(log_settings_path(), content)
+        .map_err(|e| AppError::Config(format!("保存日志设置失败: {}", e)))
+}
+
+// ============= 文件管理 =============
+
+fn is_log_file_name(name: &str) -> bool {
+    name.starts_with(LOG_FILE_PREFIX) && (name.ends_with(".log") || name.ends_with(".log.gz"))
+}
+
+// 所有日志文件，按修改时间从新到旧
+pub(crate) fn list_log_files() -> Vec<LogFileInfo> {
+    let current = current_log_file();
+    let Ok(entries) = fs::read_dir(log_dir()) else {
+        return Vec::new();
+    };
+
+    let mut files: Vec<LogFileInfo> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !is_log_file_name(&name) {
+                return None;
+            }
+            let metadata = entry.metadata().ok()?;
+            let modified = metadata
+                .modified()
+                .ok()
+                .and_then(|m| m.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            Some(LogFileInfo {
+                compressed: name.ends_with(".gz"),
+                current: entry.path() == current,
+                path: entry.path().to_string_lossy().to_string(),
+                name,
+                size: metadata.len(),
+                modified,
+            })
+        })
+        .collect();
+
+    files.sort_by(|a, b| b.modified.cmp(&a.modified).then(b.name.cmp(&a.name)));
+    files
+}
+
+fn compress_file(path: &Path) -> std::io::Result<()> {
+    let gz_path = path.with_extension("log.gz");
+    let mut input = File::open(path)?;
+    let output = File::create(&gz_path)?;
+    let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+// 压缩旧日志并按保留策略删除
+fn run_maintenance() {
+    let settings = load_log_settings();
+
+    if settings.compress {
+        for file in list_log_files().iter().filter(|f| !f.current && !f.compressed) {
+            if let Err(e) = compress_file(Path::new(&file.path)) {
+                warn!("⚠️ [LOG] 压缩日志文件 {} 失败: {}", file.name, e);
+            }
+        }
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let max_age = u64::from(settings.retention_days) * 24 * 3600;
+    let max_total = settings.max_total_size_mb * 1024 * 1024;
+
+    let mut total: u64 = 0;
+    let mut removed = 0;
+    // 从新到旧累计大小，超过保留天数或总大小的旧文件删除 (当前文件始终保留)
+    for file in list_log_files() {
+        let too_old = max_age > 0 && now.saturating_sub(file.modified) > max_age;
+        let too_large = max_total > 0 && total + file.size > max_total;
+        if !file.current && (too_old || too_large) {
+            match fs::remove_file(&file.path) {
+                Ok(_) => removed += 1,
+                Err(e) => warn!("⚠️ [LOG] 删除旧日志 {} 失败: {}", file.name, e),
+            }
+        } else {
+            total += file.size;
+        }
+    }
+
+    if removed > 0 {
+        info!("🧹 [LOG] 已删除 {} 个旧日志文件，剩余 {:.1} MB", removed, total as f64 / 1024.0 / 1024.0);
+    }
+}
+
+fn spawn_maintenance() {
+    std::thread::spawn(run_maintenance);
+}
+
+// 清空日志：删除所有旧文件，截断当前文件
+pub(crate) fn clear_logs() -> Result<usize, AppError> {
+    let mut removed = 0;
+    for file in list_log_files().iter().filter(|f| !f.current) {
+        fs::remove_file(&file.path)?;
+        removed += 1;
+    }
+
+    let mut state = LOG_FILE.lock().unwrap();
+    match state.file.as_ref() {
+        Some(file) => file.set_len(0)?,
+        None => {
+            if state.path.exists() {
+                File::create(&state.path)?;
+            }
+        }
+    }
+    state.size = 0;
+    Ok(removed)
+}
@@ -3,13 +3,47 @@
 
 use serde::{Deserialize, Serialize};
 use std::sync::{Arc, Mutex};
-use tauri::{State, Window};
+use tauri::{Manager, State, Window};
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
 use futures_util::{StreamExt, SinkExt};
 use log::{info, debug, warn, error};
-use std::fs;
 use std::path::PathBuf;
 
+mod api_client;
+mod catchup;
+mod chinese_script;
+mod config;
+mod connection;
+mod diagnostics;
+mod encoding_calibration;
+mod error;
+mod events;
+mod headless;
+mod http_api;
+mod invoice_pdf;
+mod log_query;
+mod logging;
+mod order_changes;
+mod order_export;
+mod order_history;
+mod print_jobs;
+mod printer_actions;
+mod printer_profiles;
+mod receipt_copies;
+mod receipt_layout;
+mod redact;
+mod sales_report;
+mod session;
+#[cfg(test)]
+mod test_support;
+mod ws_protocol;
+
+use api_client::{ApiError, OrderListQuery};
+use connection::ConnectionState;
+use error::AppError;
+use events::{AppContext, Ctx};
+use receipt_layout::{rule, ReceiptLayout, Section, TextSize};
+
 #[cfg(target_os = "windows")]
 use winapi::um::winspool::{EnumPrintersW, PRINTER_INFO_2W, PRINTER_ENUM_LOCAL, PRINTER_ENUM_CONNECTIONS, OpenPrinterW, ClosePrinter, StartDocPrinterW, StartPagePrinter, EndPagePrinter, EndDocPrinter, WritePrinter, DOC_INFO_1W};
 #[cfg(target_os = "windows")]
@@ -25,55 +59,20 @@ use winapi::um::winnt::HANDLE;
 #[cfg(target_os = "windows")]
 use std::ptr;
 
-// 初始化日志系统
-fn init_logger() -> Result<PathBuf, Box<dyn std::error::Error>> {
-    // 获取应用数据目录
-    let app_data_dir = if cfg!(windows) {
-        std::env::var("APPDATA").unwrap_or_else(|_| ".".to_string())
-    } else {
-        std::env::var("HOME").unwrap_or_else(|_| ".".to_string())
-    };
-
-    let log_dir = PathBuf::from(app_data_dir).join("OrderPrintClient").join("logs");
-
-    // 创建日志目录
-    fs::create_dir_all(&log_dir)?;
-
-    // 日志文件路径，包含日期
-    let log_file = log_dir.join(format!("print_client_{}.log", chrono::Local::now().format("%Y%m%d")));
-
-    // 配置日志
-    fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "{}[{}][{}] {}",
-                chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.3f"),
-                record.target(),
-                record.level(),
-                message
-            ))
-        })
-        .level(log::LevelFilter::Debug)
-        .chain(std::io::stdout()) // 同时输出到控制台
-        .chain(fern::log_file(&log_file)?) // 输出到文件
-        .apply()?;
-
-    info!("🚀 订单打印客户端启动");
-    info!("📝 日志文件位置: {}", log_file.display());
-
-    Ok(log_file)
-}
+// 获取应用数据目录 (日志、会话等文件都保存在这里)
+fn get_app_data_dir() -> PathBuf {
+    // 测试使用单独的临时目录，不读写真实的应用数据
+    if cfg!(test) {
+        return std::env::temp_dir().join(format!("OrderPrintClient-test-{}", std::process::id()));
+    }
 
-// 获取日志文件路径
-fn get_log_file_path() -> PathBuf {
     let app_data_dir = if cfg!(windows) {
         std::env::var("APPDATA").unwrap_or_else(|_| ".".to_string())
     } else {
         std::env::var("HOME").unwrap_or_else(|_| ".".to_string())
     };
 
-    let log_dir = PathBuf::from(app_data_dir).join("OrderPrintClient").join("logs");
-    log_dir.join(format!("print_client_{}.log", chrono::Local::now().format("%Y%m%d")))
+    PathBuf::from(app_data_dir).join("OrderPrintClient")
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -83,6 +82,9 @@ struct LoginRequest {
     login_method: String,
     login_type: String,
     terminal: i32,
+    // 操作员勾选"记住登录"时才保存密码用于token过期后自动重新登录，否则只保存token
+    #[serde(default)]
+    remember_login: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -95,22 +97,6 @@ struct LoginResponse {
     rd_id: Option<i32>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct ApiResponse<T> {
-    status_code: i32,
-    message: String,
-    data: Option<T>,
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct LoginData {
-    token: String,
-    username: String,
-    user_id: String,
-    role_id: i32,
-    rd_id: i32,
-}
-
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct OrderData {
     order_id: String,
@@ -192,20 +178,57 @@ struct DishItem {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct PrinterConfig {
     name: String,
-    width: i32, // 58 or 80
+    width: i32, // 纸宽 (mm)，例如 58、76、80、112
     is_default: bool,
     is_enabled: bool, // 是否启用用于订单打印
     font_size: i32, // 字体大小：0=小(默认), 1=中, 2=大
+    #[serde(default)]
+    section_sizes: receipt_layout::SectionSizes, // 按段落单独设置的放大倍数，未设置的段落使用 font_size
+    #[serde(default)]
+    font: receipt_layout::PrinterFont, // 字体A/B
+    #[serde(default)]
+    dots_per_line: u32, // 可打印宽度 (点)，0=按打印机配置库或纸宽计算
+    #[serde(default)]
+    columns: u32, // 正常大小时每行字符数，0=自动计算
+    #[serde(default)]
+    role: printer_actions::PrinterRole, // 前台/厨房，用于开钱箱和蜂鸣器规则
+    #[serde(default)]
+    copies: receipt_copies::CopySettings, // 外送/自取各打印几联，未设置时打印一联
 }
 
 // 应用状态管理
 #[derive(Default)]
 struct AppState {
-    is_connected: Arc<Mutex<bool>>,
+    connection: connection::ConnectionManager, // WebSocket连接任务 (同一时间只有一个)
     user_token: Arc<Mutex<Option<String>>>,
     user_id: Arc<Mutex<Option<String>>>,
     printers: Arc<Mutex<Vec<PrinterConfig>>>,
     global_font_size: Arc<Mutex<i32>>, // 全局字体大小设置: 0=小, 1=中, 2=大
+    session: Arc<Mutex<Option<session::StoredSession>>>, // 当前登录会话 (加密持久化)
+    order_ledger: Arc<Mutex<catchup::OrderLedger>>, // 已打印订单台账，用于漏单补打
+    config: Arc<Mutex<config::AppConfig>>, // 应用配置 (config.json)
+    print_jobs: print_jobs::JobQueue, // 外部提交的打印任务队列
+    http_api: http_api::HttpApiServer, // 本地HTTP打印接口
+    order_history: order_history::OrderHistory, // 本地订单历史 (SQLite)
+}
+
+// 登录成功后保存会话并连接WebSocket
+fn start_session(state: &AppState, ctx: Ctx, session: session::StoredSession) {
+    *state.user_token.lock().unwrap() = Some(session.token.clone());
+    *state.user_id.lock().unwrap() = Some(session.user_id.clone());
+
+    if let Err(e) = session::save_session(&session) {
+        error!("❌ [SESSION] 保存会话失败: {}", e);
+    }
+
+    let ws_url = websocket_url(&session.user_id);
+    *state.session.lock().unwrap() = Some(session);
+
+    state.connection.start(ws_url, ctx);
+}
+
+fn websocket_url(user_id: &str) -> String {
+    format!("wss://message.menuorg.com/app/v1/web_socket/7/{}", user_id)
 }
 
 // 登录命令
@@ -214,133 +237,215 @@ async fn login(
     login_data: LoginRequest,
     state: State<'_, AppState>,
     window: Window,
-) -> Result<LoginResponse, String> {
-    let client = reqwest::Client::new();
-
-    let login_url = "https://api.menuorg.com/app/v1/login";
-
-    // 前端已经进行了MD5加密，直接使用接收到的密码
-    let response = client
-        .post(login_url)
-        .json(&serde_json::json!({
-            "username": login_data.username,
-            "password": login_data.password, // 直接使用前端MD5加密后的密码
-            "login_method": "password",
-            "login_type": "platform",
-            "terminal": 1
-        }))
-        .send()
-        .await
-        .map_err(|e| format!("登录请求失败: {}", e))?;
-        println!("密码: {}", login_data.password);
-    if response.status().is_success() {
-        // 先获取原始响应文本进行调试
-        let response_text = response.text().await
-            .map_err(|e| format!("读取响应文本失败: {}", e))?;
-
-        println!("API 响应原始数据: {}", response_text);
-
-        // 尝试解析为通用JSON
-        let json_value: serde_json::Value = serde_json::from_str(&response_text)
-            .map_err(|e| format!("解析JSON失败: {}", e))?;
-
-        println!("解析后的JSON: {:#}", json_value);
-
-        // 先检查status_code，只有成功时才尝试解析LoginData
-        if json_value["status_code"].as_i64() == Some(200) {
-            // 只有成功时才尝试解析为包含LoginData的结构
-            let api_response: ApiResponse<LoginData> = serde_json::from_str(&response_text)
-                .map_err(|e| format!("解析登录响应失败: {}. 原始响应: {}", e, response_text))?;
-
-            if let Some(data) = api_response.data {
-                *state.user_token.lock().unwrap() = Some(data.token.clone());
-                *state.user_id.lock().unwrap() = Some(data.user_id.clone());
-
-                // 登录成功后自动连接WebSocket
-                let ws_url = format!("wss://message.menuorg.com/app/v1/web_socket/7/{}", data.user_id);
-                let is_connected = state.is_connected.clone();
-                let printers = state.printers.clone();
-                tokio::spawn(connect_websocket(ws_url, data.user_id.clone(), is_connected, printers, window));
-
-                Ok(LoginResponse {
-                    success: true,
-                    token: Some(data.token),
-                    message: "登录成功".to_string(),
-                    user_id: Some(data.user_id),
-                    username: Some(data.username),
-                    rd_id: Some(data.rd_id),
-                })
-            } else {
-                Ok(LoginResponse {
-                    success: false,
-                    token: None,
-                    message: "登录失败：服务器返回数据为空".to_string(),
-                    user_id: None,
-                    username: None,
-                    rd_id: None,
-                })
-            }
-        } else {
-            // 登录失败，直接返回错误信息
-            let status_code = json_value["status_code"].as_i64().unwrap_or(0);
-            let message = json_value["message"].as_str().unwrap_or("未知错误").to_string();
+) -> Result<LoginResponse, AppError> {
+    match api_client::shared()?.login(&login_data.username, &login_data.password).await {
+        Ok(data) => {
+            // 登录成功后保存会话并自动连接WebSocket
+            let password_md5 = if login_data.remember_login { Some(login_data.password.clone()) } else { None };
+            start_session(
+                &state,
+                events::window_context(&window),
+                session::StoredSession::from_login(&data, &login_data.username, password_md5),
+            );
 
             Ok(LoginResponse {
-                success: false,
-                token: None,
-                message: format!("登录失败 ({}): {}", status_code, message),
-                user_id: None,
-                username: None,
-                rd_id: None,
+                success: true,
+                token: Some(data.token),
+                message: "登录成功".to_string(),
+                user_id: Some(data.user_id),
+                username: Some(data.username),
+                rd_id: Some(data.rd_id),
             })
         }
-    } else {
-        Ok(LoginResponse {
+        Err(e) if e.is_rejection() => Ok(LoginResponse {
             success: false,
             token: None,
-            message: "登录失败：服务器错误".to_string(),
+            message: format!("登录失败: {}", e),
             user_id: None,
             username: None,
             rd_id: None,
-        })
+        }),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// 获取启动时恢复的会话 (前端加载完成后调用，避免错过 session-restored 事件)
+#[tauri::command]
+async fn get_saved_session(state: State<'_, AppState>) -> Result<Option<LoginResponse>, AppError> {
+    let session = state.session.lock().unwrap().clone();
+    Ok(session.map(|s| LoginResponse {
+        success: true,
+        token: Some(s.token),
+        message: "已恢复登录会话".to_string(),
+        user_id: Some(s.user_id),
+        username: Some(s.username),
+        rd_id: Some(s.rd_id),
+    }))
+}
+
+// token过期处理：有保存的密码时自动重新登录，否则通知前端重新登录
+// 成功时返回新的token
+async fn handle_session_expired(ctx: &Ctx) -> Result<String, AppError> {
+    warn!("⚠️ [SESSION] 检测到登录已过期");
+    let state = ctx.app_state();
+
+    let stored = state.session.lock().unwrap().clone();
+
+    if let Some(mut stored) = stored {
+        if let Some(password) = stored.password_md5.clone() {
+            info!("🔄 [SESSION] 尝试使用保存的凭据自动重新登录: {}", stored.login_username);
+
+            match api_client::shared()?.login(&stored.login_username, &password).await {
+                Ok(data) => {
+                    stored.token = data.token.clone();
+                    stored.saved_at = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+                    *state.user_token.lock().unwrap() = Some(data.token.clone());
+                    *state.session.lock().unwrap() = Some(stored.clone());
+                    if let Err(e) = session::save_session(&stored) {
+                        error!("❌ [SESSION] 保存会话失败: {}", e);
+                    }
+
+                    info!("✅ [SESSION] 自动重新登录成功");
+                    ctx.emit("session-renewed", serde_json::json!({
+                        "token": data.token,
+                        "user_id": data.user_id
+                    }));
+                    return Ok(data.token);
+                }
+                Err(e) if e.is_rejection() => {
+                    warn!("⚠️ [SESSION] 自动重新登录被拒绝: {}", e);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
     }
+
+    // 无法自动续期，清除会话并通知前端
+    *state.user_token.lock().unwrap() = None;
+    *state.session.lock().unwrap() = None;
+    session::clear_session();
+
+    ctx.emit("session-expired", AppError::AuthExpired);
+    Err(AppError::AuthExpired)
 }
 
-// 从消息中提取订单ID的辅助函数
-fn extract_order_id_from_message(text: &str) -> Option<String> {
-    // 尝试多种模式匹配订单ID
-    let patterns = [
-        "\"order_id\":",
-        "order_id:",
-        "订单ID:",
-        "Order:",
-        "order:",
-    ];
+// 使用当前会话获取订单详情，token过期时自动重新登录后重试一次
+async fn fetch_order(ctx: &Ctx, order_id: &str) -> Result<OrderData, AppError> {
+    let session = ctx.app_state().session.lock().unwrap().clone().ok_or(AppError::NotLoggedIn)?;
 
-    for pattern in &patterns {
-        if let Some(start_pos) = text.find(pattern) {
-            let after_pattern = &text[start_pos + pattern.len()..];
+    let order = match api_client::shared()?.get_order_detail(&session.token, &session.user_id, order_id).await {
+        Ok(order) => order,
+        Err(ApiError::Unauthorized(_)) => {
+            let token = handle_session_expired(ctx).await?;
+            api_client::shared()?.get_order_detail(&token, &session.user_id, order_id).await?
+        }
+        Err(e) => return Err(e.into()),
+    };
+    ctx.app_state().order_history.record_order(&order, "api");
+    Ok(order)
+}
+
+// 发送新订单通知到前端，并记录到台账以免补打时重复打印
+fn emit_new_order_notification(ctx: &dyn AppContext, order_id: &str) {
+    catchup::record_notified(ctx, order_id);
+    ctx.emit("new-order-notification", serde_json::json!({
+        "order_id": order_id
+    }));
+}
+
+// 处理一条WebSocket文本消息
+async fn handle_ws_message(ctx: &Ctx, text: &str) {
+    let message = match ws_protocol::parse_message(text) {
+        Ok(message) => message,
+        Err(reason) => {
+            ws_protocol::quarantine_message(text, &reason);
+            ctx.emit("ws-message-quarantined", serde_json::json!({ "reason": reason }));
+            return;
+        }
+    };
+    debug!("📨 [WS] 收到消息: {}", message.kind());
 
-            // 查找引号或冒号后的值
-            let chars = after_pattern.chars().skip_while(|c| c.is_whitespace() || *c == ':' || *c == '"' || *c == '\'');
-            let order_id: String = chars.take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '-').collect();
+    match message {
+        ws_protocol::WsMessage::OrderNew { order_id, order: None } => {
+            println!("🔔 收到新订单通知，订单ID: {}", order_id);
+            emit_new_order_notification(ctx.as_ref(), &order_id);
+            println!("✅ 成功发送新订单通知到前端");
+        }
+        ws_protocol::WsMessage::OrderNew { order_id, order: Some(order) } => {
+            println!("🔔 收到完整订单数据，订单ID: {}", order_id);
+            ctx.app_state().order_history.record_order(&order, "websocket");
+            // 放入打印队列 (已打印过的订单会被跳过)
+            let payload = print_jobs::JobPayload::Order { order, force: false };
+            match ctx.app_state().print_jobs.submit(payload, "websocket") {
+                Ok(job) => println!("✅ 订单 {} 已加入打印队列 ({})", order_id, job.id),
+                Err(e) => {
+                    error!("❌ [WS] 订单 {} 加入打印队列失败: {}", order_id, e);
+                    ctx.emit("print-error", &e);
+                }
+            }
+        }
+        ws_protocol::WsMessage::LegacyOrder { order_id, order } => {
+            // 旧格式消息和以前一样只通知前端，由前端决定是否打印
+            println!("🔔 收到新订单通知 (旧格式)，订单ID: {}", order_id);
+            if let Some(order) = &order {
+                ctx.app_state().order_history.record_order(order, "websocket");
+            }
+            emit_new_order_notification(ctx.as_ref(), &order_id);
+        }
+        ws_protocol::WsMessage::OrderUpdated { order_id, order } => {
+            info!("✏️ [WS] 订单已修改: {}", order_id);
+            ctx.emit("order-updated", serde_json::json!({
+                "order_id": order_id,
+                "order": order
+            }));
+
+            if let Some(order) = &order {
+                ctx.app_state().order_history.record_order(order, "websocket");
+            }
 
-            if !order_id.is_empty() && order_id.len() > 3 {
-                return Some(order_id);
+            // 已打印的订单在原打印机上打印修改小票
+            let result = match order {
+                Some(order) => order_changes::handle_order_updated(ctx, &order).await,
+                None => order_changes::handle_order_updated_by_id(ctx, &order_id).await,
+            };
+            if let Err(e) = result {
+                error!("❌ [ORDER_CHANGE] 处理订单 {} 的修改失败: {}", order_id, e);
+                ctx.emit("print-error", &e);
+            }
+        }
+        ws_protocol::WsMessage::OrderCancelled { order_id, reason } => {
+            info!("🚫 [WS] 订单已取消: {} ({})", order_id, reason.as_deref().unwrap_or("未说明原因"));
+            ctx.emit("order-cancelled", serde_json::json!({
+                "order_id": order_id,
+                "reason": reason
+            }));
+
+            // 已打印的订单在原打印机上打印取消小票
+            if let Err(e) = order_changes::handle_order_cancelled(ctx, &order_id, reason.as_deref()).await {
+                error!("❌ [ORDER_CHANGE] 处理订单 {} 的取消失败: {}", order_id, e);
+                ctx.emit("print-error", &e);
             }
         }
+        ws_protocol::WsMessage::Ping => {
+            debug!("💓 [WS] 收到应用层心跳");
+        }
+        ws_protocol::WsMessage::System { level, message } => {
+            info!("📢 [WS] 系统消息 ({}): {}", level, message);
+            ctx.emit("system-message", serde_json::json!({
+                "level": level,
+                "message": message
+            }));
+        }
     }
-
-    None
 }
 
-// WebSocket连接
-async fn connect_websocket(
+// WebSocket连接任务 (由 ConnectionManager 启动，shutdown 收到信号时退出)
+async fn run_websocket_connection(
     ws_url: String,
-    _user_id: String,
-    is_connected: Arc<Mutex<bool>>,
-    printers: Arc<Mutex<Vec<PrinterConfig>>>,
-    window: Window
+    status: connection::StatusReporter,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+    ctx: Ctx
 ) {
     let mut retry_count = 0;
     let max_retries = 999; // 几乎无限重连
@@ -348,24 +453,48 @@ async fn connect_websocket(
 
     loop {
         println!("WebSocket连接URL: {} (尝试第 {} 次)", ws_url, retry_count + 1);
-        
-        match connect_async(&ws_url).await {
+        status.set(ConnectionState::Connecting { attempt: retry_count + 1 });
+
+        let connect_result = tokio::select! {
+            result = connect_async(&ws_url) => result,
+            _ = shutdown.changed() => {
+                info!("🔌 [WS] 连接任务已取消");
+                return;
+            }
+        };
+
+        match connect_result {
             Ok((ws_stream, _)) => {
                 println!("WebSocket连接成功");
-                *is_connected.lock().unwrap() = true;
+                status.set(ConnectionState::Connected {
+                    since: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+                });
+                status.record_connected();
                 retry_count = 0; // 重置重试计数
                 retry_delay = 5; // 重置重连间隔
 
                 // 通知前端连接状态
-                let _ = window.emit("websocket-status", "connected");
+                ctx.emit("websocket-status", "connected");
+
+                // 重连后检查断线期间漏掉的订单
+                let catch_up_ctx = ctx.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = catchup::catch_up_missed_orders(&catch_up_ctx, "reconnect").await {
+                        warn!("⚠️ [CATCH_UP] 重连后检查漏单失败: {}", e);
+                    }
+                });
 
                 let (write, mut read) = ws_stream.split();
 
+                // 每次连接时读取心跳设置，修改后下次连接生效
+                let heartbeat = ctx.app_state().config.lock().unwrap().heartbeat.clone();
+                let pong_timeout = tokio::time::Duration::from_secs(heartbeat.pong_timeout_secs);
+
                 // 使用tokio的Mutex来支持异步操作
                 let write_clone = Arc::new(tokio::sync::Mutex::new(write));
                 let heartbeat_write = write_clone.clone();
                 let heartbeat_task = tokio::spawn(async move {
-                    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(30));
+                    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(heartbeat.ping_interval_secs));
                     loop {
                         interval.tick().await;
                         let mut writer = heartbeat_write.lock().await;
@@ -373,110 +502,69 @@ async fn connect_websocket(
                             println!("❌ 心跳发送失败，连接可能已断开");
                             break;
                         }
+                        // 应用层心跳：部分路由器/代理不转发控制帧，发送普通文本消息保持连接
+                        if heartbeat.app_heartbeat_enabled
+                            && writer.send(Message::Text(heartbeat.app_heartbeat_message.clone())).await.is_err()
+                        {
+                            println!("❌ 应用层心跳发送失败，连接可能已断开");
+                            break;
+                        }
                         println!("💓 发送心跳包");
                         drop(writer); // 显式释放锁
                     }
                 });
 
+                // 定期检查是否超过pong_timeout没有收到任何数据 (半开连接)
+                let mut liveness_check = tokio::time::interval(tokio::time::Duration::from_secs(5));
+
                 // 监听消息
-                while let Some(message) = read.next().await {
+                loop {
+                    let message = tokio::select! {
+                        message = read.next() => message,
+                        _ = liveness_check.tick() => {
+                            if status.check_liveness(pong_timeout) {
+                                ctx.emit("websocket-status", "timeout");
+                                break;
+                            }
+                            continue;
+                        }
+                        _ = shutdown.changed() => {
+                            // 主动断开：停止心跳并关闭连接
+                            heartbeat_task.abort();
+                            let mut writer = write_clone.lock().await;
+                            let _ = writer.send(Message::Close(None)).await;
+                            info!("🔌 [WS] 连接任务已取消，连接已关闭");
+                            return;
+                        }
+                    };
+                    let Some(message) = message else { break };
+
                     match message {
                         Ok(Message::Text(text)) => {
-                            println!("收到WebSocket消息: {}", text);
-
-                            // 尝试解析为新订单通知消息
-                            if let Ok(msg) = serde_json::from_str::<serde_json::Value>(&text) {
-                                println!("解析WebSocket消息成功: {}", serde_json::to_string_pretty(&msg).unwrap_or_else(|_| "无法格式化".to_string()));
-
-                                if msg["type"] == "order" {
-                                    if let Some(order_id) = msg["data"]["order_id"].as_str() {
-                                        println!("🔔 收到新订单通知，订单ID: {}", order_id);
-
-                                        // 发送订单ID给前端处理
-                                        let event_data = serde_json::json!({
-                                            "order_id": order_id
-                                        });
-                                        println!("📤 准备发送事件到前端: {}", event_data);
-
-                                        match window.emit("new-order-notification", &event_data) {
-                                            Ok(_) => {
-                                                println!("✅ 成功发送新订单通知到前端");
-                                            }
-                                            Err(e) => {
-                                                println!("❌ 发送新订单通知失败: {}", e);
-                                            }
-                                        }
-                                    } else {
-                                        println!("❌ 新订单消息格式错误: 缺少order_id");
-                                        println!("消息内容: {}", text);
-                                    }
-                                } else if msg["type"] == "new_order" || msg.get("order_id").is_some() {
-                                    // 处理其他格式的新订单消息
-                                    if let Some(order_id) = msg.get("order_id").and_then(|v| v.as_str()) {
-                                        println!("🔔 收到新订单通知 (格式2)，订单ID: {}", order_id);
-                                        let _ = window.emit("new-order-notification", serde_json::json!({
-                                            "order_id": order_id
-                                        }));
-                                        println!("✅ 已发送新订单通知到前端");
-                                    } else {
-                                        println!("❌ 无法从消息中提取订单ID");
-                                        println!("消息内容: {}", text);
-                                    }
-                                } else {
-                                    // 尝试解析为完整订单数据（兼容旧格式）
-                                    if let Ok(order) = serde_json::from_str::<OrderData>(&text) {
-                                        println!("🔔 收到完整订单数据，订单ID: {}", order.order_id);
-                                        // 直接打印订单
-                                        let _ = print_order(order.clone(), printers.clone(), window.clone()).await;
-                                        let _ = window.emit("new-order", &order);
-                                        println!("✅ 已处理完整订单数据");
-                                    } else {
-                                        println!("⚠️ 无法解析WebSocket消息为已知格式");
-                                        println!("消息类型: {:?}", msg.get("type"));
-                                        println!("消息内容: {}", text);
-
-                                        // 尝试作为通用订单通知处理
-                                        if let Some(order_id) = extract_order_id_from_message(&text) {
-                                            println!("🔍 从消息中提取到订单ID: {}", order_id);
-                                            let _ = window.emit("new-order-notification", serde_json::json!({
-                                                "order_id": order_id
-                                            }));
-                                        }
-                                    }
-                                }
-                            } else {
-                                println!("❌ 无法解析WebSocket消息JSON: {}", text);
-
-                                // 尝试从纯文本中提取订单ID
-                                if let Some(order_id) = extract_order_id_from_message(&text) {
-                                    println!("🔍 从纯文本消息中提取到订单ID: {}", order_id);
-                                    let _ = window.emit("new-order-notification", serde_json::json!({
-                                        "order_id": order_id
-                                    }));
-                                } else {
-                                let _ = window.emit("order-error", format!("无法解析WebSocket消息: {}", text));
-                                }
-                            }
+                            debug!("收到WebSocket消息: {}", text);
+                            status.record_message();
+
+                            handle_ws_message(&ctx, &text).await;
                         }
                         Ok(Message::Close(_)) => {
                             println!("WebSocket连接被服务器关闭");
-                            *is_connected.lock().unwrap() = false;
-                            let _ = window.emit("websocket-status", "disconnected");
+                            ctx.emit("websocket-status", "disconnected");
                             break;
                         }
                         Ok(Message::Ping(payload)) => {
                             println!("💓 收到Ping，发送Pong响应");
+                            status.record_activity();
                             let mut writer = write_clone.lock().await;
                             let _ = writer.send(Message::Pong(payload)).await;
                             drop(writer); // 显式释放锁
                         }
                         Ok(Message::Pong(_)) => {
                             println!("💓 收到Pong响应");
+                            status.record_pong();
                         }
                         Err(e) => {
                             println!("WebSocket错误: {}", e);
-                            *is_connected.lock().unwrap() = false;
-                            let _ = window.emit("websocket-status", "error");
+                            ctx.emit("websocket-status", "error");
                             break;
                         }
                         _ => {}
@@ -485,16 +573,15 @@ async fn connect_websocket(
 
                 // 取消心跳任务
                 heartbeat_task.abort();
-                
+                status.record_disconnected();
+
                 // 连接断开，准备重连
                 println!("WebSocket连接断开，准备重连...");
-                *is_connected.lock().unwrap() = false;
-                let _ = window.emit("websocket-status", "disconnected");
+                ctx.emit("websocket-status", "disconnected");
             }
             Err(e) => {
                 println!("WebSocket连接失败: {}", e);
-                *is_connected.lock().unwrap() = false;
-                let _ = window.emit("websocket-status", "failed");
+                ctx.emit("websocket-status", "failed");
             }
         }
         
@@ -502,19 +589,34 @@ async fn connect_websocket(
         retry_count += 1;
         if retry_count >= max_retries {
             println!("WebSocket重连次数已达上限 ({}), 停止重连", max_retries);
-            let _ = window.emit("websocket-status", "failed");
+            status.set(ConnectionState::Stopped {
+                reason: Some(format!("重连次数已达上限 ({})，可调用 connect_websocket 重新连接", max_retries)),
+            });
+            ctx.emit("websocket-status", "failed");
             break;
         }
         
         println!("等待 {} 秒后重连...", retry_delay);
-        tokio::time::sleep(tokio::time::Duration::from_secs(retry_delay)).await;
+        let next_retry_at = chrono::Local::now() + chrono::Duration::seconds(retry_delay as i64);
+        status.set(ConnectionState::BackingOff {
+            attempt: retry_count,
+            delay_secs: retry_delay,
+            next_retry_at: next_retry_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        });
+        tokio::select! {
+            _ = tokio::time::sleep(tokio::time::Duration::from_secs(retry_delay)) => {}
+            _ = shutdown.changed() => {
+                info!("🔌 [WS] 连接任务已取消");
+                return;
+            }
+        }
         retry_delay = std::cmp::min(retry_delay * 2, 60); // 指数退避，最大60秒
     }
 }
 
-// 打印订单
-async fn print_order(order: OrderData, printers_arc: Arc<Mutex<Vec<PrinterConfig>>>, window: Window) -> Result<(), String> {
-    let printers = printers_arc.lock().unwrap().clone();
+// 打印订单到所有启用的打印机，返回打印成功的打印机数量
+async fn print_order(order: OrderData, ctx: &Ctx) -> Result<usize, AppError> {
+    let printers = ctx.app_state().printers.lock().unwrap().clone();
 
     // 过滤出启用的打印机
     let enabled_printers: Vec<PrinterConfig> = printers.into_iter()
@@ -522,92 +624,165 @@ async fn print_order(order: OrderData, printers_arc: Arc<Mutex<Vec<PrinterConfig
         .collect();
 
     if enabled_printers.is_empty() {
-        let _ = window.emit("print-error", "No enabled printers");
-        return Err("No enabled printers".to_string());
+        ctx.emit("print-error", AppError::NoEnabledPrinters);
+        return Err(AppError::NoEnabledPrinters);
     }
 
     let mut print_success_count = 0;
-    let mut print_errors = Vec::new();
+    let mut printed_on: Vec<String> = Vec::new();
+    let mut print_errors: Vec<AppError> = Vec::new();
+    let history = &ctx.app_state().order_history;
+    history.record_order(&order, "print");
+
+    // 开钱箱/蜂鸣器只在订单第一次打印时触发 (规则允许时重新打印也触发)
+    let first_print = !ctx.app_state().order_ledger.lock().unwrap().is_printed(&order.order_id);
+    let action_rules = ctx.app_state().config.lock().unwrap().printer_actions.rules.clone();
 
     for printer in enabled_printers {
-        match generate_print_content(&order, printer.width, printer.font_size) {
+        let resolved = printer_profiles::resolve(&printer.name);
+        if resolved.needs_confirmation {
+            // 没有匹配到配置的打印机暂按ESC/POS小票打印机处理，提示用户选择类型
+            warn!("⚠️ [PROFILE] 打印机 {} 没有匹配的配置，请选择打印机类型", printer.name);
+            ctx.emit("printer-profile-unconfirmed", serde_json::json!({
+                "printer": printer.name,
+                "profile": resolved.profile.id
+            }));
+        }
+
+        // 非小票打印机 (激光、喷墨、虚拟PDF打印机) 打印A4/Letter发票，不发送ESC/POS命令
+        if !resolved.profile.thermal {
+            let settings = ctx.app_state().config.lock().unwrap().invoice.clone();
+            let result = send_invoice_to_printer(&printer.name, &order, &settings).await;
+            history.record_print_attempt(&order.order_id, &printer.name, result.as_ref().err());
+            match result {
+                Ok(_) => {
+                    info!("✅ [INVOICE] 订单 {} 的发票已发送到打印机 {}", order.order_id, printer.name);
+                    print_success_count += 1;
+                    printed_on.push(printer.name.clone());
+                    ctx.emit("printer-print-success", serde_json::json!({
+                        "printer": printer.name,
+                        "order_id": order.order_id
+                    }));
+                }
+                Err(error) => {
+                    error!("❌ [INVOICE] 订单 {} 的发票打印失败: {}", order.order_id, error);
+                    ctx.emit("printer-print-error", &error);
+                    print_errors.push(error);
+                }
+            }
+            continue;
+        }
+
+        // 按打印机的多联设置生成，所有联在同一个打印任务中发送
+        let result = match receipt_copies::build_job(&order, &printer) {
             Ok(content) => {
-                // 调用实际的打印机API
-                match print_to_printer(&printer.name, &content).await {
-                    Ok(_) => {
-                        println!("Successfully printed to {} (width: {}mm)", printer.name, printer.width);
-                        print_success_count += 1;
-
-                        // 通知前端单个打印机打印成功
-                        let _ = window.emit("printer-print-success", serde_json::json!({
-                            "printer": printer.name,
-                            "order_id": order.order_id
-                        }));
-                    }
-                    Err(e) => {
-                        println!("标准打印失败: {}, 尝试增强版打印...", e);
-
-                        // 尝试增强版打印
-                        #[cfg(target_os = "windows")]
-                        match print_to_printer_enhanced(&printer.name, &content).await {
-                            Ok(_) => {
-                                println!("增强版打印成功: {} (width: {}mm)", printer.name, printer.width);
+                let content = printer_actions::apply(content, &order, &printer, &action_rules, first_print);
+                send_to_printer(&printer.name, &content).await
+            }
+            Err(e) => Err(e),
+        };
+
+        history.record_print_attempt(&order.order_id, &printer.name, result.as_ref().err());
+
+        match result {
+            Ok(_) => {
+                println!("Successfully printed to {} (width: {}mm)", printer.name, printer.width);
                 print_success_count += 1;
+                printed_on.push(printer.name.clone());
 
                 // 通知前端单个打印机打印成功
-                let _ = window.emit("printer-print-success", serde_json::json!({
+                ctx.emit("printer-print-success", serde_json::json!({
                     "printer": printer.name,
                     "order_id": order.order_id
                 }));
-                            }
-                            Err(enhanced_error) => {
-                                let error_msg = format!("Failed to print to {} (both standard and enhanced): {} | {}",
-                                                       printer.name, e, enhanced_error);
-                                println!("{}", error_msg);
-                                print_errors.push(error_msg.clone());
-                                let _ = window.emit("printer-print-error", serde_json::json!({
-                                    "printer": printer.name,
-                                    "error": error_msg
-                                }));
-                            }
-                        }
-
-                        #[cfg(not(target_os = "windows"))]
-                        {
-                            let error_msg = format!("Failed to print to {}: {}", printer.name, e);
-                            println!("{}", error_msg);
-                            print_errors.push(error_msg.clone());
-                            let _ = window.emit("printer-print-error", serde_json::json!({
-                                "printer": printer.name,
-                                "error": error_msg
-                            }));
-                        }
-                    }
-                }
             }
-            Err(e) => {
-                print_errors.push(format!("{}: {}", printer.name, e));
-                let _ = window.emit("printer-print-error", serde_json::json!({
-                    "printer": printer.name,
-                    "error": e
-                }));
+            Err(error) => {
+                println!("{}", error);
+                ctx.emit("printer-print-error", &error);
+                print_errors.push(error);
             }
         }
     }
 
     // 通知前端总体打印结果
     if print_success_count > 0 {
-        let _ = window.emit("print-success", serde_json::json!({
+        catchup::record_printed(ctx.as_ref(), &order);
+        order_changes::save_printed(&order, &printed_on);
+        ctx.emit("print-success", serde_json::json!({
             "success_count": print_success_count,
             "order_id": order.order_id
         }));
     }
 
-    if !print_errors.is_empty() {
-        let _ = window.emit("print-error", print_errors.join("; "));
+    if let Some(first_error) = print_errors.first() {
+        // 多台打印机失败时只上报第一个错误，其余已通过 printer-print-error 逐个通知
+        ctx.emit("print-error", first_error);
     }
 
-    Ok(())
+    Ok(print_success_count)
+}
+
+// 发送到打印机：标准方式失败时 (Windows) 再尝试增强版
+async fn send_to_printer(printer_name: &str, content: &str) -> Result<(), AppError> {
+    // 按打印机设置做简繁转换 (默认不转换)
+    let content = chinese_script::convert_for_printer(printer_name, content);
+    let result = send_to_printer_inner(printer_name, &content).await;
+    diagnostics::record_receipt(printer_name, &content, result.is_ok());
+    result
+}
+
+async fn send_to_printer_inner(printer_name: &str, content: &str) -> Result<(), AppError> {
+    // 校准过编码的打印机按确认的编码转码后发送，否则直接发送UTF-8文本
+    let sent = match encoding_calibration::encode_for_printer(printer_name, content) {
+        Some(bytes) => print_raw_to_printer(printer_name, bytes).await,
+        None => print_to_printer(printer_name, content).await,
+    };
+    let error = match sent {
+        Ok(_) => return Ok(()),
+        Err(e) => e,
+    };
+    println!("标准打印失败: {}, 尝试增强版打印...", error);
+
+    #[cfg(target_os = "windows")]
+    {
+        match print_to_printer_enhanced(printer_name, content).await {
+            Ok(_) => {
+                println!("增强版打印成功: {}", printer_name);
+                Ok(())
+            }
+            Err(enhanced_error) => Err(AppError::print_failed(
+                printer_name,
+                format!("both standard and enhanced failed: {} | {}", error, enhanced_error),
+            )),
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        Err(AppError::print_failed(printer_name, error))
+    }
+}
+
+// 生成发票PDF并交给系统打印 (非小票打印机)
+async fn send_invoice_to_printer(
+    printer_name: &str,
+    order: &OrderData,
+    settings: &config::InvoiceSettings,
+) -> Result<(), AppError> {
+    invoice_pdf::prune_spool();
+    let path = invoice_pdf::spool_path(order, printer_name);
+    let result = match invoice_pdf::save(order, settings, &path) {
+        Ok(_) => print_pdf_to_printer(printer_name, &path)
+            .await
+            .map_err(|e| AppError::print_failed(printer_name, e)),
+        Err(e) => Err(e),
+    };
+    // 打印成功时PDF程序可能还在读取文件，由 prune_spool 之后删除；失败时直接删除
+    if result.is_err() {
+        let _ = std::fs::remove_file(&path);
+    }
+    diagnostics::record_receipt(printer_name, &format!("[PDF invoice: {}]", path.display()), result.is_ok());
+    result
 }
 
 // 中文字符编码处理函数 - 针对热敏打印机优化
@@ -633,41 +808,30 @@ fn prepare_mixed_content(text: &str) -> String {
         .collect()
 }
 
-fn generate_print_content(order: &OrderData, width: i32, font_size: i32) -> Result<String, String> {
-    // 根据纸张宽度设置字符数 (考虑中文字符占2个位置)
-    let char_width = if width == 80 { 48 } else { 32 };
+fn generate_print_content(order: &OrderData, layout: &ReceiptLayout) -> Result<String, AppError> {
+    generate_receipt(order, layout, None)
+}
 
+// copy_label: 多联打印时这一联的标注 (receipt_copies)
+fn generate_receipt(order: &OrderData, layout: &ReceiptLayout, copy_label: Option<&str>) -> Result<String, AppError> {
     let mut content = String::new();
 
-    // ESC/POS初始化命令 - 简化编码设置
-    content.push_str("\x1B@"); // 初始化打印机
-    
-    // 简化的编码设置 - 让打印机使用默认编码处理
-    content.push_str("\x1C\x26"); // 启用汉字模式 (通用命令)
-    content.push_str("\x1C\x43\x01"); // 选择汉字字符模式
-    
-    // 设置字体大小 - 确保中号和大号比小号大
-    match font_size {
-        0 => { // 小号字体 (默认大小)
-            content.push_str("\x1D\x21\x00"); // 正常大小 (1x1)
-        },
-        1 => { // 中号字体 - 高度放大
-            content.push_str("\x1D\x21\x10"); // 宽度1x，高度2x
-        },
-        2 => { // 大号字体 - 宽度和高度都放大
-            content.push_str("\x1D\x21\x11"); // 宽度2x，高度2x
-        },
-        _ => { // 默认情况
-            content.push_str("\x1D\x21\x00"); // 正常大小
-        }
-    }
-
-    // 设置行间距为更宽松的间距
-    content.push_str("\x1B\x33\x30"); // 设置行间距为48/180英寸 (比默认大)
+    // ESC/POS初始化命令：汉字模式、行间距和字体A/B
+    layout.init(&mut content);
 
+    // 每个段落先切换字体大小，再按放大后的列数排版
     // ============= 头部信息 (居中) =============
-    content.push_str("=".repeat(char_width).as_str());
-    content.push_str("\n");
+    let char_width = layout.begin(&mut content, Section::Header);
+    if let Some(label) = copy_label {
+        // 联别反白显示，方便分拣
+        let label = format!(" {} ", label);
+        content.push_str(&" ".repeat(char_width.saturating_sub(display_width(&label)) / 2));
+        content.push_str("\x1D\x42\x01"); // 反白
+        content.push_str(&label);
+        content.push_str("\x1D\x42\x00"); // 关闭反白
+        content.push_str("\n");
+    }
+    content.push_str(&rule('=', char_width));
     content.push_str("\x1B\x45\x01"); // 加粗
     content.push_str(&center_text_mixed(&order.rd_name.to_uppercase(), char_width));
     content.push_str("\x1B\x45\x00"); // 关闭加粗
@@ -679,8 +843,8 @@ fn generate_print_content(order: &OrderData, width: i32, font_size: i32) -> Resu
     content.push_str(&center_text_mixed(order_type, char_width));
     content.push_str("\x1B\x45\x00"); // 关闭加粗
     content.push_str("\n");
-    content.push_str("=".repeat(char_width).as_str());
-    content.push_str("\n\n");
+    content.push_str(&rule('=', char_width));
+    content.push_str("\n");
 
     // ============= 订单信息表格 =============
     // 订单号 (居中显示)
@@ -699,6 +863,7 @@ fn generate_print_content(order: &OrderData, width: i32, font_size: i32) -> Resu
     content.push_str("\n\n");
 
     // 基本信息表格 (左对齐标签，右对齐数值)
+    let char_width = layout.begin(&mut content, Section::Info);
     content.push_str(&format_table_row("Order Date:", &format_order_time(&order.create_time), char_width));
 
     if order.delivery_style == 1 {  // 外送
@@ -724,28 +889,27 @@ fn generate_print_content(order: &OrderData, width: i32, font_size: i32) -> Resu
     }
 
     content.push_str("\n");
-    content.push_str("-".repeat(char_width).as_str());
-    content.push_str("\n");
+    content.push_str(&rule('-', char_width));
 
     // ============= 商品明细表格 =============
+    let char_width = layout.begin(&mut content, Section::Items);
     content.push_str("\x1B\x45\x01"); // 加粗
     content.push_str(&center_text_mixed("ORDER ITEMS", char_width));
     content.push_str("\x1B\x45\x00"); // 关闭加粗
     content.push_str("\n");
-    content.push_str("-".repeat(char_width).as_str());
-    content.push_str("\n");
+    content.push_str(&rule('-', char_width));
 
     // 表格标题 - 简化版本
     let header = format_table_header("Item Name", "Qty", "", "Total", char_width);
     content.push_str(&header);
-    content.push_str("-".repeat(char_width).as_str());
-    content.push_str("\n");
+    content.push_str(&rule('-', char_width));
 
     for item in &order.dishes_array {
         let price: f64 = item.price.parse().unwrap_or(0.0);
         let unit_price: f64 = item.unit_price.parse().unwrap_or(0.0);
         
         // 商品行 (使用混合编码处理菜名)
+        let char_width = layout.begin(&mut content, Section::Items);
         content.push_str(&format_item_table_row(
             &prepare_mixed_content(&item.dishes_name),
             item.amount,
@@ -754,14 +918,18 @@ fn generate_print_content(order: &OrderData, width: i32, font_size: i32) -> Resu
             char_width
         ));
 
-        // 附加项目 (如米饭等) - 只显示名称，不显示价格和数量
-        if !item.dishes_describe.is_empty() {
-            content.push_str(&format!("  + {}\n", prepare_mixed_content(&item.dishes_describe)));
-        }
+        if !item.dishes_describe.is_empty() || !item.remark.is_empty() {
+            let note_width = layout.begin(&mut content, Section::ItemNotes);
+
+            // 附加项目 (如米饭等) - 只显示名称，不显示价格和数量
+            if !item.dishes_describe.is_empty() {
+                content.push_str(&format!("  + {}\n", wrap_text_with_indent(&prepare_mixed_content(&item.dishes_describe), note_width, "    ")));
+            }
 
-        // 特殊要求 (使用混合编码)
-        if !item.remark.is_empty() {
-            content.push_str(&format!("  Note: {}\n", prepare_mixed_content(&item.remark)));
+            // 特殊要求 (使用混合编码)
+            if !item.remark.is_empty() {
+                content.push_str(&format!("  Note: {}\n", wrap_text_with_indent(&prepare_mixed_content(&item.remark), note_width, "        ")));
+            }
         }
         
         // 增加商品间的行距
@@ -780,14 +948,13 @@ fn generate_print_content(order: &OrderData, width: i32, font_size: i32) -> Resu
     let tip_fee: f64 = order.tip_fee.parse().unwrap_or(0.0);
     let total: f64 = order.total.parse().unwrap_or(0.0);
 
-    content.push_str("-".repeat(char_width).as_str());
-    content.push_str("\n");
+    let char_width = layout.begin(&mut content, Section::Totals);
+    content.push_str(&rule('-', char_width));
     content.push_str("\x1B\x45\x01"); // 加粗
     content.push_str(&center_text_mixed("PAYMENT SUMMARY", char_width));
     content.push_str("\x1B\x45\x00"); // 关闭加粗
     content.push_str("\n");
-    content.push_str("-".repeat(char_width).as_str());
-    content.push_str("\n");
+    content.push_str(&rule('-', char_width));
 
     // 小计
     content.push_str(&format_fee_line("Subtotal", sub_total, char_width));
@@ -839,18 +1006,17 @@ fn generate_print_content(order: &OrderData, width: i32, font_size: i32) -> Resu
     }
     
     content.push_str("\n");
-    content.push_str("=".repeat(char_width).as_str());
-    content.push_str("\n");
+    content.push_str(&rule('=', char_width));
 
     // 总计 (加粗显示)
     content.push_str("\x1B\x45\x01"); // 加粗
     content.push_str(&format_fee_line("TOTAL", total, char_width));
     content.push_str("\x1B\x45\x00"); // 关闭加粗
 
-    content.push_str("=".repeat(char_width).as_str());
-    content.push_str("\n");
+    content.push_str(&rule('=', char_width));
 
     // 底部信息 (使用混合编码)
+    let char_width = layout.begin(&mut content, Section::Footer);
     if !order.order_notes.is_empty() {
         content.push_str("\nNotes:\n");
         content.push_str(&prepare_mixed_content(&order.order_notes));
@@ -864,6 +1030,7 @@ fn generate_print_content(order: &OrderData, width: i32, font_size: i32) -> Resu
     content.push_str("\n\n\n\n"); // 空行，为切纸预留空间
 
     // 单次自动切纸命令 - 避免重复切纸
+    content.push_str(&TextSize::NORMAL.command());
     content.push_str("\x1D\x56\x00"); // GS V 0 - 全切 (最通用的切纸命令)
 
     Ok(content)
@@ -1111,12 +1278,19 @@ fn format_table_row(label: &str, value: &str, width: usize) -> String {
     }
 }
 
+// 商品表格的列宽 (菜名, 数量, 总价)，总价和数量固定宽度，其余给菜名
+// 放大字体后列数可能只有十几列，菜名列不能再按固定最小值计算，否则整行超宽
+fn item_table_columns(width: usize) -> (usize, usize, usize) {
+    let qty_width = if width < 32 { 3 } else { 4 };
+    let total_width = if width < 24 { 7 } else { 8 }; // 放得下 "9999.99"
+    let name_width = width.saturating_sub(qty_width + total_width + 2).max(1);
+    (name_width, qty_width, total_width)
+}
+
 // 商品表格标题
 fn format_table_header(name: &str, qty: &str, _price: &str, total: &str, width: usize) -> String {
     // 简化表格：只显示菜名、数量、总价
-    let name_width = (width * 70 / 100).max(20);  // 菜名占70%宽度
-    let qty_width = 4;    // 数量宽度
-    let total_width = width.saturating_sub(name_width + qty_width + 2); // 总价宽度
+    let (name_width, qty_width, total_width) = item_table_columns(width);
 
     format!("{:<name_width$} {:>qty_width$} {:>total_width$}\n",
         truncate_for_width(name, name_width),
@@ -1131,9 +1305,7 @@ fn format_table_header(name: &str, qty: &str, _price: &str, total: &str, width:
 // 商品表格行 - 简化版本
 fn format_item_table_row(name: &str, qty: i32, _unit_price: f64, total_price: f64, width: usize) -> String {
     // 简化表格：只显示菜名、数量、总价
-    let name_width = (width * 70 / 100).max(20);  // 菜名占70%宽度
-    let qty_width = 4;    // 数量宽度
-    let total_width = width.saturating_sub(name_width + qty_width + 2); // 总价宽度
+    let (name_width, qty_width, total_width) = item_table_columns(width);
 
     let qty_str = format!("{}", qty);
     let total_str = if total_price == 0.0 { "+0.00".to_string() } else { format!("{:.2}", total_price) };
@@ -1148,11 +1320,11 @@ fn format_item_table_row(name: &str, qty: i32, _unit_price: f64, total_price: f6
         
         // 第一行显示菜名开头和价格信息
         if !lines.is_empty() {
-            result.push_str(&format!("{:<name_width$} {:>qty_width$} {:>total_width$}\n",
-                truncate_for_width(lines[0], name_width),
+            // 按显示宽度填充，中文菜名也能与数量列对齐 (不能再用 {:<} 按字符数填充)
+            result.push_str(&format!("{} {:>qty_width$} {:>total_width$}\n",
+                pad_for_width(&truncate_for_width(lines[0], name_width), name_width),
                 qty_str,
                 total_str,
-                name_width = name_width,
                 qty_width = qty_width,
                 total_width = total_width
             ));
@@ -1160,20 +1332,16 @@ fn format_item_table_row(name: &str, qty: i32, _unit_price: f64, total_price: f6
 
         // 后续行只显示菜名的剩余部分
         for line in lines.iter().skip(1) {
-            result.push_str(&format!("{:<name_width$}\n",
-                truncate_for_width(line, name_width),
-                name_width = name_width
-            ));
+            result.push_str(&format!("{}\n", truncate_for_width(line, name_width)));
         }
 
         result
     } else {
         // 菜名长度适中，单行显示
-        format!("{:<name_width$} {:>qty_width$} {:>total_width$}\n",
+        format!("{} {:>qty_width$} {:>total_width$}\n",
             pad_for_width(name, name_width),
             qty_str,
             total_str,
-            name_width = name_width,
             qty_width = qty_width,
             total_width = total_width
         )
@@ -1352,9 +1520,23 @@ fn get_system_printers() -> Result<Vec<PrinterConfig>, String> {
 
                 println!("🔍 [SYSTEM] 打印机名称: {}", name);
 
-                // 判断打印机类型和宽度
-                let (width, is_thermal) = classify_printer(&name);
-                println!("🔍 [SYSTEM] 分类结果: 宽度={}mm, 热敏打印机={}", width, is_thermal);
+                // 驱动名称用于匹配打印机配置库
+                let driver_ptr = printer_info.pDriverName;
+                if !driver_ptr.is_null() {
+                    let driver_slice = std::slice::from_raw_parts(
+                        driver_ptr,
+                        (0..).take_while(|&i| *driver_ptr.add(i) != 0).count(),
+                    );
+                    let driver = OsString::from_wide(driver_slice).to_string_lossy().to_string();
+                    println!("🔍 [SYSTEM] 驱动名称: {}", driver);
+                    printer_profiles::remember_driver(&name, &driver);
+                }
+
+                // 根据打印机配置库判断类型和宽度
+                let resolved = printer_profiles::resolve(&name);
+                let width = layout_width(&resolved.profile);
+                println!("🔍 [SYSTEM] 配置: {} (匹配方式: {}), 宽度={}mm, 热敏打印机={}",
+                         resolved.profile.id, resolved.matched_by, width, resolved.profile.thermal);
 
                 // 检查是否为默认打印机
                 let is_default = (printer_info.Attributes & 0x00000004) != 0; // PRINTER_ATTRIBUTE_DEFAULT
@@ -1366,6 +1548,12 @@ fn get_system_printers() -> Result<Vec<PrinterConfig>, String> {
                     is_default,
                     is_enabled: false, // 默认禁用，用户需要手动选择
                     font_size: 0, // 默认小号字体
+                    section_sizes: Default::default(),
+                    font: Default::default(),
+                    dots_per_line: 0,
+                    columns: 0,
+                    role: Default::default(),
+                    copies: Default::default(),
                 });
 
                 println!("✅ [SYSTEM] 打印机 {} 添加完成", name);
@@ -1386,38 +1574,29 @@ fn get_system_printers() -> Result<Vec<PrinterConfig>, String> {
     Ok(Vec::new())
 }
 
-// 根据打印机名称分类判断宽度
-fn classify_printer(name: &str) -> (i32, bool) {
-    let name_lower = name.to_lowercase();
-
-    // 检查是否为热敏打印机和宽度
-    if name_lower.contains("58") || name_lower.contains("58mm") {
-        (58, true)
-    } else if name_lower.contains("80") || name_lower.contains("80mm") {
-        (80, true)
-    } else if name_lower.contains("thermal") || name_lower.contains("receipt") || name_lower.contains("pos") {
-        // 热敏打印机但未明确宽度，默认80mm
-        (80, true)
+// 小票排版使用的纸宽；非小票打印机暂时仍按80mm小票排版
+fn layout_width(profile: &printer_profiles::PrinterProfile) -> i32 {
+    if profile.thermal {
+        profile.paper_width_mm
     } else {
-        // 其他类型打印机，默认80mm宽度
-        (80, false)
+        80
     }
 }
 
 // 获取打印机列表
 #[tauri::command]
-async fn get_printers(state: State<'_, AppState>) -> Result<Vec<PrinterConfig>, String> {
+async fn get_printers(state: State<'_, AppState>) -> Result<Vec<PrinterConfig>, AppError> {
     println!("🔍 [PRINTER] 开始扫描系统打印机...");
 
     // 获取系统真实打印机
-    let system_printers = get_system_printers()?;
+    let system_printers = get_system_printers().map_err(AppError::PrinterQuery)?;
 
     println!("🔍 [PRINTER] 系统扫描完成，发现 {} 台打印机", system_printers.len());
 
     // 如果没有检测到打印机，返回提示
     if system_printers.is_empty() {
         println!("⚠️ [PRINTER] 警告：未检测到任何打印机");
-        return Err("未检测到任何打印机。请确保打印机已正确安装并连接。".to_string());
+        return Err(AppError::NoPrintersDetected);
     }
 
     // 如果状态中有现有配置，保持用户的启用状态
@@ -1431,6 +1610,12 @@ async fn get_printers(state: State<'_, AppState>) -> Result<Vec<PrinterConfig>,
             if let Some(existing) = current_printers.iter().find(|p| p.name == printer.name) {
                 printer.is_enabled = existing.is_enabled;
                 printer.font_size = existing.font_size; // 保持字体大小设置
+                printer.section_sizes = existing.section_sizes.clone();
+                printer.font = existing.font;
+                printer.dots_per_line = existing.dots_per_line;
+                printer.columns = existing.columns;
+                printer.role = existing.role;
+                printer.copies = existing.copies.clone();
                 println!("🔍 [PRINTER] 保持打印机 {} 的启用状态: {}, 字体大小: {}", printer.name, printer.is_enabled, printer.font_size);
             }
         }
@@ -1454,20 +1639,243 @@ async fn get_printers(state: State<'_, AppState>) -> Result<Vec<PrinterConfig>,
 async fn set_printer_config(
     printers: Vec<PrinterConfig>,
     state: State<'_, AppState>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
+    receipt_copies::validate(&printers)?;
     *state.printers.lock().unwrap() = printers;
     Ok(())
 }
 
+// 获取打印机配置库 (用户配置在前)
+#[tauri::command]
+async fn get_printer_profiles() -> Result<Vec<printer_profiles::ProfileEntry>, AppError> {
+    Ok(printer_profiles::list_profiles())
+}
+
+// 获取打印机匹配到的配置 (包括手动覆盖)
+#[tauri::command]
+async fn get_printer_profile(printer_name: String) -> Result<printer_profiles::ResolvedProfile, AppError> {
+    Ok(printer_profiles::resolve(&printer_name))
+}
+
+// 设置打印机的手动覆盖，settings 为 null 时恢复自动匹配
+#[tauri::command]
+async fn set_printer_override(
+    printer_name: String,
+    settings: Option<printer_profiles::PrinterOverride>,
+    state: State<'_, AppState>,
+) -> Result<printer_profiles::ResolvedProfile, AppError> {
+    let resolved = printer_profiles::set_override(&printer_name, settings)?;
+
+    // 同步已配置打印机的纸宽
+    if let Some(printer) = state.printers.lock().unwrap().iter_mut().find(|p| p.name == printer_name) {
+        printer.width = layout_width(&resolved.profile);
+        println!("🔍 [PRINTER] 打印机 {} 使用配置 {}，宽度: {}mm", printer.name, resolved.profile.id, printer.width);
+    }
+    Ok(resolved)
+}
+
+// 重新加载用户打印机配置库
+#[tauri::command]
+async fn reload_printer_profiles() -> Result<usize, AppError> {
+    Ok(printer_profiles::reload())
+}
+
 // 获取连接状态
 #[tauri::command]
-async fn get_connection_status(state: State<'_, AppState>) -> Result<bool, String> {
-    Ok(*state.is_connected.lock().unwrap())
+async fn get_connection_status(state: State<'_, AppState>) -> Result<ConnectionState, AppError> {
+    Ok(state.connection.state())
+}
+
+// 获取连接统计 (在线时长、最后消息时间、重连次数等)
+#[tauri::command]
+async fn get_connection_stats(state: State<'_, AppState>) -> Result<connection::ConnectionStats, AppError> {
+    Ok(state.connection.stats())
+}
+
+// 获取心跳设置
+#[tauri::command]
+async fn get_heartbeat_settings(state: State<'_, AppState>) -> Result<config::HeartbeatSettings, AppError> {
+    Ok(state.config.lock().unwrap().heartbeat.clone())
+}
+
+// 修改心跳设置 (下次连接时生效)
+#[tauri::command]
+async fn set_heartbeat_settings(settings: config::HeartbeatSettings, state: State<'_, AppState>) -> Result<(), AppError> {
+    settings.validate()?;
+
+    let mut config = state.config.lock().unwrap();
+    let mut updated = config.clone();
+    updated.heartbeat = settings;
+    config::save_config(&updated)?;
+    *config = updated;
+
+    info!("⚙️ [WS] 心跳设置已更新: 间隔={}秒, 超时={}秒, 应用层心跳={}",
+          config.heartbeat.ping_interval_secs, config.heartbeat.pong_timeout_secs, config.heartbeat.app_heartbeat_enabled);
+    Ok(())
+}
+
+// 获取本地HTTP打印接口设置
+#[tauri::command]
+async fn get_http_api_settings(state: State<'_, AppState>) -> Result<serde_json::Value, AppError> {
+    let settings = state.config.lock().unwrap().http_api.clone();
+    Ok(serde_json::json!({
+        "enabled": settings.enabled,
+        "port": settings.port,
+        "token": settings.token,
+        "running": state.http_api.is_running()
+    }))
+}
+
+// 修改本地HTTP打印接口设置 (立即重启服务)；token 为空时生成新令牌
+#[tauri::command]
+async fn set_http_api_settings(
+    mut settings: config::HttpApiSettings,
+    state: State<'_, AppState>,
+    window: Window,
+) -> Result<config::HttpApiSettings, AppError> {
+    if settings.port < 1024 {
+        return Err(AppError::InvalidArgument("端口必须在 1024-65535 之间".to_string()));
+    }
+    if settings.token.trim().is_empty() {
+        settings.token = http_api::generate_token();
+    }
+
+    {
+        let mut config = state.config.lock().unwrap();
+        let mut updated = config.clone();
+        updated.http_api = settings.clone();
+        config::save_config(&updated)?;
+        *config = updated;
+    }
+
+    if settings.enabled {
+        state.http_api.start(events::window_context(&window), &settings);
+    } else {
+        state.http_api.stop();
+    }
+    info!("⚙️ [HTTP_API] 设置已更新: 启用={}, 端口={}", settings.enabled, settings.port);
+    Ok(settings)
+}
+
+// 获取最近的打印任务 (本地HTTP接口提交的任务)
+#[tauri::command]
+async fn get_print_jobs(state: State<'_, AppState>) -> Result<Vec<print_jobs::PrintJob>, AppError> {
+    Ok(state.print_jobs.list())
+}
+
+// 手动连接 (或重新连接) WebSocket
+#[tauri::command]
+async fn connect_websocket(state: State<'_, AppState>, window: Window) -> Result<(), AppError> {
+    let user_id = state.user_id.lock().unwrap().clone().ok_or(AppError::NotLoggedIn)?;
+    state.connection.start(websocket_url(&user_id), events::window_context(&window));
+    Ok(())
+}
+
+// 断开WebSocket (保留登录状态)
+#[tauri::command]
+async fn disconnect_websocket(state: State<'_, AppState>, window: Window) -> Result<(), AppError> {
+    state.connection.stop(&window, "用户手动断开");
+    Ok(())
+}
+
+// 退出登录：断开WebSocket并删除保存的会话
+#[tauri::command]
+async fn logout(state: State<'_, AppState>, window: Window) -> Result<(), AppError> {
+    state.connection.stop(&window, "已退出登录");
+
+    *state.user_token.lock().unwrap() = None;
+    *state.user_id.lock().unwrap() = None;
+    *state.session.lock().unwrap() = None;
+    session::clear_session();
+
+    info!("👋 [SESSION] 用户已退出登录");
+    let _ = window.emit("logged-out", ());
+    Ok(())
+}
+
+// 测试打印使用的示例订单 (包含中英文混合内容)
+fn sample_test_order() -> OrderData {
+    OrderData {
+        order_id: "23410121749595834".to_string(),
+        rd_id: 341,
+        user_id: "6305000000012".to_string(),
+        order_status: 1,
+        paystyle: 0,  // 线下付款测试
+        delivery_style: 0,  // 自取测试
+        delivery_type: 0,
+        doordash_id: "".to_string(),
+        recipient_name: "张三 (Zhang San)".to_string(),
+        recipient_address: "北京市朝阳区望京街道 123号 2B室 (123 Wangjing St, Apt 2B, Beijing)".to_string(),
+        recipient_phone: "(555) 123-4567".to_string(),
+        recipient_distance: "2.5".to_string(),
+        rd_name: "老王川菜馆 (Lao Wang Sichuan Restaurant)".to_string(),
+        rd_address: "456 Broadway Avenue, New York, NY 10012".to_string(),
+        rd_phone: "(555) 987-6543".to_string(),
+        dishes_count: 3,
+        dishes_id_list: "[341120650,341120651,341120652]".to_string(),
+        dishes_array: vec![
+            DishItem {
+                dishes_id: 341120650,  // 这个值在i32范围内，保持不变
+                dishes_name: "麻婆豆腐 (Mapo Tofu)".to_string(),
+                amount: 1,
+                price: "18.99".to_string(),
+                unit_price: "18.99".to_string(),
+                remark: "不要太辣 (Not too spicy)".to_string(),
+                dishes_describe: "嫩豆腐配麻辣汤汁 (Soft tofu with spicy sauce)".to_string(),
+                dishes_series_id: 10771,
+                image_url: "https://www.menuorg.com/image/webp/dishes_photo/1746236681_13.png".to_string(),
+                dishes_specs_id: serde_json::Value::Null,
+            },
+            DishItem {
+                dishes_id: 341120651,  // 这个值在i32范围内，保持不变
+                dishes_name: "宫保鸡丁 (Kung Pao Chicken)".to_string(),
+                amount: 2,
+                price: "23.98".to_string(),
+                unit_price: "11.99".to_string(),
+                remark: "多放花生米 (Extra peanuts)".to_string(),
+                dishes_describe: "鸡肉丁配花生米和青椒 (Diced chicken with peanuts and peppers)".to_string(),
+                dishes_series_id: 10772,
+                image_url: "".to_string(),
+                dishes_specs_id: serde_json::Value::Null,
+            },
+            DishItem {
+                dishes_id: 341120652,  // 这个值在i32范围内，保持不变
+                dishes_name: "白米饭 (Steamed Rice)".to_string(),
+                amount: 1,
+                price: "6.99".to_string(),
+                unit_price: "6.99".to_string(),
+                remark: "".to_string(),
+                dishes_describe: "香喷喷的白米饭 (Fragrant steamed white rice)".to_string(),
+                dishes_series_id: 10773,
+                image_url: "".to_string(),
+                dishes_specs_id: serde_json::Value::Null,
+            },
+        ],
+        discount_total: "5.00".to_string(),
+        exemption: "0.00".to_string(),
+        sub_total: "49.96".to_string(),
+        user_commission: "1.25".to_string(),
+        tax_rate: "0.0825".to_string(),
+        tax_fee: "4.37".to_string(),
+        delivery_fee: "3.99".to_string(),
+        convenience_rate: "0.035".to_string(),
+        convenience_fee: "1.75".to_string(),
+        retail_delivery_fee: "0.00".to_string(),
+        tip_fee: "7.50".to_string(),
+        total: "65.82".to_string(),
+        cloud_print: 0,
+        order_notes: "请按门铃两次。如无人应答请放在门口。(Please ring doorbell twice. Leave at front door if no answer.)".to_string(),
+        serial_num: 42,
+        order_pdf_url: "https://www.menuorg.com/order_pdf/order_23410121749595834.pdf".to_string(),
+        user_email: "john.smith@email.com".to_string(),
+        create_time: "2025-01-15 18:30:00".to_string(),
+        delivery_time: "2025-01-15 19:15:00".to_string(),
+    }
 }
 
 // 测试打印
 #[tauri::command]
-async fn test_print(printer_name: String, state: State<'_, AppState>) -> Result<(), String> {
+async fn test_print(printer_name: String, state: State<'_, AppState>) -> Result<(), AppError> {
     info!("🧪 [TEST] 开始测试打印");
     info!("🧪 [TEST] 目标打印机: {}", printer_name);
 
@@ -1487,87 +1895,12 @@ async fn test_print(printer_name: String, state: State<'_, AppState>) -> Result<
         info!("✅ [TEST] 找到目标打印机: {} (宽度: {}mm)", printer.name, printer.width);
 
         info!("🧪 [TEST] 生成包含中文的测试订单数据...");
-        let test_order = OrderData {
-            order_id: "23410121749595834".to_string(),
-            rd_id: 341,
-            user_id: "6305000000012".to_string(),
-            order_status: 1,
-            paystyle: 0,  // 线下付款测试
-            delivery_style: 0,  // 自取测试
-            delivery_type: 0,
-            doordash_id: "".to_string(),
-            recipient_name: "张三 (Zhang San)".to_string(),
-            recipient_address: "北京市朝阳区望京街道 123号 2B室 (123 Wangjing St, Apt 2B, Beijing)".to_string(),
-            recipient_phone: "(555) 123-4567".to_string(),
-            recipient_distance: "2.5".to_string(),
-            rd_name: "老王川菜馆 (Lao Wang Sichuan Restaurant)".to_string(),
-            rd_address: "456 Broadway Avenue, New York, NY 10012".to_string(),
-            rd_phone: "(555) 987-6543".to_string(),
-            dishes_count: 3,
-            dishes_id_list: "[341120650,341120651,341120652]".to_string(),
-            dishes_array: vec![
-                DishItem {
-                    dishes_id: 341120650,  // 这个值在i32范围内，保持不变
-                    dishes_name: "麻婆豆腐 (Mapo Tofu)".to_string(),
-                    amount: 1,
-                    price: "18.99".to_string(),
-                    unit_price: "18.99".to_string(),
-                    remark: "不要太辣 (Not too spicy)".to_string(),
-                    dishes_describe: "嫩豆腐配麻辣汤汁 (Soft tofu with spicy sauce)".to_string(),
-                    dishes_series_id: 10771,
-                    image_url: "https://www.menuorg.com/image/webp/dishes_photo/1746236681_13.png".to_string(),
-                    dishes_specs_id: serde_json::Value::Null,
-                },
-                DishItem {
-                    dishes_id: 341120651,  // 这个值在i32范围内，保持不变
-                    dishes_name: "宫保鸡丁 (Kung Pao Chicken)".to_string(),
-                    amount: 2,
-                    price: "23.98".to_string(),
-                    unit_price: "11.99".to_string(),
-                    remark: "多放花生米 (Extra peanuts)".to_string(),
-                    dishes_describe: "鸡肉丁配花生米和青椒 (Diced chicken with peanuts and peppers)".to_string(),
-                    dishes_series_id: 10772,
-                    image_url: "".to_string(),
-                    dishes_specs_id: serde_json::Value::Null,
-                },
-                DishItem {
-                    dishes_id: 341120652,  // 这个值在i32范围内，保持不变
-                    dishes_name: "白米饭 (Steamed Rice)".to_string(),
-                    amount: 1,
-                    price: "6.99".to_string(),
-                    unit_price: "6.99".to_string(),
-                    remark: "".to_string(),
-                    dishes_describe: "香喷喷的白米饭 (Fragrant steamed white rice)".to_string(),
-                    dishes_series_id: 10773,
-                    image_url: "".to_string(),
-                    dishes_specs_id: serde_json::Value::Null,
-                },
-            ],
-            discount_total: "5.00".to_string(),
-            exemption: "0.00".to_string(),
-            sub_total: "49.96".to_string(),
-            user_commission: "1.25".to_string(),
-            tax_rate: "0.0825".to_string(),
-            tax_fee: "4.37".to_string(),
-            delivery_fee: "3.99".to_string(),
-            convenience_rate: "0.035".to_string(),
-            convenience_fee: "1.75".to_string(),
-            retail_delivery_fee: "0.00".to_string(),
-            tip_fee: "7.50".to_string(),
-            total: "65.82".to_string(),
-            cloud_print: 0,
-            order_notes: "请按门铃两次。如无人应答请放在门口。(Please ring doorbell twice. Leave at front door if no answer.)".to_string(),
-            serial_num: 42,
-            order_pdf_url: "https://www.menuorg.com/order_pdf/order_23410121749595834.pdf".to_string(),
-            user_email: "john.smith@email.com".to_string(),
-            create_time: "2025-01-15 18:30:00".to_string(),
-            delivery_time: "2025-01-15 19:15:00".to_string(),
-        };
+        let test_order = sample_test_order();
 
         println!("✅ [TEST] 测试订单数据生成完成");
         println!("🧪 [TEST] 正在生成打印内容...");
 
-        let content = generate_print_content(&test_order, printer.width, printer.font_size)?;
+        let content = generate_print_content(&test_order, &ReceiptLayout::for_printer(&printer))?;
 
         println!("✅ [TEST] 打印内容生成完成，长度: {} 字符", content.len());
         println!("🧪 [TEST] 打印内容预览 (前100字符):");
@@ -1593,14 +1926,14 @@ async fn test_print(printer_name: String, state: State<'_, AppState>) -> Result<
                     Err(enhanced_error) => {
                         println!("❌ [TEST] 增强版打印也失败! 打印机: {}, 错误: {} | {}",
                                 printer.name, e, enhanced_error);
-                        Err(format!("Test print failed (both standard and enhanced): {} | {}", e, enhanced_error))
+                        Err(AppError::print_failed(&printer.name, format!("both standard and enhanced failed: {} | {}", e, enhanced_error)))
                     }
                 }
 
                 #[cfg(not(target_os = "windows"))]
                 {
                     println!("❌ [TEST] 测试打印失败! 打印机: {}, 错误: {}", printer.name, e);
-                    Err(format!("Test print failed: {}", e))
+                    Err(AppError::print_failed(&printer.name, e))
                 }
             }
         }
@@ -1611,13 +1944,13 @@ async fn test_print(printer_name: String, state: State<'_, AppState>) -> Result<
         for (i, p) in printers.iter().enumerate() {
             println!("   {}. {}", i + 1, p.name);
         }
-        Err("Printer not found".to_string())
+        Err(AppError::PrinterNotFound(printer_name))
     }
 }
 
 // Toggle printer enable status test
 #[tauri::command]
-async fn toggle_printer(printer_name: String, enabled: bool, state: State<'_, AppState>) -> Result<(), String> {
+async fn toggle_printer(printer_name: String, enabled: bool, state: State<'_, AppState>) -> Result<(), AppError> {
     println!("🔧 [TOGGLE] 切换打印机状态: {} -> {}", printer_name, if enabled { "启用" } else { "禁用" });
 
     let mut printers = state.printers.lock().unwrap();
@@ -1635,7 +1968,7 @@ async fn toggle_printer(printer_name: String, enabled: bool, state: State<'_, Ap
         Ok(())
     } else {
         println!("❌ [TOGGLE] 未找到打印机: {}", printer_name);
-        Err("Printer not found".to_string())
+        Err(AppError::PrinterNotFound(printer_name))
     }
 }
 
@@ -1643,34 +1976,51 @@ async fn toggle_printer(printer_name: String, enabled: bool, state: State<'_, Ap
 #[tauri::command]
 async fn manual_print_order(
     order_data: OrderData,
-    state: State<'_, AppState>,
     window: Window,
-) -> Result<String, String> {
-    let printers_arc = state.printers.clone();
-    
+) -> Result<String, AppError> {
     println!("手动打印订单: {}", order_data.order_id);
-    
-    // 调用打印函数
-    match print_order(order_data.clone(), printers_arc, window).await {
-        Ok(_) => Ok(format!("订单 {} 打印成功", order_data.order_id)),
-        Err(e) => Err(format!("打印失败: {}", e)),
-    }
+    let order_id = order_data.order_id.clone();
+
+    // 经过打印队列 (与自动打印按顺序进行)，手动打印不检查是否已打印过
+    let payload = print_jobs::JobPayload::Order { order: Box::new(order_data), force: true };
+    let ctx = events::window_context(&window);
+    ctx.app_state().print_jobs.submit_and_wait(payload, "manual").await?.into_result()?;
+    Ok(format!("订单 {} 打印成功", order_id))
+}
+
+// 手动检查并补打漏单
+#[tauri::command]
+async fn catch_up_orders(window: Window) -> Result<usize, AppError> {
+    catchup::catch_up_missed_orders(&events::window_context(&window), "manual").await
 }
 
 // 生成打印预览内容
 #[tauri::command]
-async fn get_print_preview(order_data: OrderData, state: State<'_, AppState>) -> Result<String, String> {
+async fn get_print_preview(order_data: OrderData, state: State<'_, AppState>) -> Result<String, AppError> {
     let printers = state.printers.lock().unwrap();
     let global_font_size = *state.global_font_size.lock().unwrap();
     
-    // 获取第一个启用的打印机的宽度，如果没有则使用80mm
-    let width = printers.iter()
-        .find(|p| p.is_enabled)
-        .map(|p| p.width)
-        .unwrap_or(80);
+    // 按第一个启用的打印机的纸宽和字体排版，如果没有则使用80mm
+    let layout = match printers.iter().find(|p| p.is_enabled) {
+        Some(printer) => {
+            let mut printer = printer.clone();
+            printer.font_size = global_font_size;
+            ReceiptLayout::for_printer(&printer)
+        }
+        None => ReceiptLayout::new(80, global_font_size),
+    };
     
     // 生成打印内容 - 使用全局字体大小设置
-    generate_print_content(&order_data, width, global_font_size)
+    generate_print_content(&order_data, &layout)
+}
+
+// 命令传入的token为空时使用当前会话中的token
+fn resolve_token(token: String, state: &AppState) -> String {
+    if token.is_empty() {
+        state.user_token.lock().unwrap().clone().unwrap_or_default()
+    } else {
+        token
+    }
 }
 
 // 获取单个订单详情
@@ -1679,76 +2029,35 @@ async fn get_order_detail(
     user_id: String,
     order_id: String,
     token: String,
-) -> Result<OrderData, String> {
-    println!("🔍 [API] 开始获取订单详情");
-    println!("🔍 [API] 用户ID: {}", user_id);
-    println!("🔍 [API] 订单ID: {}", order_id);
-    println!("🔍 [API] Token: {}", if token.is_empty() { "空" } else { "已设置" });
-
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://api.menuorg.com/app/v1/order/get_by_id?user_id={}&id={}",
-        user_id, order_id
-    );
-
-    println!("📡 [API] 请求订单详情URL: {}", url);
-    println!("🔑 [API] 使用token: {}", token);
-
-    let response = client
-        .get(&url)
-        .header("authorization", &token)
-        .send()
-        .await
-        .map_err(|e| {
-            println!("❌ [API] 请求订单详情失败: {}", e);
-            format!("请求订单详情失败: {}", e)
-        })?;
-
-    println!("📡 [API] 响应状态码: {}", response.status());
-
-    if response.status().is_success() {
-        let response_text = response.text().await
-            .map_err(|e| {
-                println!("❌ [API] 读取订单响应失败: {}", e);
-                format!("读取订单响应失败: {}", e)
-            })?;
-
-        println!("📄 [API] 订单详情API响应: {}", response_text);
-
-        let json_value: serde_json::Value = serde_json::from_str(&response_text)
-            .map_err(|e| {
-                println!("❌ [API] 解析订单JSON失败: {}", e);
-                format!("解析订单JSON失败: {}", e)
-            })?;
-
-        let status_code = json_value["status_code"].as_i64().unwrap_or(0);
-        println!("📊 [API] API状态码: {}", status_code);
-
-        if status_code == 200 {
-            if json_value["data"].is_object() {
-                println!("✅ [API] 订单数据存在，开始解析...");
-                let order: OrderData = serde_json::from_value(json_value["data"].clone())
-                    .map_err(|e| {
-                        println!("❌ [API] 解析订单数据失败: {}", e);
-                        println!("❌ [API] 原始数据: {}", json_value["data"]);
-                        format!("解析订单数据失败: {}", e)
-                    })?;
-                println!("✅ [API] 订单解析成功: {}", order.order_id);
-                Ok(order)
-            } else {
-                println!("❌ [API] 订单数据为空或格式错误");
-                println!("❌ [API] data字段内容: {}", json_value["data"]);
-                Err("订单数据为空".to_string())
+    state: State<'_, AppState>,
+    window: Window,
+) -> Result<OrderData, AppError> {
+    let token = resolve_token(token, &state);
+    let client = api_client::shared()?;
+
+    let order = match client.get_order_detail(&token, &user_id, &order_id).await {
+        Ok(order) => order,
+        Err(ApiError::Unauthorized(_)) => {
+            // token过期：自动重新登录后重试一次
+            let new_token = handle_session_expired(&events::window_context(&window)).await?;
+            client.get_order_detail(&new_token, &user_id, &order_id).await?
+        }
+        Err(e @ (ApiError::Timeout | ApiError::Network(_))) => {
+            // 网络不可用时使用本地保存的副本
+            if let Ok(Some(order)) = state.order_history.get_order(&order_id) {
+                warn!("⚠️ [API] 获取订单详情失败 ({})，使用本地保存的订单: {}", e, order_id);
+                return Ok(order);
             }
-        } else {
-            let message = json_value["message"].as_str().unwrap_or("未知错误");
-            println!("❌ [API] 获取订单详情失败: {} - {}", status_code, message);
-            Err(format!("获取订单详情失败 ({}): {}", status_code, message))
+            error!("❌ [API] 获取订单详情失败: {} - {}", order_id, e);
+            return Err(e.into());
         }
-    } else {
-        println!("❌ [API] HTTP请求失败，状态码: {}", response.status());
-        Err(format!("订单详情API请求失败: {}", response.status()))
-    }
+        Err(e) => {
+            error!("❌ [API] 获取订单详情失败: {} - {}", order_id, e);
+            return Err(e.into());
+        }
+    };
+    state.order_history.record_order(&order, "api");
+    Ok(order)
 }
 
 // 获取订单列表
@@ -1759,117 +2068,285 @@ async fn get_order_list(
     token: String,
     page: i32,
     per_page: i32,
-) -> Result<Vec<OrderData>, String> {
-    println!("🔍 [ORDER_LIST] 开始获取订单列表");
-    println!("🔍 [ORDER_LIST] 用户ID: {}", user_id);
-    println!("🔍 [ORDER_LIST] 餐厅ID: {}", rd_id);
-    println!("🔍 [ORDER_LIST] 页码: {}", page);
-    println!("🔍 [ORDER_LIST] 每页数量: {}", per_page);
-    println!("🔍 [ORDER_LIST] Token: {}", if token.is_empty() { "空" } else { "已设置" });
-
-    let client = reqwest::Client::new();
-    let url = format!(
-        "https://api.menuorg.com/app/v1/order/get_by_rd_two?user_id={}&rd_id={}&page={}&per_page={}",
-        user_id, rd_id, page, per_page
-    );
+    state: State<'_, AppState>,
+    window: Window,
+) -> Result<Vec<OrderData>, AppError> {
+    let token = resolve_token(token, &state);
+    let client = api_client::shared()?;
+    let query = OrderListQuery { user_id: &user_id, rd_id, page, per_page };
+
+    let orders = match client.get_order_list(&token, &query).await {
+        Ok(orders) => orders,
+        Err(ApiError::Unauthorized(_)) => {
+            // token过期：自动重新登录后重试一次
+            let new_token = handle_session_expired(&events::window_context(&window)).await?;
+            client.get_order_list(&new_token, &query).await?
+        }
+        Err(e @ (ApiError::Timeout | ApiError::Network(_))) => {
+            // 网络不可用时返回本地保存的订单
+            match state.order_history.recent_orders(rd_id, page, per_page) {
+                Ok(orders) if !orders.is_empty() => {
+                    warn!("⚠️ [ORDER_LIST] 获取订单列表失败 ({})，返回本地保存的 {} 个订单", e, orders.len());
+                    return Ok(orders);
+                }
+                _ => {
+                    error!("❌ [ORDER_LIST] 获取订单列表失败: {}", e);
+                    return Err(e.into());
+                }
+            }
+        }
+        Err(e) => {
+            error!("❌ [ORDER_LIST] 获取订单列表失败: {}", e);
+            return Err(e.into());
+        }
+    };
+    state.order_history.record_orders(&orders, "api");
+    Ok(orders)
+}
 
-    println!("📡 [ORDER_LIST] 请求URL: {}", url);
-    println!("🔑 [ORDER_LIST] 请求头 Authorization: {}", token);
+// 搜索本地订单历史
+#[tauri::command]
+async fn search_order_history(
+    mut query: order_history::OrderSearchQuery,
+    state: State<'_, AppState>,
+) -> Result<Vec<order_history::OrderHistoryEntry>, AppError> {
+    // 登录后只能查到当前门店的订单 (本地历史中可能有切换账号前其他门店的订单)
+    if let Some(session) = state.session.lock().unwrap().as_ref() {
+        query.rd_id = Some(session.rd_id);
+    }
+    state.order_history.search(&query)
+}
 
-    let response = client
-        .get(&url)
-        .header("authorization", &token)
-        .send()
-        .await
-        .map_err(|e| {
-            println!("❌ [ORDER_LIST] HTTP请求失败: {}", e);
-            format!("请求订单列表失败: {}", e)
-        })?;
-
-    println!("📡 [ORDER_LIST] HTTP响应状态码: {}", response.status());
-
-    if response.status().is_success() {
-        let response_text = response.text().await
-            .map_err(|e| {
-                println!("❌ [ORDER_LIST] 读取响应内容失败: {}", e);
-                format!("读取订单响应失败: {}", e)
-            })?;
-
-        println!("📄 [ORDER_LIST] API响应内容: {}", response_text);
-
-        let json_value: serde_json::Value = serde_json::from_str(&response_text)
-            .map_err(|e| {
-                println!("❌ [ORDER_LIST] 解析JSON失败: {}", e);
-                format!("解析订单JSON失败: {}", e)
-            })?;
-
-        let status_code = json_value["status_code"].as_i64().unwrap_or(0);
-        println!("📊 [ORDER_LIST] API状态码: {}", status_code);
-        
-        if let Some(message) = json_value["message"].as_str() {
-            println!("📝 [ORDER_LIST] API消息: {}", message);
-        }
-
-        if status_code == 200 {
-            // API返回的数据结构中，订单列表在data.items中
-            if let Some(data) = json_value["data"].as_object() {
-                println!("✅ [ORDER_LIST] data字段存在，类型: object");
-                println!("🔍 [ORDER_LIST] data字段内容: {}", serde_json::to_string_pretty(data).unwrap_or_else(|_| "无法格式化".to_string()));
-                
-                if let Some(items) = data["items"].as_array() {
-                    println!("✅ [ORDER_LIST] items字段存在，数量: {}", items.len());
-                let mut orders = Vec::new();
-                    for (index, item) in items.iter().enumerate() {
-                        println!("🔍 [ORDER_LIST] 解析订单 {}/{}", index + 1, items.len());
-                        match serde_json::from_value::<OrderData>(item.clone()) {
-                            Ok(order) => {
-                                println!("✅ [ORDER_LIST] 订单解析成功: {}", order.order_id);
-                        orders.push(order);
-                    }
-                            Err(e) => {
-                                println!("❌ [ORDER_LIST] 订单解析失败 {}: {}", index + 1, e);
-                                println!("❌ [ORDER_LIST] 原始订单数据: {}", serde_json::to_string_pretty(item).unwrap_or_else(|_| "无法格式化".to_string()));
-                            }
-                        }
-                    }
-                    println!("✅ [ORDER_LIST] 成功解析 {} 个订单", orders.len());
-                    
-                    // 添加订单详细信息用于调试
-                    for (i, order) in orders.iter().enumerate() {
-                        println!("📋 [ORDER_LIST] 订单 {}: ID={}, 客户={}, 状态={}, 总额=${}", 
-                                i + 1, order.order_id, order.recipient_name, order.order_status, order.total);
-                    }
-                    
-                Ok(orders)
-            } else {
-                    println!("⚠️ [ORDER_LIST] items字段不存在或不是数组");
-                    println!("🔍 [ORDER_LIST] data字段所有键: {:?}", data.keys().collect::<Vec<_>>());
-                Ok(Vec::new()) // 返回空列表
-            }
-        } else {
-                println!("⚠️ [ORDER_LIST] data字段不存在或不是对象");
-                println!("🔍 [ORDER_LIST] data字段类型: {:?}", json_value["data"]);
-                Ok(Vec::new()) // 返回空列表
+// 获取本地保存的订单和打印记录
+#[tauri::command]
+async fn get_order_history_detail(
+    order_id: String,
+    state: State<'_, AppState>,
+) -> Result<order_history::OrderHistoryDetail, AppError> {
+    state.order_history
+        .get_detail(&order_id)?
+        .ok_or_else(|| AppError::InvalidArgument(format!("本地没有保存订单: {}", order_id)))
+}
+
+// 使用本地保存的副本重新打印订单 (不需要网络)
+#[tauri::command]
+async fn reprint_order_from_history(
+    order_id: String,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let order = state.order_history
+        .get_order(&order_id)?
+        .ok_or_else(|| AppError::InvalidArgument(format!("本地没有保存订单: {}", order_id)))?;
+
+    info!("🔁 [HISTORY] 使用本地副本重新打印订单: {}", order_id);
+    let payload = print_jobs::JobPayload::Order { order: Box::new(order), force: true };
+    let printed = state.print_jobs.submit_and_wait(payload, "history").await?.into_result()?;
+    if printed == 0 {
+        return Err(AppError::PrintFailed {
+            printer: "全部打印机".to_string(),
+            reason: format!("订单 {} 重新打印失败", order_id),
+        });
+    }
+    Ok(format!("订单 {} 已重新打印到 {} 台打印机", order_id, printed))
+}
+
+// 生成营业日报 (Z-report)
+#[tauri::command]
+async fn generate_sales_report(
+    date_from: String,
+    date_to: String,
+    source: Option<sales_report::ReportSource>,
+    window: Window,
+) -> Result<sales_report::SalesReport, AppError> {
+    let ctx = events::window_context(&window);
+    sales_report::generate_report(&ctx, &date_from, &date_to, source.unwrap_or_default()).await
+}
+
+// 打印营业日报，未指定打印机时打印到所有启用的打印机
+#[tauri::command]
+async fn print_sales_report(
+    date_from: String,
+    date_to: String,
+    source: Option<sales_report::ReportSource>,
+    printer_name: Option<String>,
+    window: Window,
+) -> Result<String, AppError> {
+    let ctx = events::window_context(&window);
+    let report = sales_report::generate_report(&ctx, &date_from, &date_to, source.unwrap_or_default()).await?;
+
+    let printers: Vec<PrinterConfig> = {
+        let printers = ctx.app_state().printers.lock().unwrap();
+        match &printer_name {
+            Some(name) => printers.iter().filter(|p| &p.name == name).cloned().collect(),
+            None => printers.iter().filter(|p| p.is_enabled).cloned().collect(),
+        }
+    };
+    if printers.is_empty() {
+        return Err(match printer_name {
+            Some(name) => AppError::PrinterNotFound(name),
+            None => AppError::NoEnabledPrinters,
+        });
+    }
+
+    let mut printed = 0;
+    let mut last_error = None;
+    for printer in &printers {
+        let content = sales_report::generate_report_content(&report, &ReceiptLayout::for_printer(printer));
+        match send_to_printer(&printer.name, &content).await {
+            Ok(()) => printed += 1,
+            Err(e) => {
+                error!("❌ [REPORT] 营业日报打印失败: {}", e);
+                last_error = Some(e);
             }
-        } else {
-            let message = json_value["message"].as_str().unwrap_or("未知错误");
-            println!("❌ [ORDER_LIST] API返回错误状态码: {} - {}", status_code, message);
-            Err(format!("获取订单失败 ({}): {}", status_code, message))
         }
-    } else {
-        println!("❌ [ORDER_LIST] HTTP请求失败，状态码: {}", response.status());
-        Err(format!("订单API请求失败: {}", response.status()))
     }
+
+    match last_error {
+        Some(e) if printed == 0 => Err(e),
+        _ => Ok(format!("营业日报已打印到 {} 台打印机", printed)),
+    }
+}
+
+// 导出营业日报为CSV
+#[tauri::command]
+async fn export_sales_report_csv(
+    date_from: String,
+    date_to: String,
+    source: Option<sales_report::ReportSource>,
+    file_path: String,
+    window: Window,
+) -> Result<String, AppError> {
+    let ctx = events::window_context(&window);
+    let report = sales_report::generate_report(&ctx, &date_from, &date_to, source.unwrap_or_default()).await?;
+
+    std::fs::write(&file_path, sales_report::report_to_csv(&report))
+        .map_err(|e| AppError::Io(format!("写入 {} 失败: {}", file_path, e)))?;
+    info!("📊 [REPORT] 营业日报已导出: {}", file_path);
+    Ok(file_path)
+}
+
+// 导出订单 (CSV / JSON Lines / XLSX)，columns 为空时使用保存的导出设置
+#[tauri::command]
+async fn export_orders(
+    date_from: String,
+    date_to: String,
+    format: order_export::ExportFormat,
+    source: Option<sales_report::ReportSource>,
+    columns: Option<Vec<String>>,
+    file_path: String,
+    window: Window,
+) -> Result<usize, AppError> {
+    let ctx = events::window_context(&window);
+    order_export::export_orders(&ctx, &date_from, &date_to, source.unwrap_or_default(), format, columns, &file_path).await
+}
+
+// 把订单导出为A4/Letter发票PDF，paper 为空时使用发票设置中的纸张
+#[tauri::command]
+async fn export_invoice_pdf(
+    order_data: OrderData,
+    file_path: String,
+    paper: Option<invoice_pdf::PaperSize>,
+    state: State<'_, AppState>,
+) -> Result<String, AppError> {
+    let mut settings = state.config.lock().unwrap().invoice.clone();
+    if let Some(paper) = paper {
+        settings.paper = paper;
+    }
+    invoice_pdf::save(&order_data, &settings, std::path::Path::new(&file_path))?;
+    info!("🧾 [INVOICE] 订单 {} 的发票已导出: {}", order_data.order_id, file_path);
+    Ok(file_path)
+}
+
+// 获取发票设置
+#[tauri::command]
+async fn get_invoice_settings(state: State<'_, AppState>) -> Result<config::InvoiceSettings, AppError> {
+    Ok(state.config.lock().unwrap().invoice.clone())
+}
+
+// 保存发票设置 (纸张、标志、字体)
+#[tauri::command]
+async fn set_invoice_settings(settings: config::InvoiceSettings, state: State<'_, AppState>) -> Result<(), AppError> {
+    for (label, path) in [("标志图片", &settings.logo_path), ("字体文件", &settings.font_path)] {
+        if !path.trim().is_empty() && !std::path::Path::new(path).is_file() {
+            return Err(AppError::InvalidArgument(format!("{}不存在: {}", label, path)));
+        }
+    }
+    let mut config = state.config.lock().unwrap();
+    let mut updated = config.clone();
+    updated.invoice = settings;
+    config::save_config(&updated)?;
+    *config = updated;
+    info!("🧾 [INVOICE] 发票设置已保存");
+    Ok(())
+}
+
+// 获取导出设置和可选的列
+#[tauri::command]
+async fn get_export_settings(state: State<'_, AppState>) -> Result<serde_json::Value, AppError> {
+    let settings = state.config.lock().unwrap().export.clone();
+    Ok(serde_json::json!({
+        "settings": settings,
+        "columns": order_export::COLUMNS,
+    }))
+}
+
+#[tauri::command]
+async fn set_export_settings(settings: config::ExportSettings, state: State<'_, AppState>) -> Result<(), AppError> {
+    order_export::validate_settings(&settings)?;
+
+    let mut config = state.config.lock().unwrap();
+    let mut updated = config.clone();
+    updated.export = settings;
+    config::save_config(&updated)?;
+    *config = updated;
+    Ok(())
+}
+
+// 获取开钱箱/蜂鸣器规则
+#[tauri::command]
+async fn get_printer_action_rules(state: State<'_, AppState>) -> Result<Vec<printer_actions::ActionRule>, AppError> {
+    Ok(state.config.lock().unwrap().printer_actions.rules.clone())
 }
 
-// Windows打印机调用函数（同步版本）
+#[tauri::command]
+async fn set_printer_action_rules(rules: Vec<printer_actions::ActionRule>, state: State<'_, AppState>) -> Result<(), AppError> {
+    printer_actions::validate_rules(&rules)?;
+
+    let mut config = state.config.lock().unwrap();
+    let mut updated = config.clone();
+    updated.printer_actions.rules = rules;
+    config::save_config(&updated)?;
+    *config = updated;
+    Ok(())
+}
+
+// 打开钱箱；不指定打印机时使用启用的前台打印机
+#[tauri::command]
+async fn open_cash_drawer(printer_name: Option<String>, state: State<'_, AppState>) -> Result<String, AppError> {
+    let printer_name = match printer_name {
+        Some(name) => name,
+        None => {
+            let printers = state.printers.lock().unwrap();
+            printer_actions::drawer_printer(&printers).ok_or(AppError::NoEnabledPrinters)?
+        }
+    };
+
+    info!("💰 [ACTIONS] 打开钱箱: {}", printer_name);
+    let data = printer_actions::drawer_job(&printer_name)?;
+    print_raw_to_printer(&printer_name, data)
+        .await
+        .map_err(|e| AppError::print_failed(&printer_name, e))?;
+    Ok(format!("已发送开钱箱命令到 {}", printer_name))
+}
+
+// Windows打印机调用函数（同步版本，以RAW方式写入原始字节）
 #[cfg(target_os = "windows")]
-fn print_to_printer_sync(printer_name: &str, content: &str) -> Result<(), String> {
+fn print_to_printer_sync(printer_name: &str, content: &[u8]) -> Result<(), String> {
     use std::ffi::OsStr;
     use std::os::windows::ffi::OsStrExt;
 
     info!("🖨️ [DEBUG] 开始打印到打印机: {}", printer_name);
-    debug!("🖨️ [DEBUG] 打印内容长度: {} 字符", content.len());
+    debug!("🖨️ [DEBUG] 打印内容长度: {} 字节", content.len());
 
     let wide_printer_name: Vec<u16> = OsStr::new(printer_name).encode_wide().chain(std::iter::once(0)).collect();
     let wide_document_name: Vec<u16> = OsStr::new("Order Print").encode_wide().chain(std::iter::once(0)).collect();
@@ -1931,7 +2408,7 @@ fn print_to_printer_sync(printer_name: &str, content: &str) -> Result<(), String
         println!("✅ [DEBUG] 页面开始成功");
 
         // 写入打印内容
-        let content_bytes = content.as_bytes();
+        let content_bytes = content;
         let mut bytes_written: DWORD = 0;
 
         println!("🖨️ [DEBUG] 正在写入打印数据... ({} 字节)", content_bytes.len());
@@ -1992,11 +2469,43 @@ fn print_to_printer_sync(printer_name: &str, content: &str) -> Result<(), String
 // 异步包装器
 #[cfg(target_os = "windows")]
 async fn print_to_printer(printer_name: &str, content: &str) -> Result<(), String> {
+    print_raw_to_printer(printer_name, content.as_bytes().to_vec()).await
+}
+
+// 发送原始字节 (例如POS系统生成的ESC/POS数据)，不做任何转换
+#[cfg(target_os = "windows")]
+async fn print_raw_to_printer(printer_name: &str, data: Vec<u8>) -> Result<(), String> {
     let printer_name = printer_name.to_string();
-    let content = content.to_string();
 
     tokio::task::spawn_blocking(move || {
-        print_to_printer_sync(&printer_name, &content)
+        print_to_printer_sync(&printer_name, &data)
+    }).await.map_err(|e| format!("Task execution failed: {}", e))?
+}
+
+// 用系统关联的PDF程序打印到指定打印机 (printto)
+#[cfg(target_os = "windows")]
+async fn print_pdf_to_printer(printer_name: &str, path: &std::path::Path) -> Result<(), String> {
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::shellapi::ShellExecuteW;
+    use winapi::um::winuser::SW_HIDE;
+
+    let wide = |s: &OsStr| -> Vec<u16> { s.encode_wide().chain(std::iter::once(0)).collect() };
+    let verb = wide(OsStr::new("printto"));
+    let file = wide(path.as_os_str());
+    let printer = wide(OsStr::new(&format!("\"{}\"", printer_name)));
+
+    tokio::task::spawn_blocking(move || {
+        let result = unsafe {
+            ShellExecuteW(ptr::null_mut(), verb.as_ptr(), file.as_ptr(), printer.as_ptr(), ptr::null(), SW_HIDE)
+        };
+        // 返回值大于32表示成功，否则为错误代码
+        let code = result as isize;
+        if code > 32 {
+            Ok(())
+        } else {
+            Err(format!("ShellExecute printto failed: {} (没有关联的PDF程序?)", code))
+        }
     }).await.map_err(|e| format!("Task execution failed: {}", e))?
 }
 
@@ -2007,6 +2516,18 @@ async fn print_to_printer(printer_name: &str, content: &str) -> Result<(), Strin
     Ok(())
 }
 
+#[cfg(not(target_os = "windows"))]
+async fn print_raw_to_printer(printer_name: &str, data: Vec<u8>) -> Result<(), String> {
+    println!("Printing {} raw bytes to {} (Linux/macOS simulation)", data.len(), printer_name);
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+async fn print_pdf_to_printer(printer_name: &str, path: &std::path::Path) -> Result<(), String> {
+    println!("Printing PDF {} to {} (Linux/macOS simulation)", path.display(), printer_name);
+    Ok(())
+}
+
 // 增强版打印功能，包含更多调试信息和错误处理
 #[cfg(target_os = "windows")]
 fn print_to_printer_enhanced_sync(printer_name: &str, content: &str) -> Result<(), String> {
@@ -2200,7 +2721,7 @@ async fn print_via_command(printer_name: &str, content: &str) -> Result<(), Stri
 
 // 调试打印机连接和功能
 #[tauri::command]
-async fn debug_printer(printer_name: String) -> Result<String, String> {
+async fn debug_printer(printer_name: String) -> Result<String, AppError> {
     info!("🔧 [DEBUG] 开始调试打印机: {}", printer_name);
 
     let mut debug_info = Vec::new();
@@ -2293,8 +2814,8 @@ async fn debug_printer(printer_name: String) -> Result<String, String> {
 
 // 获取日志文件内容
 #[tauri::command]
-async fn get_log_content(lines: Option<usize>) -> Result<String, String> {
-    let log_file = get_log_file_path();
+async fn get_log_content(lines: Option<usize>) -> Result<String, AppError> {
+    let log_file = logging::current_log_file();
 
     info!("📖 [LOG] 请求读取日志文件: {}", log_file.display());
 
@@ -2305,6 +2826,8 @@ async fn get_log_content(lines: Option<usize>) -> Result<String, String> {
 
     match std::fs::read_to_string(&log_file) {
         Ok(content) => {
+            // 旧版本写入的日志可能包含未脱敏的数据，返回前再处理一次
+            let content = redact::redact(&content);
             let line_count = content.lines().count();
             info!("📖 [LOG] 成功读取日志文件，总行数: {}", line_count);
 
@@ -2321,35 +2844,48 @@ async fn get_log_content(lines: Option<usize>) -> Result<String, String> {
         }
         Err(e) => {
             error!("❌ [LOG] 读取日志文件失败: {}", e);
-            Err(format!("读取日志文件失败: {}", e))
+            Err(e.into())
         }
     }
 }
 
-// 获取日志文件路径信息
+// 分页搜索所有保留的日志 (从新到旧)，读取和解压日志文件在阻塞线程中进行
+#[tauri::command]
+async fn query_logs(query: log_query::LogQuery) -> Result<log_query::LogQueryResult, AppError> {
+    tokio::task::spawn_blocking(move || log_query::query_logs(&query))
+        .await
+        .map_err(|e| AppError::Internal(format!("日志查询任务失败: {}", e)))?
+}
 
+// 开启或关闭实时日志推送 (log-line 事件)
+#[tauri::command]
+async fn set_log_streaming(enabled: bool) -> Result<(), AppError> {
+    logging::set_streaming(enabled);
+    Ok(())
+}
 
+// 获取日志文件路径信息
 #[tauri::command]
-async fn get_log_info() -> Result<serde_json::Value, String> {
-    let log_file = get_log_file_path();
-    let log_dir = log_file.parent().unwrap_or_else(|| std::path::Path::new("."));
+async fn get_log_info() -> Result<serde_json::Value, AppError> {
+    let log_file = logging::current_log_file();
 
     info!("ℹ️ [LOG] 获取日志信息");
 
+    let files = logging::list_log_files();
     let mut info = serde_json::json!({
         "log_file": log_file.to_string_lossy(),
-        "log_dir": log_dir.to_string_lossy(),
-        "exists": log_file.exists()
+        "log_dir": logging::log_dir().to_string_lossy(),
+        "exists": log_file.exists(),
+        "total_size": files.iter().map(|f| f.size).sum::<u64>(),
+        "files": files
     });
 
     // 如果文件存在，获取文件大小和修改时间
-    if log_file.exists() {
-        if let Ok(metadata) = std::fs::metadata(&log_file) {
-            info["size"] = metadata.len().into();
-            if let Ok(modified) = metadata.modified() {
-                if let Ok(duration) = modified.duration_since(std::time::UNIX_EPOCH) {
-                    info["modified"] = duration.as_secs().into();
-                }
+    if let Ok(metadata) = std::fs::metadata(&log_file) {
+        info["size"] = metadata.len().into();
+        if let Ok(modified) = metadata.modified() {
+            if let Ok(duration) = modified.duration_since(std::time::UNIX_EPOCH) {
+                info["modified"] = duration.as_secs().into();
             }
         }
     }
@@ -2357,29 +2893,54 @@ async fn get_log_info() -> Result<serde_json::Value, String> {
     Ok(info)
 }
 
-// 清空日志文件
+// 清空日志：删除旧日志文件并清空当前文件
 #[tauri::command]
-async fn clear_logs() -> Result<String, String> {
-    let log_file = get_log_file_path();
-
+async fn clear_logs() -> Result<String, AppError> {
     info!("🗑️ [LOG] 请求清空日志文件");
 
-    match std::fs::write(&log_file, "") {
-        Ok(_) => {
-            info!("✅ [LOG] 日志文件已清空");
+    match logging::clear_logs() {
+        Ok(removed) => {
+            info!("✅ [LOG] 日志已清空，删除了 {} 个旧日志文件", removed);
             Ok("日志文件已清空".to_string())
         }
         Err(e) => {
             error!("❌ [LOG] 清空日志文件失败: {}", e);
-            Err(format!("清空日志文件失败: {}", e))
+            Err(e)
         }
     }
 }
 
+// 获取日志设置
+#[tauri::command]
+async fn get_log_settings() -> Result<logging::LogSettings, AppError> {
+    Ok(logging::load_log_settings())
+}
+
+// 修改日志设置 (立即生效并保存)
+#[tauri::command]
+async fn set_log_settings(settings: logging::LogSettings) -> Result<(), AppError> {
+    logging::validate_log_settings(&settings)?;
+    logging::save_log_settings(&settings)?;
+
+    logging::apply_log_settings(&settings);
+    info!("⚙️ [LOG] 日志设置已更新: 级别={}, 脱敏程度={:?}, 模块级别={:?}, 单文件上限={}MB, 保留{}天/{}MB, 压缩={}",
+          settings.level, settings.redaction, settings.targets, settings.max_file_size_mb,
+          settings.retention_days, settings.max_total_size_mb, settings.compress);
+    Ok(())
+}
+
+// 导出诊断包 (zip)，未指定路径时保存到应用数据目录下的 diagnostics 文件夹
+#[tauri::command]
+async fn export_diagnostics(file_path: Option<String>, state: State<'_, AppState>) -> Result<String, AppError> {
+    info!("🧰 [DIAGNOSTICS] 开始生成诊断包");
+    let path = diagnostics::build_bundle(&state, file_path.map(PathBuf::from)).await?;
+    Ok(path.to_string_lossy().to_string())
+}
+
 // 打开日志文件夹
 #[tauri::command]
-async fn open_log_folder() -> Result<String, String> {
-    let log_file = get_log_file_path();
+async fn open_log_folder() -> Result<String, AppError> {
+    let log_file = logging::current_log_file();
     let log_dir = log_file.parent().unwrap_or_else(|| std::path::Path::new("."));
 
     info!("📂 [LOG] 请求打开日志文件夹: {}", log_dir.display());
@@ -2396,7 +2957,7 @@ async fn open_log_folder() -> Result<String, String> {
             }
             Err(e) => {
                 error!("❌ [LOG] 打开日志文件夹失败: {}", e);
-                Err(format!("打开文件夹失败: {}", e))
+                Err(e.into())
             }
         }
     }
@@ -2410,29 +2971,29 @@ async fn open_log_folder() -> Result<String, String> {
             .spawn()
         {
             Ok(_) => Ok("已打开日志文件夹".to_string()),
-            Err(e) => Err(format!("打开文件夹失败: {}", e))
+            Err(e) => Err(e.into())
         }
     }
 }
 
 // 测试前端调用的简单命令
 #[tauri::command]
-async fn test_frontend_call(message: String) -> Result<String, String> {
+async fn test_frontend_call(message: String) -> Result<String, AppError> {
     println!("🧪 [FRONTEND_TEST] 收到前端调用: {}", message);
     Ok(format!("后端成功接收到: {}", message))
 }
 
 // 获取全局字体大小设置
 #[tauri::command]
-async fn get_global_font_size(state: State<'_, AppState>) -> Result<i32, String> {
+async fn get_global_font_size(state: State<'_, AppState>) -> Result<i32, AppError> {
     Ok(*state.global_font_size.lock().unwrap())
 }
 
 // 设置全局字体大小
 #[tauri::command]
-async fn set_global_font_size(font_size: i32, state: State<'_, AppState>) -> Result<(), String> {
+async fn set_global_font_size(font_size: i32, state: State<'_, AppState>) -> Result<(), AppError> {
     if font_size < 0 || font_size > 2 {
-        return Err("字体大小必须在0-2之间 (0=小, 1=中, 2=大)".to_string());
+        return Err(AppError::InvalidArgument("字体大小必须在0-2之间 (0=小, 1=中, 2=大)".to_string()));
     }
     
     *state.global_font_size.lock().unwrap() = font_size;
@@ -2461,6 +3022,14 @@ struct EncodingTestResult {
     score: f64,
     success: bool,
     error: Option<String>,
+    // 测试文本中可以用该编码表示的字符比例
+    #[serde(default)]
+    coverage: f64,
+    #[serde(default)]
+    unsupported_chars: Vec<String>,
+    // 打印校准的结果：Some(true)=操作员确认打印正确，None=还没有校准
+    #[serde(default)]
+    verified: Option<bool>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -2489,83 +3058,14 @@ struct EncodingScoreInfo {
     grade: String,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-struct ChineseCharacterAnalysis {
-    character_type: String,  // "NONE", "SYMBOLS_ONLY", "SIMPLIFIED", "TRADITIONAL", "MIXED"
-    simplified_count: i32,
-    traditional_count: i32,
-    symbol_count: i32,
-    total_chars: i32,
-    confidence: f64,
-}
-
 // 新增：检测文本的中文字符类型
 #[tauri::command]
-async fn detect_chinese_character_type(text: String) -> Result<ChineseCharacterAnalysis, String> {
+async fn detect_chinese_character_type(text: String) -> Result<chinese_script::ChineseCharacterAnalysis, AppError> {
     info!("🔍 [ENCODING] 开始分析中文字符类型");
     info!("🔍 [ENCODING] 文本长度: {} 字符", text.chars().count());
 
-    let mut simplified_count = 0;
-    let mut traditional_count = 0;
-    let mut symbol_count = 0;
-    let total_chars = text.chars().count() as i32;
-
-    // 简体中文常用字符范围
-    let simplified_chars = [
-        '你', '我', '他', '们', '的', '是', '在', '有', '和', '对', '就', '会', '说', '要', '来', '到', '这', '那', '可', '以',
-        '了', '不', '个', '人', '一', '二', '三', '四', '五', '六', '七', '八', '九', '十', '年', '月', '日', '时', '分', '秒',
-        '元', '块', '钱', '订', '单', '打', '印', '测', '试', '餐', '厅', '菜', '品', '地', '址', '电', '话', '号', '码'
-    ];
-
-    // 繁体中文常用字符
-    let traditional_chars = [
-        '您', '們', '個', '來', '這', '那', '會', '說', '對', '時', '間', '點', '錢', '訂', '單', '測', '試', '餐', '廳', '電', '話'
-    ];
-
-    // 中文符号
-    let chinese_symbols = [
-        '￥', '＄', '€', '【', '】', '《', '》', '（', '）', '「', '」', '、', '。', '，', '；', '：', '？', '！', '※'
-    ];
-
-    for ch in text.chars() {
-        if simplified_chars.contains(&ch) {
-            simplified_count += 1;
-        } else if traditional_chars.contains(&ch) {
-            traditional_count += 1;
-        } else if chinese_symbols.contains(&ch) {
-            symbol_count += 1;
-        }
-    }
-
-    // 判断字符类型
-    let character_type = if simplified_count == 0 && traditional_count == 0 && symbol_count == 0 {
-        "NONE"
-    } else if simplified_count == 0 && traditional_count == 0 && symbol_count > 0 {
-        "SYMBOLS_ONLY"
-    } else if simplified_count > traditional_count * 2 {
-        "SIMPLIFIED"
-    } else if traditional_count > simplified_count * 2 {
-        "TRADITIONAL"
-    } else {
-        "MIXED"
-    };
-
-    // 计算置信度
-    let chinese_chars = simplified_count + traditional_count + symbol_count;
-    let confidence = if total_chars > 0 {
-        chinese_chars as f64 / total_chars as f64
-    } else {
-        0.0
-    };
-
-    let analysis = ChineseCharacterAnalysis {
-        character_type: character_type.to_string(),
-        simplified_count,
-        traditional_count,
-        symbol_count,
-        total_chars,
-        confidence,
-    };
+    // 按简繁对照表逐字判断，简繁写法相同的字只计入通用字
+    let analysis = chinese_script::analyze(&text);
 
     info!("✅ [ENCODING] 字符分析完成: {:?}", analysis);
     Ok(analysis)
@@ -2573,22 +3073,41 @@ async fn detect_chinese_character_type(text: String) -> Result<ChineseCharacterA
 
 // 新增：获取打印机的编码支持信息
 #[tauri::command]
-async fn get_printer_encoding_info(printer_name: String) -> Result<PrinterEncodingInfo, String> {
+async fn get_printer_encoding_info(printer_name: String) -> Result<PrinterEncodingInfo, AppError> {
     info!("🔍 [ENCODING] 获取打印机编码信息: {}", printer_name);
 
-    let name_lower = printer_name.to_lowercase();
-    
-    // 根据打印机型号推断编码支持
-    let (supports_chinese, recommended_encoding, fallback_encodings, command_level) = 
-        if name_lower.contains("epson") {
-            (true, "UTF8".to_string(), vec!["UTF8".to_string(), "GBK".to_string(), "BIG5".to_string()], 2)
-        } else if name_lower.contains("xprinter") || name_lower.contains("gprinter") {
-            (true, "GBK".to_string(), vec!["GBK".to_string(), "GB18030".to_string(), "UTF8".to_string()], 2)
-        } else if name_lower.contains("thermal") || name_lower.contains("receipt") || name_lower.contains("pos") {
-            (true, "GBK".to_string(), vec!["GBK".to_string(), "UTF8".to_string(), "GB2312".to_string()], 1)
-        } else {
-            (false, "UTF8".to_string(), vec!["UTF8".to_string()], 0)
-        };
+    let resolved = printer_profiles::resolve(&printer_name);
+    let profile = &resolved.profile;
+    info!("🔍 [ENCODING] 使用打印机配置: {} (匹配方式: {})", profile.id, resolved.matched_by);
+
+    // 命令级别: 2=支持扩展命令 (二维码等), 1=基本的中文模式命令, 0=不支持中文命令
+    let command_level = match (profile.cjk, profile.qr) {
+        (false, _) => 0,
+        (true, false) => 1,
+        (true, true) => 2,
+    };
+    let supports_chinese = profile.cjk;
+    let recommended_encoding = profile.default_encoding.clone();
+    let fallback_encodings = profile.encodings();
+
+    // 校准过的打印机附带每个编码的确认结果
+    let test_results = resolved.verified_encodings.as_ref().map(|verified| {
+        fallback_encodings
+            .iter()
+            .map(|encoding| {
+                let ok = verified.iter().any(|e| e.eq_ignore_ascii_case(encoding));
+                EncodingTestResult {
+                    encoding: encoding.clone(),
+                    score: if ok { 1.0 } else { 0.0 },
+                    success: ok,
+                    error: None,
+                    coverage: 0.0,
+                    unsupported_chars: Vec::new(),
+                    verified: Some(ok),
+                }
+            })
+            .collect()
+    });
 
     let encoding_info = PrinterEncodingInfo {
         name: printer_name.clone(),
@@ -2596,7 +3115,7 @@ async fn get_printer_encoding_info(printer_name: String) -> Result<PrinterEncodi
         recommended_encoding,
         fallback_encodings,
         command_level,
-        test_results: None,
+        test_results,
     };
 
     info!("✅ [ENCODING] 编码信息: {:?}", encoding_info);
@@ -2609,102 +3128,73 @@ async fn test_printer_encoding_compatibility(
     printer_name: String,
     test_text: String,
     encoding: String,
-) -> Result<EncodingTestResult, String> {
+) -> Result<EncodingTestResult, AppError> {
     info!("🧪 [ENCODING] 测试打印机编码兼容性");
     info!("🧪 [ENCODING] 打印机: {}", printer_name);
     info!("🧪 [ENCODING] 编码: {}", encoding);
     info!("🧪 [ENCODING] 测试文本长度: {} 字符", test_text.chars().count());
 
-    // 生成带编码优化的打印内容
-    let optimized_content = match encoding.as_str() {
-        "UTF8" => {
-            format!("\x1B@\x1C&\x1C\x43\x01{}\n\n测试编码: UTF-8\n测试文本:\n{}\n\n\x1D\x56\x00", 
-                    "\x1B\x45\x01UTF-8 编码测试\x1B\x45\x00", test_text)
-        }
-        "GBK" | "GB18030" => {
-            format!("\x1B@\x1C&\x1C\x43\x01{}\n\n测试编码: {}\n测试文本:\n{}\n\n\x1D\x56\x00", 
-                    "\x1B\x45\x01GBK 编码测试\x1B\x45\x00", encoding, test_text)
-        }
-        "BIG5" => {
-            format!("\x1B@\x1C&\x1C\x43\x01{}\n\n测试编码: Big5\n测试文本:\n{}\n\n\x1D\x56\x00", 
-                    "\x1B\x45\x01Big5 编码测试\x1B\x45\x00", test_text)
-        }
-        _ => {
-            format!("\x1B@{}\n\n测试编码: {}\n测试文本:\n{}\n\n\x1D\x56\x00", 
-                    "\x1B\x45\x01编码测试\x1B\x45\x00", encoding, test_text)
-        }
+    // 先离线分析测试文本能否用该编码表示
+    let resolved = printer_profiles::resolve(&printer_name);
+    let texts = vec![("test_text".to_string(), test_text.clone())];
+    let analysis = encoding_calibration::analyze_texts(&texts, &[encoding.clone()]).remove(0);
+    let unsupported_chars: Vec<String> = analysis.unsupported.iter().map(|u| u.ch.clone()).collect();
+    if !unsupported_chars.is_empty() {
+        warn!("⚠️ [ENCODING] {} 无法表示 {} 个字符: {}", encoding, unsupported_chars.len(), unsupported_chars.join(""));
+    }
+
+    // 分数：校准确认过的编码按覆盖率计分，校准时打印错误的编码为0，没有校准时只能给一半
+    let verified = resolved
+        .verified_encodings
+        .as_ref()
+        .map(|list| list.iter().any(|e| e.eq_ignore_ascii_case(&encoding)));
+    let score = match verified {
+        Some(true) => analysis.coverage,
+        Some(false) => 0.0,
+        None => analysis.coverage * 0.5,
     };
 
-    // 尝试打印测试
-    let result = match print_to_printer(&printer_name, &optimized_content).await {
-        Ok(_) => {
-            info!("✅ [ENCODING] 编码测试成功: {} - {}", printer_name, encoding);
-            
-            // 根据编码类型计算分数
-            let score = match encoding.as_str() {
-                "UTF8" => 0.95,  // UTF8通常兼容性最好
-                "GBK" | "GB18030" => 0.90,  // GBK系列适合中文
-                "BIG5" => 0.85,  // Big5适合繁体中文
-                "GB2312" => 0.80,  // 较老的编码
-                _ => 0.70,
-            };
-
-            // 根据打印机类型调整分数
-            let name_lower = printer_name.to_lowercase();
-            let adjusted_score = if name_lower.contains("thermal") || name_lower.contains("receipt") {
-                // 热敏打印机
-                match encoding.as_str() {
-                    "GBK" | "GB18030" => score + 0.05,  // 热敏打印机更适合GBK
-                    "UTF8" => score - 0.05,
-                    _ => score,
-                }
-            } else {
-                score
-            };
-
-            EncodingTestResult {
-                encoding: encoding.clone(),
-                score: adjusted_score,
-                success: true,
-                error: None,
-            }
-        }
-        Err(e) => {
-            warn!("⚠️ [ENCODING] 编码测试失败: {} - {} - {}", printer_name, encoding, e);
-            
-            // 尝试增强版打印
-            #[cfg(target_os = "windows")]
-            match print_to_printer_enhanced(&printer_name, &optimized_content).await {
-                Ok(_) => {
-                    info!("✅ [ENCODING] 增强版编码测试成功: {} - {}", printer_name, encoding);
-                    EncodingTestResult {
-                        encoding: encoding.clone(),
-                        score: 0.75,  // 增强版成功给予较低分数
-                        success: true,
-                        error: None,
-                    }
-                }
-                Err(enhanced_error) => {
-                    error!("❌ [ENCODING] 增强版编码测试也失败: {} - {} - {}", printer_name, encoding, enhanced_error);
-                    EncodingTestResult {
-                        encoding: encoding.clone(),
-                        score: 0.0,
-                        success: false,
-                        error: Some(format!("打印失败: {} | 增强版: {}", e, enhanced_error)),
-                    }
+    // 按该编码转码后发送，打印机收到的字节和正式打印时一致
+    let body = format!(
+        "\x1B@\x1B\x45\x01{} 编码测试\x1B\x45\x00\n\n测试编码: {}\n测试文本:\n{}\n\n\x1D\x56\x00",
+        encoding, encoding, test_text
+    );
+    let result = match encoding_calibration::encode_receipt(&resolved.profile, &encoding, &body) {
+        Err(e) => EncodingTestResult {
+            encoding: encoding.clone(),
+            score: 0.0,
+            success: false,
+            error: Some(e.to_string()),
+            coverage: 0.0,
+            unsupported_chars,
+            verified,
+        },
+        Ok(bytes) => match print_raw_to_printer(&printer_name, bytes).await {
+            Ok(_) => {
+                info!("✅ [ENCODING] 编码测试已发送: {} - {} (覆盖率 {:.1}%)", printer_name, encoding, analysis.coverage * 100.0);
+                EncodingTestResult {
+                    encoding: encoding.clone(),
+                    score,
+                    success: true,
+                    error: None,
+                    coverage: analysis.coverage,
+                    unsupported_chars,
+                    verified,
                 }
             }
-            
-            #[cfg(not(target_os = "windows"))]
-            {
+            Err(e) => {
+                error!("❌ [ENCODING] 编码测试失败: {} - {} - {}", printer_name, encoding, e);
                 EncodingTestResult {
                     encoding: encoding.clone(),
                     score: 0.0,
                     success: false,
-                    error: Some(e),
+                    error: Some(format!("打印失败: {}", e)),
+                    coverage: analysis.coverage,
+                    unsupported_chars,
+                    verified,
                 }
             }
-        }
+        },
     };
 
     info!("📊 [ENCODING] 测试结果: {:?}", result);
@@ -2716,10 +3206,11 @@ async fn test_printer_encoding_compatibility(
 async fn test_all_encodings_for_printer(
     printer_name: String,
     test_text: String,
-) -> Result<Vec<EncodingTestResult>, String> {
+) -> Result<Vec<EncodingTestResult>, AppError> {
     info!("🧪 [ENCODING] 开始批量编码测试: {}", printer_name);
 
-    let encodings = vec!["UTF8", "GBK", "GB18030", "BIG5", "GB2312"];
+    let profile = printer_profiles::resolve(&printer_name).profile;
+    let encodings = encoding_calibration::candidate_encodings(&profile, None);
     let mut results = Vec::new();
 
     for encoding in encodings {
@@ -2731,7 +3222,7 @@ async fn test_all_encodings_for_printer(
         match test_printer_encoding_compatibility(
             printer_name.clone(),
             test_text.clone(),
-            encoding.to_string(),
+            encoding.clone(),
         ).await {
             Ok(result) => {
                 results.push(result);
@@ -2739,10 +3230,13 @@ async fn test_all_encodings_for_printer(
             Err(e) => {
                 warn!("⚠️ [ENCODING] 编码 {} 测试失败: {}", encoding, e);
                 results.push(EncodingTestResult {
-                    encoding: encoding.to_string(),
+                    encoding: encoding.clone(),
                     score: 0.0,
                     success: false,
-                    error: Some(e),
+                    error: Some(e.to_string()),
+                    coverage: 0.0,
+                    unsupported_chars: Vec::new(),
+                    verified: None,
                 });
             }
         }
@@ -2757,7 +3251,7 @@ async fn test_all_encodings_for_printer(
 async fn generate_encoding_compatibility_report(
     printer_name: String,
     test_results: Vec<EncodingTestResult>,
-) -> Result<EncodingCompatibilityReport, String> {
+) -> Result<EncodingCompatibilityReport, AppError> {
     info!("📊 [ENCODING] 生成兼容性报告: {}", printer_name);
 
     let mut encoding_scores = std::collections::HashMap::new();
@@ -2830,6 +3324,20 @@ async fn generate_encoding_compatibility_report(
         recommendations.push("部分编码测试失败，建议使用评分最高的编码".to_string());
     }
 
+    if test_results.iter().any(|r| r.verified.is_none()) {
+        recommendations.push("评分只根据字符覆盖率估算，请打印编码校准页并确认打印正确的编号".to_string());
+    }
+
+    let mut missing: Vec<String> = test_results
+        .iter()
+        .flat_map(|r| r.unsupported_chars.iter().cloned())
+        .collect();
+    missing.sort();
+    missing.dedup();
+    if !missing.is_empty() {
+        recommendations.push(format!("以下字符在部分编码中无法打印: {}", missing.join("")));
+    }
+
     let report = EncodingCompatibilityReport {
         printer_name: printer_name.clone(),
         overall_score,
@@ -2849,7 +3357,7 @@ async fn print_order_with_encoding(
     order_data: OrderData,
     encoding: String,
     state: State<'_, AppState>,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     info!("🖨️ [ENCODING] 使用指定编码打印订单");
     info!("🖨️ [ENCODING] 打印机: {}", printer_name);
     info!("🖨️ [ENCODING] 编码: {}", encoding);
@@ -2861,58 +3369,84 @@ async fn print_order_with_encoding(
         printers.iter().find(|p| p.name == printer_name).cloned()
     };
 
-    let printer = printer_config.ok_or_else(|| format!("打印机 {} 未找到", printer_name))?;
+    let printer = printer_config.ok_or_else(|| AppError::PrinterNotFound(printer_name.clone()))?;
 
     // 生成基础打印内容
-    let base_content = generate_print_content(&order_data, printer.width, printer.font_size)?;
+    let base_content = generate_print_content(&order_data, &ReceiptLayout::for_printer(&printer))?;
 
-    // 根据编码优化打印内容
-    let optimized_content = match encoding.as_str() {
-        "UTF8" => {
-            format!("\x1B@\x1C&\x1C\x43\x01{}", base_content)
-        }
-        "GBK" | "GB18030" => {
-            format!("\x1B@\x1C&\x1C\x2E\x00{}", base_content) // GBK编码设置
-        }
-        "BIG5" => {
-            format!("\x1B@\x1C&\x1C\x2E\x01{}", base_content) // Big5编码设置
-        }
-        _ => base_content, // 默认处理
-    };
+    // 按打印机的简繁转换设置转换后，插入该代码页的选择命令，并转码为该编码
+    let resolved = printer_profiles::resolve(&printer_name);
+    let content = chinese_script::convert_for_encoding(&resolved, &encoding, &base_content);
+    info!("🖨️ [ENCODING] 使用配置 {} 中的 {} 选择命令", resolved.profile.id, encoding);
+    let encoded = encoding_calibration::encode_receipt(&resolved.profile, &encoding, &content)?;
 
     // 执行打印
-    match print_to_printer(&printer_name, &optimized_content).await {
+    match print_raw_to_printer(&printer_name, encoded).await {
         Ok(_) => {
             info!("✅ [ENCODING] 编码打印成功: {} - {}", printer_name, encoding);
             Ok(format!("订单 {} 使用 {} 编码打印成功", order_data.order_id, encoding))
         }
         Err(e) => {
-            warn!("⚠️ [ENCODING] 编码打印失败，尝试增强版: {}", e);
-            
-            #[cfg(target_os = "windows")]
-            match print_to_printer_enhanced(&printer_name, &optimized_content).await {
-                Ok(_) => {
-                    info!("✅ [ENCODING] 增强版编码打印成功: {} - {}", printer_name, encoding);
-                    Ok(format!("订单 {} 使用 {} 编码打印成功（增强版）", order_data.order_id, encoding))
-                }
-                Err(enhanced_error) => {
-                    error!("❌ [ENCODING] 增强版编码打印失败: {}", enhanced_error);
-                    Err(format!("编码打印失败: {} | 增强版: {}", e, enhanced_error))
-                }
-            }
-            
-            #[cfg(not(target_os = "windows"))]
-            Err(format!("编码打印失败: {}", e))
+            // 增强版按文本发送，会破坏转码后的字节，这里不再重试
+            error!("❌ [ENCODING] 编码打印失败: {} - {} - {}", printer_name, encoding, e);
+            Err(AppError::print_failed(&printer_name, e))
         }
     }
 }
 
+// 离线分析订单中的文字在各编码下能否打印 (不需要打印机)
+#[tauri::command]
+async fn analyze_order_encoding(
+    order: OrderData,
+    encodings: Option<Vec<String>>,
+    printer_name: Option<String>,
+) -> Result<Vec<encoding_calibration::EncodingCoverage>, AppError> {
+    info!("🔍 [ENCODING] 分析订单 {} 的编码覆盖率", order.order_id);
+    let coverage = encoding_calibration::analyze_order(&order, printer_name.as_deref(), encodings);
+    for item in &coverage {
+        info!("🔍 [ENCODING] {}: 覆盖率 {:.1}%, 无法表示 {} 个字符", item.encoding, item.coverage * 100.0, item.unsupported.len());
+    }
+    Ok(coverage)
+}
+
+// 打印编码校准页：每个编码一段编号的样字，操作员确认后调用 confirm_encoding_calibration
+#[tauri::command]
+async fn print_encoding_calibration(
+    printer_name: String,
+    encodings: Option<Vec<String>>,
+) -> Result<encoding_calibration::CalibrationSheet, AppError> {
+    info!("🧪 [ENCODING] 打印编码校准页: {}", printer_name);
+    let (sheet, bytes) = encoding_calibration::build_sheet(&printer_name, encodings)?;
+    print_raw_to_printer(&printer_name, bytes)
+        .await
+        .map_err(|e| AppError::print_failed(&printer_name, e))?;
+    info!("✅ [ENCODING] 校准页已发送，共 {} 个编码", sheet.samples.len());
+    Ok(sheet)
+}
+
+// 保存校准结果：correct_samples 为打印正确的编号
+#[tauri::command]
+async fn confirm_encoding_calibration(
+    printer_name: String,
+    correct_samples: Vec<u32>,
+) -> Result<printer_profiles::ResolvedProfile, AppError> {
+    info!("💾 [ENCODING] 保存编码校准结果: {} {:?}", printer_name, correct_samples);
+    encoding_calibration::confirm(&printer_name, &correct_samples)
+}
+
+// 简繁转换 (先按词组，再逐字)
+#[tauri::command]
+async fn convert_chinese_script(text: String, target: chinese_script::Script) -> Result<String, AppError> {
+    info!("🈶 [ENCODING] 简繁转换: {} 字符 -> {:?}", text.chars().count(), target);
+    Ok(chinese_script::convert(&text, target))
+}
+
 // 新增：智能选择最佳编码
 #[tauri::command]
 async fn select_optimal_encoding(
     text: String,
     printer_name: String,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     info!("🤖 [ENCODING] 智能选择最佳编码");
     info!("🤖 [ENCODING] 打印机: {}", printer_name);
 
@@ -2926,8 +3460,8 @@ async fn select_optimal_encoding(
     let optimal_encoding = match analysis.character_type.as_str() {
         "NONE" => "UTF8".to_string(), // 无中文字符，使用UTF8
         "SYMBOLS_ONLY" => "UTF8".to_string(), // 仅符号，UTF8兼容性好
-        "SIMPLIFIED" => {
-            // 简体中文，根据打印机类型选择
+        "SIMPLIFIED" | "COMMON" => {
+            // 简体中文 (或简繁写法相同的字)，根据打印机类型选择
             if printer_info.supports_chinese {
                 printer_info.recommended_encoding
             } else {
@@ -2945,9 +3479,10 @@ async fn select_optimal_encoding(
             }
         }
         "MIXED" => {
-            // 混合文本，使用通用性好的编码
-            if printer_info.supports_chinese {
-                "UTF8".to_string()
+            // 简繁混合：GBK/GB18030 两种写法都能表示，GB2312、Big5 只能表示其中一种
+            let recommended = printer_info.recommended_encoding.to_uppercase();
+            if printer_info.supports_chinese && matches!(recommended.as_str(), "GBK" | "GB18030" | "UTF8") {
+                recommended
             } else {
                 "UTF8".to_string()
             }
@@ -2961,31 +3496,152 @@ async fn select_optimal_encoding(
     Ok(optimal_encoding)
 }
 
+// 加载保存在本地的台账和配置
+fn load_persisted_state(state: &AppState) {
+    *state.order_ledger.lock().unwrap() = catchup::OrderLedger::load();
+    *state.config.lock().unwrap() = config::load_config();
+    order_changes::prune_records();
+    invoice_pdf::prune_spool();
+}
+
+// 启动后台服务：打印队列、本地HTTP接口、恢复登录会话、断线期间的漏单检查
+// (图形界面和无界面模式共用)
+fn start_background_services(ctx: Ctx) {
+    let state = ctx.app_state();
+    state.print_jobs.start_worker(ctx.clone());
+
+    // 启用了本地HTTP打印接口时启动服务 (首次启动时生成访问令牌)
+    let http_settings = {
+        let mut config = state.config.lock().unwrap();
+        if config.http_api.enabled && config.http_api.token.is_empty() {
+            config.http_api.token = http_api::generate_token();
+            if let Err(e) = config::save_config(&config) {
+                warn!("⚠️ [HTTP_API] 保存访问令牌失败: {}", e);
+            }
+        }
+        config.http_api.clone()
+    };
+    if http_settings.enabled {
+        state.http_api.start(ctx.clone(), &http_settings);
+    }
+
+    // 恢复上次保存的登录会话，并自动重新连接WebSocket
+    if let Some(stored) = session::load_session() {
+        ctx.emit("session-restored", serde_json::json!({
+            "user_id": stored.user_id,
+            "username": stored.username,
+            "rd_id": stored.rd_id
+        }));
+        start_session(state, ctx.clone(), stored);
+    }
+
+    // 断线期间定期检查漏单
+    tauri::async_runtime::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(catchup::OFFLINE_POLL_INTERVAL_SECS));
+        loop {
+            interval.tick().await;
+            let (connected, logged_in) = {
+                let state = ctx.app_state();
+                let connected = state.connection.is_connected();
+                let logged_in = state.session.lock().unwrap().is_some();
+                (connected, logged_in)
+            };
+            if logged_in && !connected {
+                if let Err(e) = catchup::catch_up_missed_orders(&ctx, "offline-poll").await {
+                    warn!("⚠️ [CATCH_UP] 断线期间检查漏单失败: {}", e);
+                }
+            }
+        }
+    });
+}
+
 fn main() {
+    // 带子命令启动时进入无界面模式 (后台服务或命令行工具)
+    let args: Vec<String> = std::env::args().collect();
+    let cli_command = headless::parse_args(&args);
+    if cli_command.is_some() {
+        headless::attach_console();
+    }
+
     // 初始化日志系统
-    if let Err(e) = init_logger() {
+    if let Err(e) = logging::init_logger() {
         eprintln!("❌ 初始化日志系统失败: {}", e);
         // 即使日志初始化失败也继续运行程序
     }
 
+    if let Some(command) = cli_command {
+        std::process::exit(headless::run(command));
+    }
+
     info!("🚀 开始启动 Tauri 应用程序");
 
     tauri::Builder::default()
         .manage(AppState::default())
+        .setup(|app| {
+            load_persisted_state(&app.state::<AppState>());
+
+            if let Some(window) = app.get_window("main") {
+                start_background_services(events::window_context(&window));
+
+                // 实时日志推送 (只在图形界面模式下，界面调用 set_log_streaming 开启)
+                let mut log_lines = logging::subscribe();
+                tauri::async_runtime::spawn(async move {
+                    while let Some(record) = log_lines.recv().await {
+                        let _ = window.emit("log-line", &record);
+                    }
+                });
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             login,
+            get_saved_session,
             get_printers,
             set_printer_config,
+            get_printer_profiles,
+            get_printer_profile,
+            set_printer_override,
+            reload_printer_profiles,
             get_connection_status,
+            get_connection_stats,
+            get_heartbeat_settings,
+            set_heartbeat_settings,
+            get_http_api_settings,
+            set_http_api_settings,
+            get_print_jobs,
+            search_order_history,
+            get_order_history_detail,
+            reprint_order_from_history,
+            generate_sales_report,
+            print_sales_report,
+            export_sales_report_csv,
+            export_orders,
+            export_invoice_pdf,
+            get_invoice_settings,
+            set_invoice_settings,
+            get_export_settings,
+            set_export_settings,
+            get_printer_action_rules,
+            set_printer_action_rules,
+            open_cash_drawer,
+            connect_websocket,
+            disconnect_websocket,
+            logout,
             test_print,
             toggle_printer,
             get_order_list,
             get_order_detail,
             manual_print_order,
+            catch_up_orders,
             get_print_preview,
             get_log_content,
             get_log_info,
+            query_logs,
+            set_log_streaming,
+            export_diagnostics,
             clear_logs,
+            get_log_settings,
+            set_log_settings,
             open_log_folder,
             debug_printer,
             test_frontend_call,
@@ -2998,8 +3654,32 @@ fn main() {
             test_all_encodings_for_printer,
             generate_encoding_compatibility_report,
             print_order_with_encoding,
-            select_optimal_encoding
+            select_optimal_encoding,
+            analyze_order_encoding,
+            print_encoding_calibration,
+            confirm_encoding_calibration,
+            convert_chinese_script
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cjk_item_rows_fill_the_line_exactly() {
+        for width in [32, 42, 48] {
+            for name in ["宫保鸡丁", "Kung Pao 宫保鸡丁", "特别长的四川风味麻辣香锅配米饭和时令蔬菜小炒"] {
+                let row = format_item_table_row(name, 2, 11.99, 23.98, width);
+                let first = row.lines().next().unwrap();
+                assert_eq!(display_width(first), width, "{} @ {}: {:?}", name, width, first);
+                assert!(first.ends_with("23.98"));
+                for line in row.lines().skip(1) {
+                    assert!(display_width(line) <= width, "{:?}", line);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,533 @@
+// 订单取消和修改通知小票
+//
+// 订单打印后如果被取消或修改，厨房需要知道。每次打印成功后把打印的订单内容和
+// 打印机保存到 printed_orders/<order_id>.json，收到取消/修改消息 (WebSocket 或补打轮询)
+// 时与保存的版本对比，在原来的打印机上打印醒目的 VOID 或 CHANGED 小票。
+
+use log::{info, debug, warn, error};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::error::AppError;
+use crate::events::Ctx;
+use crate::receipt_layout::{rule, ReceiptLayout, Section, TextSize};
+use crate::{DishItem, OrderData, PrinterConfig};
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+// 保存的打印记录保留天数 (与订单台账一致)
+const RECORD_RETENTION_DAYS: u64 = 7;
+// 表示订单已取消的 order_status 取值：接口文档中 0 为取消订单 (4 是催单，6 是制作完成)
+const CANCELLED_ORDER_STATUSES: &[i32] = &[0];
+// 原打印机已从配置中删除时使用的纸宽
+const FALLBACK_PRINTER_WIDTH: i32 = 58;
+
+// 已打印订单的记录
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct PrintedRecord {
+    pub order: OrderData,
+    pub printers: Vec<String>,
+    pub printed_at: String,
+    #[serde(default)]
+    pub voided_at: Option<String>,
+}
+
+// 菜品变化
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub(crate) enum ItemChange {
+    Added { name: String, amount: i32, remark: String },
+    Removed { name: String, amount: i32, remark: String },
+    Changed { name: String, old_amount: i32, new_amount: i32, old_remark: String, new_remark: String },
+}
+
+// 订单信息变化 (取餐时间、地址、备注等)
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct FieldChange {
+    pub label: &'static str,
+    pub old: String,
+    pub new: String,
+}
+
+pub(crate) fn is_cancelled(order: &OrderData) -> bool {
+    CANCELLED_ORDER_STATUSES.contains(&order.order_status)
+}
+
+// ============= 打印记录 =============
+
+fn records_dir() -> PathBuf {
+    crate::get_app_data_dir().join("printed_orders")
+}
+
+fn record_path(order_id: &str) -> PathBuf {
+    // 订单ID用作文件名，只保留安全字符
+    let file_name: String = order_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    records_dir().join(format!("{}.json", file_name))
+}
+
+pub(crate) fn load_printed(order_id: &str) -> Option<PrintedRecord> {
+    let content = fs::read_to_string(record_path(order_id)).ok()?;
+    match serde_json::from_str::<PrintedRecord>(&content) {
+        Ok(record) => Some(record),
+        Err(e) => {
+            warn!("⚠️ [ORDER_CHANGE] 订单 {} 的打印记录格式错误: {}", order_id, e);
+            None
+        }
+    }
+}
+
+fn write_record(record: &PrintedRecord) {
+    let path = record_path(&record.order.order_id);
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    match serde_json::to_string_pretty(record) {
+        Ok(content) => {
+            if let Err(e) = fs::write(&path, content) {
+                error!("❌ [ORDER_CHANGE] 保存打印记录失败: {}", e);
+            }
+        }
+        Err(e) => error!("❌ [ORDER_CHANGE] 序列化打印记录失败: {}", e),
+    }
+}
+
+// 打印成功后保存打印的版本和打印机 (重复打印时合并打印机列表)
+pub(crate) fn save_printed(order: &OrderData, printers: &[String]) {
+    let mut all_printers = load_printed(&order.order_id)
+        .map(|record| record.printers)
+        .unwrap_or_default();
+    for printer in printers {
+        if !all_printers.contains(printer) {
+            all_printers.push(printer.clone());
+        }
+    }
+
+    write_record(&PrintedRecord {
+        order: order.clone(),
+        printers: all_printers,
+        printed_at: chrono::Local::now().format(TIME_FORMAT).to_string(),
+        voided_at: None,
+    });
+}
+
+// 删除超过保留天数的打印记录
+pub(crate) fn prune_records() {
+    let Ok(entries) = fs::read_dir(records_dir()) else { return };
+    let retention = std::time::Duration::from_secs(RECORD_RETENTION_DAYS * 24 * 60 * 60);
+    let mut removed = 0;
+
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .map(|age| age > retention)
+            .unwrap_or(false);
+        if expired && fs::remove_file(entry.path()).is_ok() {
+            removed += 1;
+        }
+    }
+
+    if removed > 0 {
+        info!("🧹 [ORDER_CHANGE] 已删除 {} 条过期的打印记录", removed);
+    }
+}
+
+// ============= 对比 =============
+
+struct ItemLine {
+    key: String,
+    name: String,
+    amount: i32,
+    remark: String,
+}
+
+// 同一菜品 (ID + 规格 + 附加项) 出现多行时合并数量和备注
+fn collect_items(items: &[DishItem]) -> Vec<ItemLine> {
+    let mut lines: Vec<ItemLine> = Vec::new();
+    for item in items {
+        let key = format!("{}|{}|{}", item.dishes_id, item.dishes_specs_id, item.dishes_describe);
+        if let Some(line) = lines.iter_mut().find(|line| line.key == key) {
+            line.amount += item.amount;
+            if !item.remark.is_empty() {
+                if !line.remark.is_empty() {
+                    line.remark.push_str("; ");
+                }
+                line.remark.push_str(&item.remark);
+            }
+            continue;
+        }
+
+        let name = if item.dishes_describe.is_empty() {
+            item.dishes_name.clone()
+        } else {
+            format!("{} ({})", item.dishes_name, item.dishes_describe)
+        };
+        lines.push(ItemLine {
+            key,
+            name,
+            amount: item.amount,
+            remark: item.remark.clone(),
+        });
+    }
+    lines
+}
+
+pub(crate) fn diff_items(old: &OrderData, new: &OrderData) -> Vec<ItemChange> {
+    let old_items = collect_items(&old.dishes_array);
+    let new_items = collect_items(&new.dishes_array);
+    let mut changes = Vec::new();
+
+    for old_line in &old_items {
+        match new_items.iter().find(|line| line.key == old_line.key) {
+            None => changes.push(ItemChange::Removed {
+                name: old_line.name.clone(),
+                amount: old_line.amount,
+                remark: old_line.remark.clone(),
+            }),
+            Some(new_line) if new_line.amount != old_line.amount || new_line.remark != old_line.remark => {
+                changes.push(ItemChange::Changed {
+                    name: new_line.name.clone(),
+                    old_amount: old_line.amount,
+                    new_amount: new_line.amount,
+                    old_remark: old_line.remark.clone(),
+                    new_remark: new_line.remark.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+
+    for new_line in &new_items {
+        if !old_items.iter().any(|line| line.key == new_line.key) {
+            changes.push(ItemChange::Added {
+                name: new_line.name.clone(),
+                amount: new_line.amount,
+                remark: new_line.remark.clone(),
+            });
+        }
+    }
+
+    changes
+}
+
+pub(crate) fn diff_fields(old: &OrderData, new: &OrderData) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    let mut compare = |label: &'static str, old: String, new: String| {
+        if old != new {
+            changes.push(FieldChange { label, old, new });
+        }
+    };
+
+    compare("Type", crate::get_order_type_text(old).to_string(), crate::get_order_type_text(new).to_string());
+    compare("Time", crate::format_delivery_time(&old.delivery_time), crate::format_delivery_time(&new.delivery_time));
+    if new.delivery_style == 1 {
+        compare("Address", old.recipient_address.clone(), new.recipient_address.clone());
+    }
+    compare("Notes", old.order_notes.clone(), new.order_notes.clone());
+    compare("Total", old.total.clone(), new.total.clone());
+
+    changes
+}
+
+// ============= 小票内容 =============
+
+fn push_ticket_header(content: &mut String, title: &str, layout: &ReceiptLayout) {
+    layout.init(content);
+
+    // 标题：默认2倍宽高 + 反白，厨房一眼能看出不是普通订单
+    let char_width = layout.begin(content, Section::Header);
+    content.push_str(&rule('=', char_width));
+    let title_width = layout.begin(content, Section::Title);
+    content.push_str("\x1D\x42\x01"); // 反白
+    content.push_str(&crate::center_text_mixed(&format!(" *** {} *** ", title), title_width));
+    content.push_str("\x1D\x42\x00"); // 关闭反白
+    content.push_str("\n");
+
+    let char_width = layout.begin(content, Section::Header);
+    content.push_str(&rule('=', char_width));
+}
+
+fn push_order_info(content: &mut String, order: &OrderData, layout: &ReceiptLayout) {
+    let char_width = layout.begin(content, Section::Header);
+    content.push_str("\x1B\x45\x01"); // 加粗
+    content.push_str(&crate::center_text_mixed(&format!("Order #: {}", order.order_id), char_width));
+    content.push_str("\x1B\x45\x00"); // 关闭加粗
+    content.push_str("\n");
+
+    let serial = if order.serial_num > 0 {
+        format!("#{:03}", order.serial_num)
+    } else {
+        format!("#{}", crate::get_order_serial(order))
+    };
+    let char_width = layout.begin(content, Section::Info);
+    content.push_str(&crate::format_table_row("Serial:", &serial, char_width));
+    content.push_str(&crate::format_table_row("Type:", crate::get_order_type_text(order), char_width));
+    content.push_str(&crate::format_table_row("Customer:", &crate::prepare_mixed_content(&order.recipient_name), char_width));
+}
+
+fn push_ticket_footer(content: &mut String, label: &str, layout: &ReceiptLayout) {
+    let now = chrono::Local::now().format("%m/%d/%Y %I:%M %p").to_string();
+    let char_width = layout.begin(content, Section::Footer);
+    content.push_str(&rule('=', char_width));
+    content.push_str(&crate::format_table_row(label, &now, char_width));
+    content.push_str("\n\n\n\n"); // 为切纸预留空间
+    content.push_str(&TextSize::NORMAL.command());
+    content.push_str("\x1D\x56\x00"); // 全切
+}
+
+// 菜品段落的标题 (分隔线 + 加粗标题)
+fn push_items_title(content: &mut String, title: &str, layout: &ReceiptLayout) {
+    let char_width = layout.begin(content, Section::Items);
+    content.push_str(&rule('-', char_width));
+    content.push_str("\x1B\x45\x01");
+    content.push_str(title);
+    content.push_str("\x1B\x45\x00");
+    content.push_str("\n");
+}
+
+// 取消小票
+pub(crate) fn generate_void_content(order: &OrderData, reason: Option<&str>, layout: &ReceiptLayout) -> String {
+    let mut content = String::new();
+
+    push_ticket_header(&mut content, "VOID", layout);
+    push_order_info(&mut content, order, layout);
+
+    if let Some(reason) = reason.filter(|r| !r.is_empty()) {
+        let char_width = layout.columns(Section::Info);
+        content.push_str(&crate::format_table_row("Reason:", &crate::prepare_mixed_content(reason), char_width));
+    }
+
+    let char_width = layout.begin(&mut content, Section::Items);
+    content.push_str(&rule('-', char_width));
+    content.push_str("\x1B\x45\x01");
+    content.push_str(&crate::center_text_mixed("DO NOT PREPARE", char_width));
+    content.push_str("\x1B\x45\x00");
+    content.push_str("\n");
+    content.push_str(&rule('-', char_width));
+
+    for line in collect_items(&order.dishes_array) {
+        content.push_str(&format!("X {} x {}\n", line.amount, crate::prepare_mixed_content(&line.name)));
+    }
+
+    push_ticket_footer(&mut content, "Voided:", layout);
+    content
+}
+
+// 修改小票：只列出变化的部分
+pub(crate) fn generate_changed_content(
+    order: &OrderData,
+    item_changes: &[ItemChange],
+    field_changes: &[FieldChange],
+    layout: &ReceiptLayout,
+) -> String {
+    let mut content = String::new();
+
+    push_ticket_header(&mut content, "CHANGED", layout);
+    push_order_info(&mut content, order, layout);
+
+    let sections: [(&str, Vec<String>); 3] = [
+        ("ADDED", item_changes.iter().filter_map(|change| match change {
+            ItemChange::Added { name, amount, remark } => Some(format_item_line("+", *amount, name, remark)),
+            _ => None,
+        }).collect()),
+        ("REMOVED", item_changes.iter().filter_map(|change| match change {
+            ItemChange::Removed { name, amount, remark } => Some(format_item_line("-", *amount, name, remark)),
+            _ => None,
+        }).collect()),
+        ("CHANGED", item_changes.iter().filter_map(|change| match change {
+            ItemChange::Changed { name, old_amount, new_amount, old_remark, new_remark } => {
+                let mut line = format!("* {}\n", crate::prepare_mixed_content(name));
+                if old_amount != new_amount {
+                    line.push_str(&format!("  Qty: {} -> {}\n", old_amount, new_amount));
+                }
+                if old_remark != new_remark {
+                    line.push_str(&format!(
+                        "  Note: {} -> {}\n",
+                        display_or_none(old_remark),
+                        display_or_none(new_remark)
+                    ));
+                }
+                Some(line)
+            }
+            _ => None,
+        }).collect()),
+    ];
+
+    for (title, lines) in sections.iter() {
+        if lines.is_empty() {
+            continue;
+        }
+        push_items_title(&mut content, title, layout);
+        for line in lines {
+            content.push_str(line);
+        }
+    }
+
+    if !field_changes.is_empty() {
+        let char_width = layout.begin(&mut content, Section::Info);
+        content.push_str(&rule('-', char_width));
+        for change in field_changes {
+            content.push_str(&format!("{}:\n", change.label));
+            content.push_str(&format!("  {} -> {}\n",
+                crate::prepare_mixed_content(display_or_none(&change.old)),
+                crate::prepare_mixed_content(display_or_none(&change.new))));
+        }
+    }
+
+    push_ticket_footer(&mut content, "Changed:", layout);
+    content
+}
+
+fn format_item_line(mark: &str, amount: i32, name: &str, remark: &str) -> String {
+    let mut line = format!("{} {} x {}\n", mark, amount, crate::prepare_mixed_content(name));
+    if !remark.is_empty() {
+        line.push_str(&format!("  Note: {}\n", crate::prepare_mixed_content(remark)));
+    }
+    line
+}
+
+fn display_or_none(value: &str) -> &str {
+    if value.is_empty() { "(none)" } else { value }
+}
+
+// ============= 处理取消/修改 =============
+
+// 在原来的打印机上打印，返回成功的打印机数量
+async fn print_on_original_printers(
+    ctx: &Ctx,
+    record: &PrintedRecord,
+    render: impl Fn(&ReceiptLayout) -> String,
+) -> usize {
+    let configured: Vec<PrinterConfig> = ctx.app_state().printers.lock().unwrap().clone();
+    let mut success = 0;
+
+    for printer_name in &record.printers {
+        let layout = match configured.iter().find(|p| &p.name == printer_name) {
+            Some(p) => ReceiptLayout::for_printer(p),
+            None => {
+                warn!("⚠️ [ORDER_CHANGE] 打印机 {} 已不在配置中，按 {}mm 纸宽打印", printer_name, FALLBACK_PRINTER_WIDTH);
+                ReceiptLayout::new(FALLBACK_PRINTER_WIDTH, 0)
+            }
+        };
+
+        match crate::send_to_printer(printer_name, &render(&layout)).await {
+            Ok(_) => success += 1,
+            Err(e) => {
+                error!("❌ [ORDER_CHANGE] {}", e);
+                ctx.emit("printer-print-error", &e);
+            }
+        }
+    }
+
+    success
+}
+
+// 订单被取消：打印过且还没打印取消小票时打印 VOID 小票，返回是否打印
+pub(crate) async fn handle_order_cancelled(ctx: &Ctx, order_id: &str, reason: Option<&str>) -> Result<bool, AppError> {
+    let Some(mut record) = load_printed(order_id) else {
+        debug!("🚫 [ORDER_CHANGE] 订单 {} 没有打印过，不需要取消小票", order_id);
+        return Ok(false);
+    };
+    if record.voided_at.is_some() {
+        debug!("🚫 [ORDER_CHANGE] 订单 {} 已打印过取消小票", order_id);
+        return Ok(false);
+    }
+
+    info!("🚫 [ORDER_CHANGE] 订单 {} 已取消，在 {} 台打印机上打印 VOID 小票", order_id, record.printers.len());
+    let order = record.order.clone();
+    let printed = print_on_original_printers(ctx, &record, |layout| {
+        generate_void_content(&order, reason, layout)
+    }).await;
+
+    if printed == 0 {
+        return Err(AppError::print_failed(&record.printers.join(", "), "取消小票打印失败"));
+    }
+
+    record.voided_at = Some(chrono::Local::now().format(TIME_FORMAT).to_string());
+    write_record(&record);
+    ctx.emit("order-voided", serde_json::json!({
+        "order_id": order_id,
+        "reason": reason,
+        "printers": record.printers
+    }));
+    Ok(true)
+}
+
+// 订单被修改：与打印时的版本对比，有变化时打印 CHANGED 小票，返回是否打印
+pub(crate) async fn handle_order_updated(ctx: &Ctx, order: &OrderData) -> Result<bool, AppError> {
+    if is_cancelled(order) {
+        return handle_order_cancelled(ctx, &order.order_id, None).await;
+    }
+
+    let Some(mut record) = load_printed(&order.order_id) else {
+        debug!("✏️ [ORDER_CHANGE] 订单 {} 没有打印过，不需要修改小票", order.order_id);
+        return Ok(false);
+    };
+    if record.voided_at.is_some() {
+        warn!("⚠️ [ORDER_CHANGE] 订单 {} 已取消，忽略修改", order.order_id);
+        return Ok(false);
+    }
+
+    let item_changes = diff_items(&record.order, order);
+    let field_changes = diff_fields(&record.order, order);
+    if item_changes.is_empty() && field_changes.is_empty() {
+        return Ok(false);
+    }
+
+    info!("✏️ [ORDER_CHANGE] 订单 {} 有 {} 项菜品变化、{} 项信息变化，打印 CHANGED 小票",
+          order.order_id, item_changes.len(), field_changes.len());
+    let printed = print_on_original_printers(ctx, &record, |layout| {
+        generate_changed_content(order, &item_changes, &field_changes, layout)
+    }).await;
+
+    if printed == 0 {
+        return Err(AppError::print_failed(&record.printers.join(", "), "修改小票打印失败"));
+    }
+
+    // 之后的修改与这次打印的版本对比
+    record.order = order.clone();
+    record.printed_at = chrono::Local::now().format(TIME_FORMAT).to_string();
+    write_record(&record);
+    ctx.emit("order-changed", serde_json::json!({
+        "order_id": order.order_id,
+        "items": item_changes,
+        "fields": field_changes,
+        "printers": record.printers
+    }));
+    Ok(true)
+}
+
+// WebSocket 只推送了订单ID时，先获取最新的订单详情
+pub(crate) async fn handle_order_updated_by_id(ctx: &Ctx, order_id: &str) -> Result<bool, AppError> {
+    if load_printed(order_id).is_none() {
+        return Ok(false);
+    }
+
+    let order = crate::fetch_order(ctx, order_id).await?;
+    handle_order_updated(ctx, &order).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order_with_status(order_status: i32) -> OrderData {
+        let mut order = crate::sample_test_order();
+        order.order_status = order_status;
+        order
+    }
+
+    #[test]
+    fn cancelled_statuses_follow_the_api_docs() {
+        assert!(is_cancelled(&order_with_status(0)));
+        // 1:下单 2:商家接单 3:支付完成 4:催单 6:制作完成 8:外送中 10:订单完成
+        for status in [1, 2, 3, 4, 6, 8, 10] {
+            assert!(!is_cancelled(&order_with_status(status)), "status {}", status);
+        }
+    }
+}
@@ -0,0 +1,401 @@
+// 订单导出 (CSV / JSON Lines / XLSX)
+//
+// 导出日期范围内的订单和菜品明细，列可以选择 (保存在 config.json 的 export 段落)。
+// 接口返回的金额是字符串，导出时统一转换为两位小数的数字。
+
+use log::info;
+use rust_xlsxwriter::{Format, Workbook};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::config::ExportSettings;
+use crate::error::AppError;
+use crate::events::Ctx;
+use crate::sales_report::{self, csv_field, format_cents, parse_cents, ReportSource};
+use crate::{DishItem, OrderData};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ExportFormat {
+    Csv,
+    Jsonl,
+    Xlsx,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ColumnKind {
+    Text,
+    Integer,
+    Money,  // 两位小数
+    Rate,   // 税率等比例
+}
+
+// 可导出的列 (名称, 类型, 是否菜品列)
+#[derive(Clone, Copy, Debug, Serialize)]
+pub(crate) struct Column {
+    pub name: &'static str,
+    kind: ColumnKind,
+    pub item: bool,
+}
+
+const fn order_column(name: &'static str, kind: ColumnKind) -> Column {
+    Column { name, kind, item: false }
+}
+
+const fn item_column(name: &'static str, kind: ColumnKind) -> Column {
+    Column { name, kind, item: true }
+}
+
+pub(crate) const COLUMNS: &[Column] = &[
+    order_column("order_id", ColumnKind::Text),
+    order_column("serial_num", ColumnKind::Integer),
+    order_column("create_time", ColumnKind::Text),
+    order_column("delivery_time", ColumnKind::Text),
+    order_column("order_status", ColumnKind::Integer),
+    order_column("paystyle", ColumnKind::Integer),
+    order_column("payment_method", ColumnKind::Text),
+    order_column("delivery_style", ColumnKind::Integer),
+    order_column("order_type", ColumnKind::Text),
+    order_column("recipient_name", ColumnKind::Text),
+    order_column("recipient_phone", ColumnKind::Text),
+    order_column("recipient_address", ColumnKind::Text),
+    order_column("user_email", ColumnKind::Text),
+    order_column("dishes_count", ColumnKind::Integer),
+    order_column("sub_total", ColumnKind::Money),
+    order_column("discount_total", ColumnKind::Money),
+    order_column("exemption", ColumnKind::Money),
+    order_column("tax_rate", ColumnKind::Rate),
+    order_column("tax_fee", ColumnKind::Money),
+    order_column("delivery_fee", ColumnKind::Money),
+    order_column("retail_delivery_fee", ColumnKind::Money),
+    order_column("convenience_rate", ColumnKind::Rate),
+    order_column("convenience_fee", ColumnKind::Money),
+    order_column("tip_fee", ColumnKind::Money),
+    order_column("total", ColumnKind::Money),
+    order_column("order_notes", ColumnKind::Text),
+    item_column("item_name", ColumnKind::Text),
+    item_column("item_amount", ColumnKind::Integer),
+    item_column("item_unit_price", ColumnKind::Money),
+    item_column("item_price", ColumnKind::Money),
+    item_column("item_remark", ColumnKind::Text),
+    item_column("item_describe", ColumnKind::Text),
+];
+
+// 没有配置时导出的列
+const DEFAULT_COLUMNS: &[&str] = &[
+    "order_id", "create_time", "order_status", "payment_method", "order_type",
+    "recipient_name", "recipient_phone",
+    "sub_total", "discount_total", "tax_fee", "delivery_fee", "convenience_fee", "tip_fee", "total",
+    "item_name", "item_amount", "item_unit_price", "item_price",
+];
+
+enum Cell {
+    Text(String),
+    Integer(i64),
+    Cents(i64),
+    Rate(f64),
+}
+
+impl Cell {
+    fn to_csv(&self) -> String {
+        match self {
+            Cell::Text(text) => csv_field(text),
+            Cell::Integer(value) => value.to_string(),
+            Cell::Cents(cents) => format_cents(*cents),
+            Cell::Rate(rate) => rate.to_string(),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Cell::Text(text) => serde_json::json!(text),
+            Cell::Integer(value) => serde_json::json!(value),
+            Cell::Cents(cents) => serde_json::json!(*cents as f64 / 100.0),
+            Cell::Rate(rate) => serde_json::json!(rate),
+        }
+    }
+}
+
+fn order_cell(order: &OrderData, name: &str) -> Cell {
+    let money = |value: &str| Cell::Cents(parse_cents(value));
+    let rate = |value: &str| Cell::Rate(value.trim().parse::<f64>().unwrap_or(0.0));
+
+    match name {
+        "order_id" => Cell::Text(order.order_id.clone()),
+        "serial_num" => Cell::Integer(i64::from(order.serial_num)),
+        "create_time" => Cell::Text(order.create_time.clone()),
+        "delivery_time" => Cell::Text(order.delivery_time.clone()),
+        "order_status" => Cell::Integer(i64::from(order.order_status)),
+        "paystyle" => Cell::Integer(i64::from(order.paystyle)),
+        "payment_method" => Cell::Text(crate::get_payment_method_text(order.paystyle).to_string()),
+        "delivery_style" => Cell::Integer(i64::from(order.delivery_style)),
+        "order_type" => Cell::Text(crate::get_order_type_text(order).to_string()),
+        "recipient_name" => Cell::Text(order.recipient_name.clone()),
+        "recipient_phone" => Cell::Text(order.recipient_phone.clone()),
+        "recipient_address" => Cell::Text(order.recipient_address.clone()),
+        "user_email" => Cell::Text(order.user_email.clone()),
+        "dishes_count" => Cell::Integer(i64::from(order.dishes_count)),
+        "sub_total" => money(&order.sub_total),
+        "discount_total" => money(&order.discount_total),
+        "exemption" => money(&order.exemption),
+        "tax_rate" => rate(&order.tax_rate),
+        "tax_fee" => money(&order.tax_fee),
+        "delivery_fee" => money(&order.delivery_fee),
+        "retail_delivery_fee" => money(&order.retail_delivery_fee),
+        "convenience_rate" => rate(&order.convenience_rate),
+        "convenience_fee" => money(&order.convenience_fee),
+        "tip_fee" => money(&order.tip_fee),
+        "total" => money(&order.total),
+        "order_notes" => Cell::Text(order.order_notes.clone()),
+        _ => Cell::Text(String::new()),
+    }
+}
+
+fn item_cell(item: Option<&DishItem>, name: &str) -> Cell {
+    let Some(item) = item else {
+        return Cell::Text(String::new());
+    };
+    match name {
+        "item_name" => Cell::Text(item.dishes_name.clone()),
+        "item_amount" => Cell::Integer(i64::from(item.amount)),
+        "item_unit_price" => Cell::Cents(parse_cents(&item.unit_price)),
+        "item_price" => Cell::Cents(parse_cents(&item.price)),
+        "item_remark" => Cell::Text(item.remark.clone()),
+        "item_describe" => Cell::Text(item.dishes_describe.clone()),
+        _ => Cell::Text(String::new()),
+    }
+}
+
+// 检查并解析要导出的列，未指定时使用默认列
+fn resolve_columns(names: &[String]) -> Result<Vec<Column>, AppError> {
+    if names.is_empty() {
+        return Ok(DEFAULT_COLUMNS
+            .iter()
+            .filter_map(|name| COLUMNS.iter().find(|c| c.name == *name).copied())
+            .collect());
+    }
+
+    names
+        .iter()
+        .map(|name| {
+            COLUMNS
+                .iter()
+                .find(|c| c.name == name.as_str())
+                .copied()
+                .ok_or_else(|| AppError::InvalidArgument(format!("未知的导出列: {}", name)))
+        })
+        .collect()
+}
+
+pub(crate) fn validate_settings(settings: &ExportSettings) -> Result<(), AppError> {
+    resolve_columns(&settings.columns).map(|_| ())
+}
+
+// CSV/XLSX的行：每个菜品一行，或者每个订单一行 (没有选择菜品列时也是每个订单一行)
+fn table_rows(orders: &[OrderData], columns: &[Column], one_row_per_item: bool) -> Vec<Vec<Cell>> {
+    let has_item_columns = columns.iter().any(|c| c.item);
+    let mut rows = Vec::new();
+
+    for order in orders {
+        let items: Vec<Option<&DishItem>> = if one_row_per_item && has_item_columns && !order.dishes_array.is_empty() {
+            order.dishes_array.iter().map(Some).collect()
+        } else {
+            vec![None]
+        };
+
+        for item in items {
+            rows.push(
+                columns
+                    .iter()
+                    .map(|c| if c.item { item_cell(item, c.name) } else { order_cell(order, c.name) })
+                    .collect(),
+            );
+        }
+    }
+    rows
+}
+
+fn write_csv(path: &str, orders: &[OrderData], columns: &[Column], one_row_per_item: bool) -> Result<usize, AppError> {
+    // 带BOM，Excel打开中文不乱码
+    let mut csv = String::from("\u{FEFF}");
+    csv.push_str(&columns.iter().map(|c| c.name).collect::<Vec<_>>().join(","));
+    csv.push_str("\r\n");
+
+    let rows = table_rows(orders, columns, one_row_per_item);
+    for row in &rows {
+        csv.push_str(&row.iter().map(Cell::to_csv).collect::<Vec<_>>().join(","));
+        csv.push_str("\r\n");
+    }
+
+    fs::write(path, csv).map_err(|e| AppError::Io(format!("写入 {} 失败: {}", path, e)))?;
+    Ok(rows.len())
+}
+
+// JSON Lines：每个订单一行，菜品列放在 items 数组中
+fn write_jsonl(path: &str, orders: &[OrderData], columns: &[Column]) -> Result<usize, AppError> {
+    let mut output = String::new();
+
+    for order in orders {
+        let mut object = serde_json::Map::new();
+        for column in columns.iter().filter(|c| !c.item) {
+            object.insert(column.name.to_string(), order_cell(order, column.name).to_json());
+        }
+
+        let item_columns: Vec<&Column> = columns.iter().filter(|c| c.item).collect();
+        if !item_columns.is_empty() {
+            let items: Vec<serde_json::Value> = order
+                .dishes_array
+                .iter()
+                .map(|item| {
+                    let mut item_object = serde_json::Map::new();
+                    for column in &item_columns {
+                        item_object.insert(column.name.to_string(), item_cell(Some(item), column.name).to_json());
+                    }
+                    serde_json::Value::Object(item_object)
+                })
+                .collect();
+            object.insert("items".to_string(), serde_json::Value::Array(items));
+        }
+
+        output.push_str(&serde_json::to_string(&object)?);
+        output.push('\n');
+    }
+
+    fs::write(path, output).map_err(|e| AppError::Io(format!("写入 {} 失败: {}", path, e)))?;
+    Ok(orders.len())
+}
+
+fn write_xlsx(path: &str, orders: &[OrderData], columns: &[Column], one_row_per_item: bool) -> Result<usize, AppError> {
+    let xlsx_error = |e: rust_xlsxwriter::XlsxError| AppError::Io(format!("生成Excel文件失败: {}", e));
+
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Orders").map_err(xlsx_error)?;
+
+    let header_format = Format::new().set_bold();
+    let money_format = Format::new().set_num_format("0.00");
+
+    for (col, column) in columns.iter().enumerate() {
+        sheet.write_string_with_format(0, col as u16, column.name, &header_format).map_err(xlsx_error)?;
+        if column.kind == ColumnKind::Money {
+            sheet.set_column_format(col as u16, &money_format).map_err(xlsx_error)?;
+        }
+    }
+
+    let rows = table_rows(orders, columns, one_row_per_item);
+    for (index, row) in rows.iter().enumerate() {
+        let row_num = (index + 1) as u32;
+        for (col, cell) in row.iter().enumerate() {
+            let col = col as u16;
+            match cell {
+                Cell::Text(text) => sheet.write_string(row_num, col, text),
+                Cell::Integer(value) => sheet.write_number(row_num, col, *value as f64),
+                Cell::Cents(cents) => sheet.write_number_with_format(row_num, col, *cents as f64 / 100.0, &money_format),
+                Cell::Rate(rate) => sheet.write_number(row_num, col, *rate),
+            }
+            .map_err(xlsx_error)?;
+        }
+    }
+
+    workbook.save(path).map_err(xlsx_error)?;
+    Ok(rows.len())
+}
+
+// 导出日期范围内的订单，返回写入的行数
+pub(crate) async fn export_orders(
+    ctx: &Ctx,
+    date_from: &str,
+    date_to: &str,
+    source: ReportSource,
+    format: ExportFormat,
+    columns: Option<Vec<String>>,
+    path: &str,
+) -> Result<usize, AppError> {
+    let settings = ctx.app_state().config.lock().unwrap().export.clone();
+    let columns = resolve_columns(columns.as_deref().unwrap_or(&settings.columns))?;
+
+    let (from, to, orders) = sales_report::collect_orders(ctx, date_from, date_to, source).await?;
+
+    let rows = match format {
+        ExportFormat::Csv => write_csv(path, &orders, &columns, settings.one_row_per_item)?,
+        ExportFormat::Jsonl => write_jsonl(path, &orders, &columns)?,
+        ExportFormat::Xlsx => write_xlsx(path, &orders, &columns, settings.one_row_per_item)?,
+    };
+
+    info!("📤 [EXPORT] 已导出 {} ~ {} 的 {} 个订单 ({} 行) 到 {}", from, to, orders.len(), rows, path);
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn named(names: &[&str]) -> Vec<Column> {
+        resolve_columns(&names.iter().map(|n| n.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    fn csv_cells(row: &[Cell]) -> Vec<String> {
+        row.iter().map(Cell::to_csv).collect()
+    }
+
+    #[test]
+    fn unknown_columns_are_rejected() {
+        let names: Vec<&str> = resolve_columns(&[]).unwrap().iter().map(|c| c.name).collect();
+        assert_eq!(names, DEFAULT_COLUMNS);
+
+        let error = resolve_columns(&["order_id".to_string(), "tips".to_string()]).err();
+        assert!(matches!(error, Some(AppError::InvalidArgument(message)) if message.contains("tips")));
+    }
+
+    #[test]
+    fn rows_follow_item_setting() {
+        let orders = [crate::sample_test_order()];
+        let columns = named(&["order_id", "total", "item_name", "item_amount"]);
+
+        let per_item = table_rows(&orders, &columns, true);
+        assert_eq!(per_item.len(), 3);
+        assert_eq!(csv_cells(&per_item[1]), ["23410121749595834", "65.82", "宫保鸡丁 (Kung Pao Chicken)", "2"]);
+
+        let per_order = table_rows(&orders, &columns, false);
+        assert_eq!(per_order.len(), 1);
+        assert_eq!(csv_cells(&per_order[0]), ["23410121749595834", "65.82", "", ""]);
+
+        // 没有菜品列时每个订单一行
+        assert_eq!(table_rows(&orders, &named(&["order_id"]), true).len(), 1);
+    }
+
+    #[test]
+    fn money_is_normalised_to_two_decimals() {
+        let mut order = crate::sample_test_order();
+        order.total = "65.8".to_string();
+        order.tip_fee = String::new();
+        order.dishes_array[0].price = " 19 ".to_string();
+        let columns = named(&["total", "tip_fee", "item_price"]);
+
+        let rows = table_rows(&[order], &columns, true);
+        assert_eq!(csv_cells(&rows[0]), ["65.80", "0.00", "19.00"]);
+        assert_eq!(rows[0][0].to_json(), serde_json::json!(65.8));
+    }
+
+    #[test]
+    fn csv_quotes_names_with_commas() {
+        let mut order = crate::sample_test_order();
+        order.recipient_name = "Zhang, San".to_string();
+        order.order_notes = "说 \"不要辣\"".to_string();
+        let columns = named(&["recipient_name", "order_notes", "total"]);
+
+        let dir = crate::get_app_data_dir();
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("export_quoting.csv");
+        let path = path.to_str().unwrap();
+        assert_eq!(write_csv(path, &[order], &columns, true).unwrap(), 1);
+
+        let csv = fs::read_to_string(path).unwrap();
+        let _ = fs::remove_file(path);
+        assert_eq!(
+            csv,
+            "\u{FEFF}recipient_name,order_notes,total\r\n\"Zhang, San\",\"说 \"\"不要辣\"\"\",65.82\r\n"
+        );
+    }
+}
@@ -0,0 +1,485 @@
+// 本地订单历史 (order_history.db)
+//
+// 收到或打印过的订单连同完整的 OrderData 保存到SQLite，记录每次打印尝试和使用的打印机。
+// 接口很慢或断网时也可以按订单号、电话、顾客姓名、日期和状态查找订单，并用保存的副本补打。
+
+use log::{info, warn};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+use crate::error::AppError;
+use crate::OrderData;
+
+const DEFAULT_SEARCH_LIMIT: u32 = 50;
+const MAX_SEARCH_LIMIT: u32 = 500;
+
+// orders.user_id 是下单顾客的ID (游客订单都是110)，按门店筛选时使用 rd_id
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS orders (
+        order_id        TEXT PRIMARY KEY,
+        user_id         TEXT NOT NULL,
+        rd_id           INTEGER NOT NULL,
+        order_status    INTEGER NOT NULL,
+        recipient_name  TEXT NOT NULL,
+        recipient_phone TEXT NOT NULL,
+        phone_digits    TEXT NOT NULL,
+        total           TEXT NOT NULL,
+        create_time     TEXT NOT NULL,
+        order_json      TEXT NOT NULL,
+        source          TEXT NOT NULL,
+        received_at     TEXT NOT NULL,
+        updated_at      TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_orders_create_time ON orders (create_time);
+    CREATE INDEX IF NOT EXISTS idx_orders_phone ON orders (phone_digits);
+    CREATE TABLE IF NOT EXISTS print_attempts (
+        id           INTEGER PRIMARY KEY AUTOINCREMENT,
+        order_id     TEXT NOT NULL,
+        printer      TEXT NOT NULL,
+        success      INTEGER NOT NULL,
+        error        TEXT,
+        attempted_at TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_print_attempts_order ON print_attempts (order_id);
+";
+
+// 查询条件，所有字段都可以省略
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct OrderSearchQuery {
+    pub order_id: Option<String>,   // 订单号 (部分匹配)
+    pub phone: Option<String>,      // 电话 (只比较数字，部分匹配)
+    pub name: Option<String>,       // 顾客姓名 (部分匹配，不区分大小写)
+    pub date_from: Option<String>,  // 下单日期 YYYY-MM-DD (包含)
+    pub date_to: Option<String>,    // 下单日期 YYYY-MM-DD (包含)
+    pub status: Option<i32>,        // 订单状态
+    pub user_id: Option<String>,    // 下单顾客ID (游客为110)，不是商家账号
+    pub rd_id: Option<i32>,         // 门店
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct OrderHistoryEntry {
+    pub order_id: String,
+    pub order_status: i32,
+    pub recipient_name: String,
+    pub recipient_phone: String,
+    pub total: String,
+    pub create_time: String,
+    pub received_at: String,
+    pub print_count: u32,          // 成功打印的次数 (按打印机计)
+    pub printers: Vec<String>,     // 打印成功过的打印机
+    pub last_printed_at: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct PrintAttempt {
+    pub printer: String,
+    pub success: bool,
+    pub error: Option<String>,
+    pub attempted_at: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct OrderHistoryDetail {
+    pub order: OrderData,
+    pub source: String,
+    pub received_at: String,
+    pub updated_at: String,
+    pub attempts: Vec<PrintAttempt>,
+}
+
+fn db_file_path() -> std::path::PathBuf {
+    crate::get_app_data_dir().join("order_history.db")
+}
+
+fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+fn phone_digits(phone: &str) -> String {
+    phone.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+// 数据库在第一次使用时打开
+#[derive(Default)]
+pub(crate) struct OrderHistory {
+    conn: Mutex<Option<Connection>>,
+}
+
+impl OrderHistory {
+    // 测试用的内存数据库
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        OrderHistory { conn: Mutex::new(Some(conn)) }
+    }
+
+    fn with_conn<T>(&self, f: impl FnOnce(&mut Connection) -> Result<T, AppError>) -> Result<T, AppError> {
+        let mut guard = self.conn.lock().unwrap();
+        if guard.is_none() {
+            let path = db_file_path();
+            let conn = Connection::open(&path)?;
+            conn.execute_batch(SCHEMA)?;
+            info!("🗄️ [HISTORY] 订单历史数据库已打开: {}", path.display());
+            *guard = Some(conn);
+        }
+        f(guard.as_mut().unwrap())
+    }
+
+    // 保存或更新订单 (保留第一次收到的时间)
+    pub fn record_order(&self, order: &OrderData, source: &str) {
+        let result = self.with_conn(|conn| {
+            let order_json = serde_json::to_string(order)?;
+            let now = now();
+            conn.execute(
+                "INSERT INTO orders (order_id, user_id, rd_id, order_status, recipient_name, recipient_phone,
+                                     phone_digits, total, create_time, order_json, source, received_at, updated_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?12)
+                 ON CONFLICT(order_id) DO UPDATE SET
+                     user_id = excluded.user_id,
+                     rd_id = excluded.rd_id,
+                     order_status = excluded.order_status,
+                     recipient_name = excluded.recipient_name,
+                     recipient_phone = excluded.recipient_phone,
+                     phone_digits = excluded.phone_digits,
+                     total = excluded.total,
+                     create_time = excluded.create_time,
+                     order_json = excluded.order_json,
+                     updated_at = excluded.updated_at",
+                params![
+                    order.order_id,
+                    order.user_id,
+                    order.rd_id,
+                    order.order_status,
+                    order.recipient_name,
+                    order.recipient_phone,
+                    phone_digits(&order.recipient_phone),
+                    order.total,
+                    order.create_time,
+                    order_json,
+                    source,
+                    now,
+                ],
+            )?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            warn!("⚠️ [HISTORY] 保存订单 {} 失败: {}", order.order_id, e);
+        }
+    }
+
+    pub fn record_orders(&self, orders: &[OrderData], source: &str) {
+        for order in orders {
+            self.record_order(order, source);
+        }
+    }
+
+    // 记录一次打印尝试
+    pub fn record_print_attempt(&self, order_id: &str, printer: &str, error: Option<&AppError>) {
+        let result = self.with_conn(|conn| {
+            conn.execute(
+                "INSERT INTO print_attempts (order_id, printer, success, error, attempted_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![order_id, printer, error.is_none(), error.map(|e| e.to_string()), now()],
+            )?;
+            Ok(())
+        });
+        if let Err(e) = result {
+            warn!("⚠️ [HISTORY] 记录订单 {} 的打印结果失败: {}", order_id, e);
+        }
+    }
+
+    // 读取保存的订单副本
+    pub fn get_order(&self, order_id: &str) -> Result<Option<OrderData>, AppError> {
+        self.with_conn(|conn| {
+            let json: Option<String> = conn
+                .query_row("SELECT order_json FROM orders WHERE order_id = ?1", [order_id], |row| row.get(0))
+                .optional()?;
+            match json {
+                Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    pub fn get_detail(&self, order_id: &str) -> Result<Option<OrderHistoryDetail>, AppError> {
+        self.with_conn(|conn| {
+            let row: Option<(String, String, String, String)> = conn
+                .query_row(
+                    "SELECT order_json, source, received_at, updated_at FROM orders WHERE order_id = ?1",
+                    [order_id],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+                )
+                .optional()?;
+            let Some((json, source, received_at, updated_at)) = row else {
+                return Ok(None);
+            };
+
+            let mut stmt = conn.prepare(
+                "SELECT printer, success, error, attempted_at FROM print_attempts
+                 WHERE order_id = ?1 ORDER BY id",
+            )?;
+            let attempts = stmt
+                .query_map([order_id], |row| {
+                    Ok(PrintAttempt {
+                        printer: row.get(0)?,
+                        success: row.get(1)?,
+                        error: row.get(2)?,
+                        attempted_at: row.get(3)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(Some(OrderHistoryDetail {
+                order: serde_json::from_str(&json)?,
+                source,
+                received_at,
+                updated_at,
+                attempts,
+            }))
+        })
+    }
+
+    // 按条件查找订单，按下单时间倒序
+    pub fn search(&self, query: &OrderSearchQuery) -> Result<Vec<OrderHistoryEntry>, AppError> {
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+
+        if let Some(order_id) = non_empty(&query.order_id) {
+            conditions.push("o.order_id LIKE ?");
+            values.push(format!("%{}%", order_id).into());
+        }
+        if let Some(phone) = non_empty(&query.phone) {
+            let digits = phone_digits(phone);
+            if digits.is_empty() {
+                return Err(AppError::InvalidArgument(format!("电话号码无效: {}", phone)));
+            }
+            conditions.push("o.phone_digits LIKE ?");
+            values.push(format!("%{}%", digits).into());
+        }
+        if let Some(name) = non_empty(&query.name) {
+            conditions.push("o.recipient_name LIKE ?");
+            values.push(format!("%{}%", name).into());
+        }
+        if let Some(date_from) = non_empty(&query.date_from) {
+            conditions.push("o.create_time >= ?");
+            values.push(parse_date(date_from)?.into());
+        }
+        if let Some(date_to) = non_empty(&query.date_to) {
+            // 包含当天：小于第二天0点
+            let next_day = chrono::NaiveDate::parse_from_str(&parse_date(date_to)?, "%Y-%m-%d")
+                .map(|d| d.succ_opt().unwrap_or(d))
+                .map_err(|e| AppError::InvalidArgument(e.to_string()))?;
+            conditions.push("o.create_time < ?");
+            values.push(next_day.format("%Y-%m-%d").to_string().into());
+        }
+        if let Some(status) = query.status {
+            conditions.push("o.order_status = ?");
+            values.push(i64::from(status).into());
+        }
+        if let Some(user_id) = non_empty(&query.user_id) {
+            conditions.push("o.user_id = ?");
+            values.push(user_id.to_string().into());
+        }
+        if let Some(rd_id) = query.rd_id {
+            conditions.push("o.rd_id = ?");
+            values.push(i64::from(rd_id).into());
+        }
+
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT);
+        values.push(i64::from(limit).into());
+        values.push(i64::from(query.offset.unwrap_or(0)).into());
+
+        let sql = format!(
+            "SELECT o.order_id, o.order_status, o.recipient_name, o.recipient_phone, o.total, o.create_time,
+                    o.received_at,
+                    (SELECT COUNT(*) FROM print_attempts p WHERE p.order_id = o.order_id AND p.success = 1),
+                    (SELECT GROUP_CONCAT(DISTINCT p.printer) FROM print_attempts p
+                        WHERE p.order_id = o.order_id AND p.success = 1),
+                    (SELECT MAX(p.attempted_at) FROM print_attempts p WHERE p.order_id = o.order_id AND p.success = 1)
+             FROM orders o {}
+             ORDER BY o.create_time DESC, o.received_at DESC
+             LIMIT ? OFFSET ?",
+            where_clause
+        );
+
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(&sql)?;
+            let entries = stmt
+                .query_map(params_from_iter(values.iter()), |row| {
+                    let printers: Option<String> = row.get(8)?;
+                    Ok(OrderHistoryEntry {
+                        order_id: row.get(0)?,
+                        order_status: row.get(1)?,
+                        recipient_name: row.get(2)?,
+                        recipient_phone: row.get(3)?,
+                        total: row.get(4)?,
+                        create_time: row.get(5)?,
+                        received_at: row.get(6)?,
+                        print_count: row.get(7)?,
+                        printers: printers
+                            .map(|p| p.split(',').map(str::to_string).collect())
+                            .unwrap_or_default(),
+                        last_printed_at: row.get(9)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(entries)
+        })
+    }
+
+    // 读取下单时间在 [start, end) 内的订单，start/end 为 YYYY-MM-DD；rd_id 为 None 时不限门店
+    pub fn orders_between(&self, rd_id: Option<i32>, start: &str, end: &str) -> Result<Vec<OrderData>, AppError> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT order_json FROM orders
+                 WHERE create_time >= ?1 AND create_time < ?2 AND (?3 IS NULL OR rd_id = ?3)
+                 ORDER BY create_time",
+            )?;
+            let rows = stmt
+                .query_map(params![start, end, rd_id], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows
+                .iter()
+                .filter_map(|json| serde_json::from_str::<OrderData>(json).ok())
+                .collect())
+        })
+    }
+
+    // 按下单时间倒序分页读取门店保存的订单 (接口不可用时代替订单列表)
+    pub fn recent_orders(&self, rd_id: i32, page: i32, per_page: i32) -> Result<Vec<OrderData>, AppError> {
+        let per_page = per_page.clamp(1, MAX_SEARCH_LIMIT as i32);
+        let offset = (page.max(1) - 1) * per_page;
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT order_json FROM orders WHERE rd_id = ?1
+                 ORDER BY create_time DESC, received_at DESC LIMIT ?2 OFFSET ?3",
+            )?;
+            let rows = stmt
+                .query_map(params![rd_id, per_page, offset], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows
+                .iter()
+                .filter_map(|json| serde_json::from_str::<OrderData>(json).ok())
+                .collect())
+        })
+    }
+}
+
+// 日期必须是 YYYY-MM-DD
+fn parse_date(value: &str) -> Result<String, AppError> {
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .map_err(|_| AppError::InvalidArgument(format!("日期格式应为 YYYY-MM-DD: {}", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    // 同一门店不同顾客 (含游客110) 的订单
+    fn order(order_id: &str, customer_id: &str, rd_id: i32, create_time: &str) -> OrderData {
+        let mut order = test_support::order(order_id);
+        order.user_id = customer_id.to_string();
+        order.rd_id = rd_id;
+        order.create_time = create_time.to_string();
+        order
+    }
+
+    fn ids(orders: &[OrderData]) -> Vec<&str> {
+        orders.iter().map(|o| o.order_id.as_str()).collect()
+    }
+
+    fn history() -> OrderHistory {
+        let history = OrderHistory::in_memory();
+        history.record_orders(
+            &[
+                order("H1", "110", 7, "2026-10-01 11:00:00"),
+                order("H2", "5521", 7, "2026-10-01 18:30:00"),
+                order("H3", "110", 8, "2026-10-01 12:00:00"),
+                order("H4", "5521", 7, "2026-10-02 09:00:00"),
+            ],
+            "api",
+        );
+        history
+    }
+
+    #[test]
+    fn recent_orders_include_every_customer_of_the_store() {
+        let history = history();
+        assert_eq!(ids(&history.recent_orders(7, 1, 10).unwrap()), ["H4", "H2", "H1"]);
+        assert_eq!(ids(&history.recent_orders(7, 2, 2).unwrap()), ["H1"]);
+        assert_eq!(ids(&history.recent_orders(8, 1, 10).unwrap()), ["H3"]);
+    }
+
+    #[test]
+    fn orders_between_filters_by_store_and_date() {
+        let history = history();
+        assert_eq!(ids(&history.orders_between(Some(7), "2026-10-01", "2026-10-02").unwrap()), ["H1", "H2"]);
+        assert_eq!(ids(&history.orders_between(None, "2026-10-01", "2026-10-02").unwrap()), ["H1", "H3", "H2"]);
+        assert!(history.orders_between(Some(9), "2026-10-01", "2026-10-03").unwrap().is_empty());
+    }
+
+    fn search_ids(history: &OrderHistory, query: OrderSearchQuery) -> Vec<String> {
+        history.search(&query).unwrap().into_iter().map(|e| e.order_id).collect()
+    }
+
+    #[test]
+    fn search_matches_each_filter() {
+        let history = OrderHistory::in_memory();
+        let mut wang = order("S-1001", "110", 7, "2026-10-01 11:00:00");
+        wang.recipient_name = "Wang Fang".to_string();
+        wang.recipient_phone = "(555) 010-2233".to_string();
+        wang.order_status = 10;
+        let mut li = order("S-1002", "5521", 7, "2026-10-02 18:30:00");
+        li.recipient_name = "李雷".to_string();
+        li.recipient_phone = "555-987-6543".to_string();
+        li.order_status = 2;
+        let mut other_store = order("S-2001", "5521", 8, "2026-10-02 12:00:00");
+        other_store.recipient_phone = "555 010 2233".to_string();
+        history.record_orders(&[wang, li, other_store], "api");
+
+        let query = |f: fn(&mut OrderSearchQuery)| {
+            let mut query = OrderSearchQuery::default();
+            f(&mut query);
+            query
+        };
+
+        assert_eq!(search_ids(&history, query(|q| q.order_id = Some("1002".into()))), ["S-1002"]);
+        // 电话只比较数字，格式不同也能找到
+        assert_eq!(search_ids(&history, query(|q| q.phone = Some("010-2233".into()))), ["S-2001", "S-1001"]);
+        assert_eq!(search_ids(&history, query(|q| q.name = Some("wang".into()))), ["S-1001"]);
+        assert_eq!(search_ids(&history, query(|q| q.name = Some("李".into()))), ["S-1002"]);
+        assert_eq!(
+            search_ids(&history, query(|q| {
+                q.date_from = Some("2026-10-02".into());
+                q.date_to = Some("2026-10-02".into());
+            })),
+            ["S-1002", "S-2001"]
+        );
+        assert_eq!(search_ids(&history, query(|q| q.date_to = Some("2026-10-01".into()))), ["S-1001"]);
+        assert_eq!(search_ids(&history, query(|q| q.status = Some(10))), ["S-1001"]);
+        assert_eq!(
+            search_ids(&history, query(|q| {
+                q.phone = Some("0102233".into());
+                q.rd_id = Some(7);
+            })),
+            ["S-1001"]
+        );
+        assert!(history.search(&query(|q| q.phone = Some("--".into()))).is_err());
+    }
+}
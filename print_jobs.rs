@@ -0,0 +1,288 @@
+// 打印任务队列
+//
+// 所有订单打印 (WebSocket推送、漏单补打、界面手动打印、本地HTTP接口、无界面后台服务和命令行 print) 都进入这个队列，
+// 由一个后台任务按顺序打印，避免多个来源同时写同一台打印机。订单任务按已打印台账去重，
+// force 为 true 的任务 (手动重新打印) 不去重，也不发送 new-order 事件。
+
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::error::AppError;
+use crate::events::Ctx;
+use crate::OrderData;
+
+// 保留最近多少个任务的状态供查询
+const MAX_TRACKED_JOBS: usize = 200;
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(crate) enum JobStatus {
+    Queued,
+    Printing,
+    Completed { printers: usize },
+    Skipped { reason: String },
+    Failed { error: AppError },
+}
+
+impl JobStatus {
+    fn is_pending(&self) -> bool {
+        matches!(self, JobStatus::Queued | JobStatus::Printing)
+    }
+
+    // 订单任务的结果: 打印成功的打印机数量，已打印过被跳过时为 0
+    pub fn into_result(self) -> Result<usize, AppError> {
+        match self {
+            JobStatus::Completed { printers } => Ok(printers),
+            JobStatus::Skipped { .. } => Ok(0),
+            JobStatus::Failed { error } => Err(error),
+            JobStatus::Queued | JobStatus::Printing => Err(AppError::Internal("打印任务尚未完成".to_string())),
+        }
+    }
+}
+
+pub(crate) enum JobPayload {
+    // 按正常订单流程打印到所有启用的打印机；force 为 true 时忽略已打印台账
+    Order { order: Box<OrderData>, force: bool },
+    // 原始ESC/POS数据，直接发送到指定打印机
+    Raw { printer: String, data: Vec<u8> },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct PrintJob {
+    pub id: String,
+    pub kind: &'static str,
+    pub order_id: Option<String>,
+    pub printer: Option<String>,
+    pub source: String,
+    pub submitted_at: String,
+    pub finished_at: Option<String>,
+    #[serde(flatten)]
+    pub status: JobStatus,
+}
+
+impl PrintJob {
+    fn is_pending(&self) -> bool {
+        self.status.is_pending()
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct JobQueue {
+    jobs: Mutex<VecDeque<PrintJob>>,
+    sender: Mutex<Option<mpsc::UnboundedSender<(String, JobPayload)>>>,
+    next_id: AtomicU64,
+    // 等待任务完成的调用方 (界面手动打印、补打)，先锁 jobs 再锁 waiters
+    waiters: Mutex<HashMap<String, Vec<oneshot::Sender<JobStatus>>>>,
+}
+
+impl JobQueue {
+    // 启动打印任务 (应用启动时调用一次)
+    pub fn start_worker(&self, ctx: Ctx) {
+        let (tx, mut rx) = mpsc::unbounded_channel::<(String, JobPayload)>();
+        *self.sender.lock().unwrap() = Some(tx);
+
+        tauri::async_runtime::spawn(async move {
+            while let Some((job_id, payload)) = rx.recv().await {
+                let queue = &ctx.app_state().print_jobs;
+                queue.update(&job_id, JobStatus::Printing);
+                let status = run_job(&ctx, payload).await;
+                queue.update(&job_id, status);
+            }
+        });
+    }
+
+    // 提交任务；同一订单已有未完成的任务时返回该任务
+    pub fn submit(&self, payload: JobPayload, source: &str) -> Result<PrintJob, AppError> {
+        let (kind, order_id, printer) = match &payload {
+            JobPayload::Order { order, .. } => ("order", Some(order.order_id.clone()), None),
+            JobPayload::Raw { printer, .. } => ("raw", None, Some(printer.clone())),
+        };
+
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(order_id) = &order_id {
+            if let Some(existing) = jobs.iter().find(|j| j.is_pending() && j.order_id.as_ref() == Some(order_id)) {
+                info!("🧾 [JOBS] 订单 {} 已在队列中 ({})", order_id, existing.id);
+                return Ok(existing.clone());
+            }
+        }
+
+        let job = PrintJob {
+            id: format!("job-{}", self.next_id.fetch_add(1, Ordering::SeqCst) + 1),
+            kind,
+            order_id,
+            printer,
+            source: source.to_string(),
+            submitted_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            finished_at: None,
+            status: JobStatus::Queued,
+        };
+
+        let sender = self.sender.lock().unwrap();
+        let sender = sender.as_ref().ok_or_else(|| AppError::Internal("打印队列尚未启动".to_string()))?;
+        sender
+            .send((job.id.clone(), payload))
+            .map_err(|_| AppError::Internal("打印队列已停止".to_string()))?;
+
+        info!("🧾 [JOBS] 新任务 {} ({}, 来源: {})", job.id, job.kind, job.source);
+        jobs.push_back(job.clone());
+        while jobs.len() > MAX_TRACKED_JOBS {
+            jobs.pop_front();
+        }
+        Ok(job)
+    }
+
+    // 提交任务并等待打印完成，返回最终状态 (同一订单已有未完成的任务时等待该任务)
+    pub async fn submit_and_wait(&self, payload: JobPayload, source: &str) -> Result<JobStatus, AppError> {
+        let job = self.submit(payload, source)?;
+        let receiver = {
+            let jobs = self.jobs.lock().unwrap();
+            if let Some(current) = jobs.iter().find(|j| j.id == job.id).filter(|j| !j.is_pending()) {
+                return Ok(current.status.clone());
+            }
+            let (tx, rx) = oneshot::channel();
+            self.waiters.lock().unwrap().entry(job.id.clone()).or_default().push(tx);
+            rx
+        };
+        receiver.await.map_err(|_| AppError::Internal("打印队列已停止".to_string()))
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<PrintJob> {
+        self.jobs.lock().unwrap().iter().find(|j| j.id == job_id).cloned()
+    }
+
+    // 最近的任务，最新的在前
+    pub fn list(&self) -> Vec<PrintJob> {
+        self.jobs.lock().unwrap().iter().rev().cloned().collect()
+    }
+
+    fn update(&self, job_id: &str, status: JobStatus) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if !status.is_pending() {
+            for waiter in self.waiters.lock().unwrap().remove(job_id).unwrap_or_default() {
+                let _ = waiter.send(status.clone());
+            }
+        }
+        if let Some(job) = jobs.iter_mut().find(|j| j.id == job_id) {
+            if !status.is_pending() {
+                job.finished_at = Some(chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+            }
+            job.status = status;
+        }
+    }
+}
+
+async fn run_job(ctx: &Ctx, payload: JobPayload) -> JobStatus {
+    match payload {
+        JobPayload::Order { order, force } => {
+            if !force && ctx.app_state().order_ledger.lock().unwrap().is_printed(&order.order_id) {
+                info!("🧾 [JOBS] 订单 {} 已打印过，跳过", order.order_id);
+                return JobStatus::Skipped { reason: "already_printed".to_string() };
+            }
+
+            if !force {
+                ctx.emit("new-order", &*order);
+            }
+            match crate::print_order(*order, ctx).await {
+                Ok(0) => JobStatus::Failed {
+                    error: AppError::Internal("所有打印机都打印失败".to_string()),
+                },
+                Ok(printers) => JobStatus::Completed { printers },
+                Err(error) => JobStatus::Failed { error },
+            }
+        }
+        JobPayload::Raw { printer, data } => {
+            match crate::print_raw_to_printer(&printer, data).await {
+                Ok(_) => JobStatus::Completed { printers: 1 },
+                Err(e) => {
+                    warn!("❌ [JOBS] 原始数据打印失败: {}", e);
+                    JobStatus::Failed { error: AppError::print_failed(&printer, e) }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, TestContext};
+
+    fn order_job(order_id: &str, force: bool) -> JobPayload {
+        JobPayload::Order { order: Box::new(test_support::order(order_id)), force }
+    }
+
+    #[test]
+    fn submit_fails_before_worker_starts() {
+        let queue = JobQueue::default();
+        assert!(queue.submit(order_job("JOB-0", false), "test").is_err());
+        assert!(queue.list().is_empty());
+    }
+
+    #[test]
+    fn pending_job_for_same_order_is_reused() {
+        let queue = JobQueue::default();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        *queue.sender.lock().unwrap() = Some(tx);
+
+        let first = queue.submit(order_job("JOB-1", false), "websocket").unwrap();
+        let again = queue.submit(order_job("JOB-1", false), "catch-up").unwrap();
+        let other = queue.submit(order_job("JOB-2", false), "websocket").unwrap();
+
+        assert_eq!(first.id, again.id);
+        assert_ne!(first.id, other.id);
+        assert_eq!(queue.list().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn order_job_prints_and_records_ledger() {
+        let context = TestContext::new();
+        let ctx = context.ctx();
+        let queue = &ctx.app_state().print_jobs;
+        queue.start_worker(ctx.clone());
+
+        let status = queue.submit_and_wait(order_job("JOB-3", false), "test").await.unwrap();
+
+        assert!(matches!(status, JobStatus::Completed { printers: 1 }), "{:?}", status);
+        assert!(ctx.app_state().order_ledger.lock().unwrap().is_printed("JOB-3"));
+        assert_eq!(context.events_named("new-order").len(), 1);
+        let job = queue.list().remove(0);
+        assert!(job.finished_at.is_some());
+        assert!(matches!(job.status, JobStatus::Completed { .. }));
+    }
+
+    #[tokio::test]
+    async fn printed_order_is_skipped_unless_forced() {
+        let context = TestContext::new();
+        let ctx = context.ctx();
+        let queue = &ctx.app_state().print_jobs;
+        queue.start_worker(ctx.clone());
+        ctx.app_state().order_ledger.lock().unwrap().mark_printed("JOB-4");
+
+        let status = queue.submit_and_wait(order_job("JOB-4", false), "test").await.unwrap();
+        assert!(matches!(&status, JobStatus::Skipped { reason } if reason == "already_printed"), "{:?}", status);
+        assert_eq!(status.into_result().unwrap(), 0);
+
+        // 手动重新打印不去重，也不再次提示新订单
+        let status = queue.submit_and_wait(order_job("JOB-4", true), "manual").await.unwrap();
+        assert!(matches!(status, JobStatus::Completed { printers: 1 }), "{:?}", status);
+        assert!(context.events_named("new-order").is_empty());
+    }
+
+    #[tokio::test]
+    async fn order_job_fails_without_enabled_printers() {
+        let context = TestContext::new();
+        let ctx = context.ctx();
+        ctx.app_state().printers.lock().unwrap().clear();
+        let queue = &ctx.app_state().print_jobs;
+        queue.start_worker(ctx.clone());
+
+        let status = queue.submit_and_wait(order_job("JOB-5", false), "test").await.unwrap();
+
+        assert!(matches!(status, JobStatus::Failed { error: AppError::NoEnabledPrinters }), "{:?}", status);
+        assert!(!ctx.app_state().order_ledger.lock().unwrap().is_printed("JOB-5"));
+    }
+}
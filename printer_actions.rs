@@ -0,0 +1,231 @@
+// 订单触发的打印机附加动作：开钱箱 (ESC p) 和蜂鸣器
+//
+// 规则 (config.json 的 printer_actions 段落) 按订单类型、付款方式和打印机角色匹配，
+// 默认到店付款的订单在前台打印机上开钱箱，厨房打印机收到新订单时鸣叫。
+// 只在订单第一次打印时触发，补打和从历史记录重新打印默认不触发。
+//
+// 命令字节来自打印机配置库 (drawer_command / buzzer_command，不同厂商写法不同)，
+// 可以在打印机手动设置中覆盖。命令插在小票开头的 ESC @ 之后，和小票作为同一个打印任务发送。
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::printer_profiles;
+use crate::{OrderData, PrinterConfig};
+
+// 打印机在店里的用途
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PrinterRole {
+    #[default]
+    General, // 未指定 (只有一台打印机的店)
+    Counter, // 前台/收银
+    Kitchen, // 厨房
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PrinterAction {
+    Drawer,
+    Buzzer,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct ActionRule {
+    pub action: PrinterAction,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // 以下条件为空表示不限
+    #[serde(default)]
+    pub order_types: Vec<String>, // "delivery" / "pickup"
+    #[serde(default)]
+    pub paystyles: Vec<i32>, // 0=到店付款, 1=在线付款
+    #[serde(default)]
+    pub roles: Vec<PrinterRole>,
+    // 订单已经打印过时 (补打、重新打印) 是否也触发
+    #[serde(default)]
+    pub on_reprint: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+const ORDER_TYPES: [&str; 2] = ["delivery", "pickup"];
+
+pub(crate) fn default_rules() -> Vec<ActionRule> {
+    vec![
+        ActionRule {
+            action: PrinterAction::Drawer,
+            enabled: true,
+            order_types: Vec::new(),
+            paystyles: vec![0],
+            roles: vec![PrinterRole::General, PrinterRole::Counter],
+            on_reprint: false,
+        },
+        ActionRule {
+            action: PrinterAction::Buzzer,
+            enabled: true,
+            order_types: Vec::new(),
+            paystyles: Vec::new(),
+            roles: vec![PrinterRole::Kitchen],
+            on_reprint: false,
+        },
+    ]
+}
+
+pub(crate) fn validate_rules(rules: &[ActionRule]) -> Result<(), AppError> {
+    for rule in rules {
+        if let Some(unknown) = rule
+            .order_types
+            .iter()
+            .find(|t| !ORDER_TYPES.contains(&t.to_lowercase().as_str()))
+        {
+            return Err(AppError::InvalidArgument(format!("未知的订单类型: {} (可选 delivery、pickup)", unknown)));
+        }
+    }
+    Ok(())
+}
+
+fn matches(rule: &ActionRule, order: &OrderData, role: PrinterRole, first_print: bool) -> bool {
+    let order_type = crate::get_order_type_text(order);
+    rule.enabled
+        && (first_print || rule.on_reprint)
+        && (rule.order_types.is_empty() || rule.order_types.iter().any(|t| t.eq_ignore_ascii_case(order_type)))
+        && (rule.paystyles.is_empty() || rule.paystyles.contains(&order.paystyle))
+        && (rule.roles.is_empty() || rule.roles.contains(&role))
+}
+
+// 打印机配置中该动作的命令，不支持时为 None
+fn command(printer_name: &str, action: PrinterAction) -> Option<String> {
+    let profile = printer_profiles::resolve(printer_name).profile;
+    match action {
+        PrinterAction::Drawer => profile.drawer_sequence(),
+        PrinterAction::Buzzer => profile.buzzer_sequence(),
+    }
+}
+
+// 按规则在小票开头插入开钱箱/蜂鸣器命令
+pub(crate) fn apply(content: String, order: &OrderData, printer: &PrinterConfig, rules: &[ActionRule], first_print: bool) -> String {
+    let mut actions: Vec<PrinterAction> = Vec::new();
+    for rule in rules.iter().filter(|r| matches(r, order, printer.role, first_print)) {
+        if !actions.contains(&rule.action) {
+            actions.push(rule.action);
+        }
+    }
+    if actions.is_empty() {
+        return content;
+    }
+
+    let mut commands = String::new();
+    for action in actions {
+        match command(&printer.name, action) {
+            Some(sequence) => {
+                info!("🔔 [ACTIONS] 订单 {} 在 {} 上触发 {:?}", order.order_id, printer.name, action);
+                commands.push_str(&sequence);
+            }
+            None => warn!("⚠️ [ACTIONS] 打印机 {} 不支持 {:?}，已跳过", printer.name, action),
+        }
+    }
+
+    match content.strip_prefix("\x1B@") {
+        Some(rest) => format!("\x1B@{}{}", commands, rest),
+        None => format!("{}{}", commands, content),
+    }
+}
+
+// 开钱箱使用的打印机：启用的前台打印机，其次是未指定用途的，最后是系统默认打印机
+pub(crate) fn drawer_printer(printers: &[PrinterConfig]) -> Option<String> {
+    let enabled = || printers.iter().filter(|p| p.is_enabled);
+    enabled()
+        .find(|p| p.role == PrinterRole::Counter)
+        .or_else(|| enabled().find(|p| p.role == PrinterRole::General))
+        .or_else(|| printers.iter().find(|p| p.is_default))
+        .map(|p| p.name.clone())
+}
+
+// 单独开钱箱的原始数据
+pub(crate) fn drawer_job(printer_name: &str) -> Result<Vec<u8>, AppError> {
+    let sequence = command(printer_name, PrinterAction::Drawer).ok_or_else(|| {
+        AppError::InvalidArgument(format!(
+            "打印机 {} 没有钱箱接口，可以在打印机手动设置中开启 drawer",
+            printer_name
+        ))
+    })?;
+    Ok(format!("\x1B@{}", sequence).into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, TEST_PRINTER};
+
+    // 打印机配置库中 Xprinter 80mm 的命令
+    const DRAWER: &str = "\x1B\x70\x00\x19\x78";
+    const BUZZER: &str = "\x1B\x42\x03\x02";
+
+    fn printer(role: PrinterRole) -> PrinterConfig {
+        let mut printer = test_support::printer(TEST_PRINTER);
+        printer.role = role;
+        printer
+    }
+
+    fn order(paystyle: i32, delivery_style: i32) -> OrderData {
+        let mut order = test_support::order("ACT-1");
+        order.paystyle = paystyle;
+        order.delivery_style = delivery_style;
+        order
+    }
+
+    #[test]
+    fn cash_orders_open_the_counter_drawer() {
+        let rules = default_rules();
+        let receipt = || "\x1B@RECEIPT".to_string();
+
+        let counter = apply(receipt(), &order(0, 0), &printer(PrinterRole::Counter), &rules, true);
+        assert_eq!(counter, format!("\x1B@{}RECEIPT", DRAWER));
+
+        let kitchen = apply(receipt(), &order(0, 0), &printer(PrinterRole::Kitchen), &rules, true);
+        assert_eq!(kitchen, format!("\x1B@{}RECEIPT", BUZZER));
+
+        // 在线付款不开钱箱
+        assert_eq!(apply(receipt(), &order(1, 0), &printer(PrinterRole::Counter), &rules, true), receipt());
+    }
+
+    #[test]
+    fn reprints_only_trigger_rules_marked_on_reprint() {
+        let mut rules = default_rules();
+        let counter = printer(PrinterRole::Counter);
+        assert!(matches(&rules[0], &order(0, 0), counter.role, true));
+        assert!(!matches(&rules[0], &order(0, 0), counter.role, false));
+        assert_eq!(apply("\x1B@RECEIPT".to_string(), &order(0, 0), &counter, &rules, false), "\x1B@RECEIPT");
+
+        rules[0].on_reprint = true;
+        assert!(matches(&rules[0], &order(0, 0), counter.role, false));
+    }
+
+    #[test]
+    fn rules_match_order_type_and_skip_disabled() {
+        let mut rule = default_rules().remove(0);
+        rule.order_types = vec!["Delivery".to_string()];
+        assert!(matches(&rule, &order(0, 1), PrinterRole::General, true));
+        assert!(!matches(&rule, &order(0, 0), PrinterRole::General, true));
+
+        rule.enabled = false;
+        assert!(!matches(&rule, &order(0, 1), PrinterRole::General, true));
+    }
+
+    #[test]
+    fn commands_follow_the_initial_reset() {
+        let rules = default_rules();
+        let counter = printer(PrinterRole::Counter);
+
+        // 多联打印时只在开头插入一次
+        let copies = apply("\x1B@ONE\x1B@TWO".to_string(), &order(0, 0), &counter, &rules, true);
+        assert_eq!(copies, format!("\x1B@{}ONE\x1B@TWO", DRAWER));
+
+        let without_reset = apply("RECEIPT".to_string(), &order(0, 0), &counter, &rules, true);
+        assert_eq!(without_reset, format!("{}RECEIPT", DRAWER));
+    }
+}
@@ -0,0 +1,564 @@
+// 打印机能力配置库：按型号或驱动名称匹配打印机，取代按名称猜测宽度和编码
+//
+// 每个配置记录纸宽、每行点数、字体A/B每行字符数、支持的代码页及其选择命令、
+// 中文支持、切刀类型、钱箱以及二维码/光栅图支持。
+//
+// 匹配顺序:
+//   1. printer_overrides.json 中为该打印机手动指定的 profile_id
+//   2. 用户配置库 (应用数据目录下的 printer_profiles.json 或 printer_profiles.toml)
+//   3. 内置配置库 (编译时打包的 printer_profiles.json)
+//   4. id 为 generic 的通用配置 (needs_confirmation 为 true，界面提示用户选择打印机类型)
+// 用户配置中 id 与内置配置相同的条目会替换内置条目。手动覆盖的其余字段 (纸宽、编码等)
+// 最后叠加在匹配到的配置上。
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+
+use crate::chinese_script::ScriptConversion;
+use crate::error::AppError;
+
+const BUNDLED_PROFILES: &str = include_str!("printer_profiles.json");
+const FALLBACK_PROFILE_ID: &str = "generic";
+// ESC p 0 25 120：引脚2，通电50ms，断电240ms
+const DEFAULT_DRAWER_COMMAND: &str = "1B 70 00 19 78";
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CutterType {
+    None,
+    Full,
+    Partial,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct CodePage {
+    pub encoding: String,
+    // 切换到该代码页的命令，十六进制字节，例如 "1B 74 10"；为空表示不需要切换
+    #[serde(default)]
+    pub select_command: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct PrinterProfile {
+    pub id: String,
+    pub name: String,
+    // 打印机名称和驱动名称的正则表达式 (不区分大小写)
+    pub match_names: Vec<String>,
+    pub match_drivers: Vec<String>,
+    // 是否为ESC/POS小票打印机 (热敏或针式)，否则是办公/虚拟打印机
+    pub thermal: bool,
+    pub paper_width_mm: i32,
+    pub dots_per_line: u32,
+    pub chars_per_line_a: u32,
+    pub chars_per_line_b: u32,
+    pub cjk: bool,
+    pub code_pages: Vec<CodePage>,
+    pub default_encoding: String,
+    pub cutter: CutterType,
+    pub drawer: bool,
+    pub qr: bool,
+    pub raster: bool,
+    // 开钱箱和蜂鸣器命令 (十六进制)，不同厂商的写法不同；蜂鸣器命令为空表示不支持
+    pub drawer_command: String,
+    pub buzzer_command: String,
+}
+
+// 用户配置中缺少的字段按80mm ESC/POS打印机处理
+impl Default for PrinterProfile {
+    fn default() -> Self {
+        PrinterProfile {
+            id: FALLBACK_PROFILE_ID.to_string(),
+            name: String::new(),
+            match_names: Vec::new(),
+            match_drivers: Vec::new(),
+            thermal: true,
+            paper_width_mm: 80,
+            dots_per_line: 576,
+            chars_per_line_a: 48,
+            chars_per_line_b: 64,
+            cjk: false,
+            code_pages: vec![CodePage {
+                encoding: "UTF8".to_string(),
+                select_command: String::new(),
+            }],
+            default_encoding: "UTF8".to_string(),
+            cutter: CutterType::Full,
+            drawer: false,
+            qr: false,
+            raster: false,
+            drawer_command: DEFAULT_DRAWER_COMMAND.to_string(),
+            buzzer_command: String::new(),
+        }
+    }
+}
+
+impl PrinterProfile {
+    pub fn code_page(&self, encoding: &str) -> Option<&CodePage> {
+        self.code_pages.iter().find(|c| c.encoding.eq_ignore_ascii_case(encoding))
+    }
+
+    pub fn encodings(&self) -> Vec<String> {
+        self.code_pages.iter().map(|c| c.encoding.clone()).collect()
+    }
+
+    // 切换到指定编码的命令序列，配置中没有该编码时使用通用命令
+    pub fn select_sequence(&self, encoding: &str) -> String {
+        let code_page = self.code_page(encoding).cloned().unwrap_or_else(|| generic_code_page(encoding));
+        parse_hex_command(&code_page.select_command).unwrap_or_else(|e| {
+            warn!("⚠️ [PROFILE] 配置 {} 的 {} 选择命令无效: {}", self.id, encoding, e);
+            String::new()
+        })
+    }
+
+    // 开钱箱命令，打印机没有钱箱接口时为 None
+    pub fn drawer_sequence(&self) -> Option<String> {
+        if !self.drawer {
+            return None;
+        }
+        self.action_sequence("钱箱", &self.drawer_command)
+    }
+
+    // 蜂鸣器命令，打印机没有蜂鸣器时为 None
+    pub fn buzzer_sequence(&self) -> Option<String> {
+        self.action_sequence("蜂鸣器", &self.buzzer_command)
+    }
+
+    fn action_sequence(&self, label: &str, hex: &str) -> Option<String> {
+        match parse_hex_command(hex) {
+            Ok(command) if !command.is_empty() => Some(command),
+            Ok(_) => None,
+            Err(e) => {
+                warn!("⚠️ [PROFILE] 配置 {} 的{}命令无效: {}", self.id, label, e);
+                None
+            }
+        }
+    }
+}
+
+// 配置中没有列出的编码使用的通用代码页
+fn generic_code_page(encoding: &str) -> CodePage {
+    let select_command = match encoding.to_uppercase().as_str() {
+        "GBK" | "GB2312" | "GB18030" | "BIG5" => "1C 26", // FS & 进入汉字模式
+        _ => "",
+    };
+    CodePage {
+        encoding: encoding.to_string(),
+        select_command: select_command.to_string(),
+    }
+}
+
+// 钱箱脉冲 (ESC p m t1 t2)，时长以2ms为单位发送
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) struct DrawerPulse {
+    pub pin: u8, // 0=引脚2, 1=引脚5 (第二个钱箱)
+    pub on_ms: u32,
+    pub off_ms: u32,
+}
+
+impl DrawerPulse {
+    fn command(&self) -> String {
+        format!("1B 70 {:02X} {:02X} {:02X}", self.pin, self.on_ms / 2, self.off_ms / 2)
+    }
+}
+
+// 单台打印机的手动覆盖，未设置的字段使用匹配到的配置
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct PrinterOverride {
+    pub profile_id: Option<String>,
+    pub thermal: Option<bool>,
+    pub paper_width_mm: Option<i32>,
+    pub dots_per_line: Option<u32>,
+    pub chars_per_line_a: Option<u32>,
+    pub chars_per_line_b: Option<u32>,
+    pub cjk: Option<bool>,
+    pub default_encoding: Option<String>,
+    pub cutter: Option<CutterType>,
+    pub drawer: Option<bool>,
+    // 校准时操作员确认打印正确的编码 (encoding_calibration)
+    pub verified_encodings: Option<Vec<String>>,
+    pub calibrated_at: Option<String>,
+    // 打印前的简繁转换 (chinese_script)
+    pub script_conversion: Option<ScriptConversion>,
+    // 开钱箱和蜂鸣器 (printer_actions)；drawer_pulse 优先于 drawer_command
+    pub drawer_pulse: Option<DrawerPulse>,
+    pub drawer_command: Option<String>,
+    pub buzzer_command: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ResolvedProfile {
+    pub printer_name: String,
+    pub driver: Option<String>,
+    pub matched_by: &'static str, // override / driver / name / fallback
+    pub overridden: bool,
+    pub profile: PrinterProfile,
+    // 经过打印校准确认的编码，没有校准过时为 None
+    pub verified_encodings: Option<Vec<String>>,
+    pub calibrated_at: Option<String>,
+    pub script_conversion: ScriptConversion,
+    // 配置库没有匹配到，也没有手动指定配置或类型：界面应让用户选择 (set_printer_override)
+    pub needs_confirmation: bool,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct ProfileEntry {
+    pub source: &'static str, // user / builtin
+    pub profile: PrinterProfile,
+}
+
+#[derive(Deserialize)]
+struct ProfileFile {
+    #[serde(default)]
+    profiles: Vec<PrinterProfile>,
+}
+
+struct CompiledProfile {
+    source: &'static str,
+    profile: PrinterProfile,
+    names: Vec<Regex>,
+    drivers: Vec<Regex>,
+}
+
+static DATABASE: Lazy<RwLock<Vec<CompiledProfile>>> = Lazy::new(|| RwLock::new(load_database()));
+static OVERRIDES: Lazy<RwLock<BTreeMap<String, PrinterOverride>>> = Lazy::new(|| RwLock::new(load_overrides()));
+// 枚举系统打印机时记录的驱动名称
+static DRIVERS: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn user_profiles_paths() -> [PathBuf; 2] {
+    let dir = crate::get_app_data_dir();
+    [dir.join("printer_profiles.json"), dir.join("printer_profiles.toml")]
+}
+
+fn overrides_path() -> PathBuf {
+    crate::get_app_data_dir().join("printer_overrides.json")
+}
+
+// "1B 74 10" -> ESC t 16。小票内容以文本发送，所以只接受 0x00-0x7F 的字节
+fn parse_hex_command(hex: &str) -> Result<String, String> {
+    hex.split_whitespace()
+        .map(|byte| {
+            let value = u8::from_str_radix(byte, 16).map_err(|_| format!("无效的十六进制字节: {}", byte))?;
+            if value > 0x7F {
+                return Err(format!("命令字节必须小于 0x80: {}", byte));
+            }
+            Ok(value as char)
+        })
+        .collect()
+}
+
+fn compile_patterns(profile_id: &str, patterns: &[String]) -> Vec<Regex> {
+    patterns
+        .iter()
+        .filter_map(|pattern| match RegexBuilder::new(pattern).case_insensitive(true).build() {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                warn!("⚠️ [PROFILE] 配置 {} 的匹配规则无效 {}: {}", profile_id, pattern, e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn compile(source: &'static str, profile: PrinterProfile) -> CompiledProfile {
+    CompiledProfile {
+        source,
+        names: compile_patterns(&profile.id, &profile.match_names),
+        drivers: compile_patterns(&profile.id, &profile.match_drivers),
+        profile,
+    }
+}
+
+fn read_user_profiles() -> Vec<PrinterProfile> {
+    let mut profiles = Vec::new();
+    for path in user_profiles_paths() {
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        let parsed = if path.extension().map(|e| e == "toml").unwrap_or(false) {
+            toml::from_str::<ProfileFile>(&content).map_err(|e| e.to_string())
+        } else {
+            serde_json::from_str::<ProfileFile>(&content).map_err(|e| e.to_string())
+        };
+        match parsed {
+            Ok(file) => {
+                info!("🖨️ [PROFILE] 已加载用户打印机配置 {} 条: {}", file.profiles.len(), path.display());
+                profiles.extend(file.profiles);
+            }
+            Err(e) => warn!("⚠️ [PROFILE] 用户打印机配置格式错误，已忽略 {}: {}", path.display(), e),
+        }
+    }
+    profiles
+}
+
+fn load_database() -> Vec<CompiledProfile> {
+    let bundled: ProfileFile = serde_json::from_str(BUNDLED_PROFILES).expect("内置打印机配置库格式错误");
+    let user = read_user_profiles();
+
+    let mut database: Vec<CompiledProfile> = Vec::new();
+    for profile in user {
+        if profile.id.trim().is_empty() {
+            warn!("⚠️ [PROFILE] 用户打印机配置缺少 id，已忽略: {}", profile.name);
+            continue;
+        }
+        database.push(compile("user", profile));
+    }
+    for profile in bundled.profiles {
+        if database.iter().any(|p| p.profile.id == profile.id) {
+            continue;
+        }
+        database.push(compile("builtin", profile));
+    }
+    database
+}
+
+fn load_overrides() -> BTreeMap<String, PrinterOverride> {
+    fs::read_to_string(overrides_path())
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+// 重新读取用户配置库，返回配置总数
+pub(crate) fn reload() -> usize {
+    let database = load_database();
+    let count = database.len();
+    *DATABASE.write().unwrap() = database;
+    info!("🔄 [PROFILE] 打印机配置库已重新加载，共 {} 条", count);
+    count
+}
+
+pub(crate) fn list_profiles() -> Vec<ProfileEntry> {
+    DATABASE
+        .read()
+        .unwrap()
+        .iter()
+        .map(|p| ProfileEntry {
+            source: p.source,
+            profile: p.profile.clone(),
+        })
+        .collect()
+}
+
+pub(crate) fn remember_driver(printer_name: &str, driver: &str) {
+    DRIVERS.lock().unwrap().insert(printer_name.to_string(), driver.to_string());
+}
+
+fn find_profile_in(database: &[CompiledProfile], id: &str) -> Option<PrinterProfile> {
+    database.iter().find(|p| p.profile.id == id).map(|p| p.profile.clone())
+}
+
+fn find_profile(id: &str) -> Option<PrinterProfile> {
+    find_profile_in(&DATABASE.read().unwrap(), id)
+}
+
+// 查找打印机对应的配置 (包括手动覆盖)
+pub(crate) fn resolve(printer_name: &str) -> ResolvedProfile {
+    let driver = DRIVERS.lock().unwrap().get(printer_name).cloned();
+    let manual = OVERRIDES.read().unwrap().get(printer_name).cloned();
+
+    let forced = manual
+        .as_ref()
+        .and_then(|o| o.profile_id.as_deref())
+        .and_then(|id| {
+            let profile = find_profile(id);
+            if profile.is_none() {
+                warn!("⚠️ [PROFILE] 打印机 {} 指定的配置 {} 不存在，改为自动匹配", printer_name, id);
+            }
+            profile
+        });
+
+    let (matched_by, mut profile) = match forced {
+        Some(profile) => ("override", profile),
+        None => {
+            let database = DATABASE.read().unwrap();
+            let by_driver = driver.as_deref().and_then(|driver| {
+                database.iter().find(|p| p.drivers.iter().any(|r| r.is_match(driver)))
+            });
+            match by_driver {
+                Some(p) => ("driver", p.profile.clone()),
+                None => match database.iter().find(|p| p.names.iter().any(|r| r.is_match(printer_name))) {
+                    Some(p) => ("name", p.profile.clone()),
+                    None => ("fallback", find_profile_in(&database, FALLBACK_PROFILE_ID).unwrap_or_default()),
+                },
+            }
+        }
+    };
+
+    if let Some(manual) = &manual {
+        apply_override(&mut profile, manual);
+    }
+
+    ResolvedProfile {
+        printer_name: printer_name.to_string(),
+        driver,
+        matched_by,
+        overridden: manual.is_some(),
+        profile,
+        verified_encodings: manual.as_ref().and_then(|o| o.verified_encodings.clone()),
+        calibrated_at: manual.as_ref().and_then(|o| o.calibrated_at.clone()),
+        script_conversion: manual.as_ref().and_then(|o| o.script_conversion).unwrap_or_default(),
+        needs_confirmation: matched_by == "fallback"
+            && !manual.as_ref().map(|o| o.thermal.is_some()).unwrap_or(false),
+    }
+}
+
+fn apply_override(profile: &mut PrinterProfile, manual: &PrinterOverride) {
+    if let Some(thermal) = manual.thermal {
+        profile.thermal = thermal;
+    }
+    if let Some(width) = manual.paper_width_mm {
+        profile.paper_width_mm = width;
+    }
+    if let Some(dots) = manual.dots_per_line {
+        profile.dots_per_line = dots;
+    }
+    if let Some(chars) = manual.chars_per_line_a {
+        profile.chars_per_line_a = chars;
+    }
+    if let Some(chars) = manual.chars_per_line_b {
+        profile.chars_per_line_b = chars;
+    }
+    if let Some(cjk) = manual.cjk {
+        profile.cjk = cjk;
+    }
+    if let Some(encoding) = &manual.default_encoding {
+        profile.default_encoding = encoding.clone();
+        if profile.code_page(encoding).is_none() {
+            profile.code_pages.insert(0, generic_code_page(encoding));
+        }
+    }
+    if let Some(cutter) = manual.cutter {
+        profile.cutter = cutter;
+    }
+    if let Some(drawer) = manual.drawer {
+        profile.drawer = drawer;
+    }
+    if let Some(command) = &manual.drawer_command {
+        profile.drawer_command = command.clone();
+    }
+    if let Some(pulse) = &manual.drawer_pulse {
+        profile.drawer_command = pulse.command();
+    }
+    if let Some(command) = &manual.buzzer_command {
+        profile.buzzer_command = command.clone();
+    }
+    // 校准确认的编码排在前面
+    if let Some(verified) = &manual.verified_encodings {
+        for encoding in verified.iter().rev() {
+            let code_page = match profile.code_pages.iter().position(|c| c.encoding.eq_ignore_ascii_case(encoding)) {
+                Some(index) => profile.code_pages.remove(index),
+                None => generic_code_page(encoding),
+            };
+            profile.code_pages.insert(0, code_page);
+        }
+    }
+}
+
+fn validate_override(manual: &PrinterOverride) -> Result<(), AppError> {
+    if let Some(id) = &manual.profile_id {
+        if find_profile(id).is_none() {
+            return Err(AppError::InvalidArgument(format!("打印机配置不存在: {}", id)));
+        }
+    }
+    if manual.paper_width_mm.map(|w| !(30..=300).contains(&w)).unwrap_or(false) {
+        return Err(AppError::InvalidArgument("纸宽应在 30-300mm 之间".to_string()));
+    }
+    if manual.chars_per_line_a == Some(0) || manual.chars_per_line_b == Some(0) {
+        return Err(AppError::InvalidArgument("每行字符数必须大于0".to_string()));
+    }
+    if manual.default_encoding.as_deref().map(|e| e.trim().is_empty()).unwrap_or(false) {
+        return Err(AppError::InvalidArgument("编码不能为空".to_string()));
+    }
+    if let Some(pulse) = &manual.drawer_pulse {
+        if pulse.pin > 1 {
+            return Err(AppError::InvalidArgument("钱箱引脚只能是 0 (引脚2) 或 1 (引脚5)".to_string()));
+        }
+        if !(2..=254).contains(&pulse.on_ms) || pulse.off_ms > 254 {
+            return Err(AppError::InvalidArgument("钱箱脉冲时长应在 2-254ms 之间".to_string()));
+        }
+    }
+    for command in [&manual.drawer_command, &manual.buzzer_command].into_iter().flatten() {
+        parse_hex_command(command).map_err(AppError::InvalidArgument)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn override_for(printer_name: &str) -> Option<PrinterOverride> {
+    OVERRIDES.read().unwrap().get(printer_name).cloned()
+}
+
+// 设置或清除 (None) 打印机的手动覆盖
+pub(crate) fn set_override(printer_name: &str, manual: Option<PrinterOverride>) -> Result<ResolvedProfile, AppError> {
+    if let Some(manual) = &manual {
+        validate_override(manual)?;
+    }
+
+    let mut overrides = OVERRIDES.write().unwrap();
+    let mut updated = overrides.clone();
+    match manual {
+        Some(manual) => {
+            updated.insert(printer_name.to_string(), manual);
+        }
+        None => {
+            updated.remove(printer_name);
+        }
+    }
+
+    let path = overrides_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&updated)?)
+        .map_err(|e| AppError::Config(format!("保存打印机覆盖设置失败: {}", e)))?;
+    *overrides = updated;
+    drop(overrides);
+
+    info!("💾 [PROFILE] 已更新打印机 {} 的手动设置", printer_name);
+    Ok(resolve(printer_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_keywords_only_match_whole_words() {
+        assert_eq!(resolve("POS-80 Printer").profile.id, "generic-thermal-80");
+        assert_eq!(resolve("Kitchen Receipt").profile.id, "generic-thermal-80");
+        assert_eq!(resolve("XP-80C").profile.id, "xprinter-80");
+
+        // "purpose" 和 "composer" 中的 pos 不是小票打印机
+        for name in ["Purpose Label Writer", "Composer Studio"] {
+            let resolved = resolve(name);
+            assert_eq!(resolved.matched_by, "fallback", "{}", name);
+            assert!(resolved.needs_confirmation, "{}", name);
+        }
+    }
+
+    #[test]
+    fn matched_printers_need_no_confirmation() {
+        assert!(!resolve("XP-80C").needs_confirmation);
+        assert!(!resolve("Microsoft Print to PDF").needs_confirmation);
+        assert!(!resolve("Microsoft Print to PDF").profile.thermal);
+    }
+
+    #[test]
+    fn office_printers_are_not_thermal() {
+        // 型号中的 580 不能被当成58mm小票打印机
+        for name in ["HP LaserJet M580", "HP LaserJet Pro M404dn", "Brother HL-L2350DW"] {
+            let resolved = resolve(name);
+            assert_eq!(resolved.profile.id, "office-printer", "{}", name);
+            assert!(!resolved.profile.thermal, "{}", name);
+            assert!(!resolved.needs_confirmation, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// 多联打印：按打印机和订单类型设置打印几联，每联顶部标注用途 (CUSTOMER COPY 等)
+//
+// 所有联在同一个打印任务中发送，联与联之间按打印机配置库的切刀类型切纸，没有切刀的
+// 打印机只走纸方便撕开。没有设置时只打印一联、不加标注，和以前一样。
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::printer_profiles::{self, CutterType};
+use crate::receipt_layout::ReceiptLayout;
+use crate::{OrderData, PrinterConfig};
+
+// 每种订单类型最多打印几联
+const MAX_COPIES: usize = 5;
+
+// generate_print_content 末尾的切纸命令
+const RECEIPT_CUT: &str = "\x1D\x56\x00";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CopyKind {
+    Customer, // 顾客联 (外送时放在袋子里)
+    Driver,   // 骑手联
+    Merchant, // 商家联
+}
+
+impl CopyKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            CopyKind::Customer => "CUSTOMER COPY",
+            CopyKind::Driver => "DRIVER COPY",
+            CopyKind::Merchant => "MERCHANT COPY",
+        }
+    }
+}
+
+// 按订单类型设置的各联，为空时打印一联不加标注
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct CopySettings {
+    pub delivery: Vec<CopyKind>,
+    pub pickup: Vec<CopyKind>,
+}
+
+impl CopySettings {
+    pub fn for_order(&self, order: &OrderData) -> &[CopyKind] {
+        match order.delivery_style {
+            1 => &self.delivery,
+            _ => &self.pickup,
+        }
+    }
+}
+
+pub(crate) fn validate(printers: &[PrinterConfig]) -> Result<(), AppError> {
+    for printer in printers {
+        if printer.copies.delivery.len() > MAX_COPIES || printer.copies.pickup.len() > MAX_COPIES {
+            return Err(AppError::InvalidArgument(format!(
+                "打印机 {} 每种订单最多打印 {} 联",
+                printer.name, MAX_COPIES
+            )));
+        }
+    }
+    Ok(())
+}
+
+// 联与联之间的切纸命令
+fn cut_sequence(cutter: CutterType) -> String {
+    match cutter {
+        CutterType::Full => RECEIPT_CUT.to_string(),
+        CutterType::Partial => "\x1D\x56\x01".to_string(), // GS V 1 - 半切
+        CutterType::None => "\n\n".to_string(),           // 多走两行，方便手撕
+    }
+}
+
+// 生成一台打印机的打印任务内容 (所有联)
+pub(crate) fn build_job(order: &OrderData, printer: &PrinterConfig) -> Result<String, AppError> {
+    let layout = ReceiptLayout::for_printer(printer);
+    let copies = printer.copies.for_order(order);
+    if copies.is_empty() {
+        return crate::generate_print_content(order, &layout);
+    }
+
+    let cut = cut_sequence(printer_profiles::resolve(&printer.name).profile.cutter);
+    let mut job = String::new();
+    for copy in copies {
+        let receipt = crate::generate_receipt(order, &layout, Some(copy.label()))?;
+        let body = receipt.strip_suffix(RECEIPT_CUT).unwrap_or(&receipt);
+        job.push_str(body);
+        job.push_str(&cut);
+    }
+    Ok(job)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{self, TEST_PRINTER};
+
+    fn printer_with_copies(name: &str) -> PrinterConfig {
+        let mut printer = test_support::printer(name);
+        printer.copies = CopySettings {
+            delivery: vec![CopyKind::Customer, CopyKind::Driver],
+            pickup: vec![CopyKind::Merchant],
+        };
+        printer
+    }
+
+    fn order(delivery_style: i32) -> OrderData {
+        let mut order = test_support::order("COPY-1");
+        order.delivery_style = delivery_style;
+        order
+    }
+
+    #[test]
+    fn copies_follow_the_order_type() {
+        let printer = printer_with_copies(TEST_PRINTER);
+
+        let delivery = build_job(&order(1), &printer).unwrap();
+        assert_eq!(delivery.matches("\x1B@").count(), 2);
+        assert!(delivery.find("CUSTOMER COPY").unwrap() < delivery.find("DRIVER COPY").unwrap());
+        assert!(!delivery.contains("MERCHANT COPY"));
+
+        let pickup = build_job(&order(0), &printer).unwrap();
+        assert_eq!(pickup.matches("\x1B@").count(), 1);
+        assert!(pickup.contains("MERCHANT COPY") && !pickup.contains("CUSTOMER COPY"));
+    }
+
+    #[test]
+    fn copies_are_cut_with_the_printer_cutter() {
+        // Xprinter 80mm 半切，每联后面各切一次
+        let partial = build_job(&order(1), &printer_with_copies(TEST_PRINTER)).unwrap();
+        assert_eq!(partial.matches("\x1D\x56\x01").count(), 2);
+        assert!(partial.ends_with("\x1D\x56\x01"));
+        assert!(!partial.contains(RECEIPT_CUT));
+
+        // Xprinter 58mm 没有切刀，只走纸
+        let tear_off = build_job(&order(1), &printer_with_copies("XP-58IIH")).unwrap();
+        assert!(!tear_off.contains("\x1D\x56"));
+        assert!(tear_off.contains("\n\n\x1B@"));
+    }
+
+    #[test]
+    fn no_copies_prints_one_unlabelled_receipt() {
+        let printer = test_support::printer(TEST_PRINTER);
+        let job = build_job(&order(1), &printer).unwrap();
+        assert_eq!(job, crate::generate_print_content(&order(1), &ReceiptLayout::for_printer(&printer)).unwrap());
+        assert!(!job.contains("COPY"));
+    }
+}
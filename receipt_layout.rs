@@ -0,0 +1,200 @@
+// 小票排版：根据打印宽度、字体和放大倍数计算每行实际可用的字符数
+//
+// 每行字符数按以下顺序确定:
+//   1. 打印机设置中直接指定的 columns
+//   2. 打印机设置中的可打印点数 dots_per_line ÷ 字符宽度 (字体A 12点，字体B 9点)
+//   3. 打印机配置库中该型号字体A/B的每行字符数 (纸宽与配置一致时)
+//   4. 按纸宽估算可打印点数 (203dpi，每毫米8点)
+//
+// GS ! n 放大宽度后每个字符占用的点数成倍增加，排版必须按放大后的列数计算，
+// 否则每一行都会折行，右对齐的金额会掉到下一行。只放大高度不影响列数。
+//
+// 小票分为若干段落，每个段落可以单独设置放大倍数 (例如厨房单的菜名用2倍高、
+// 合计用正常大小)。没有单独设置的段落使用打印机的 font_size。
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::{printer_profiles, PrinterConfig};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Section {
+    Title,      // 醒目的大标题 (VOID、CHANGED、Z-REPORT)
+    Header,     // 店名、订单类型、订单号
+    Info,       // 时间、顾客、地址等信息行
+    Items,      // 菜品表格
+    ItemNotes,  // 菜品的附加项和备注
+    Totals,     // 费用明细和合计
+    Footer,     // 底部信息
+}
+
+// 宽高放大倍数 (1-8)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct TextSize {
+    pub width: u8,
+    pub height: u8,
+}
+
+pub(crate) type SectionSizes = BTreeMap<Section, TextSize>;
+
+impl TextSize {
+    pub const NORMAL: TextSize = TextSize { width: 1, height: 1 };
+    pub const DOUBLE: TextSize = TextSize { width: 2, height: 2 };
+
+    // 旧的 font_size 设置：0=小 (1x1)，1=中 (高度2x)，2=大 (宽高2x)
+    pub fn from_font_size(font_size: i32) -> TextSize {
+        match font_size {
+            1 => TextSize { width: 1, height: 2 },
+            2 => TextSize::DOUBLE,
+            _ => TextSize::NORMAL,
+        }
+    }
+
+    fn width(&self) -> u8 {
+        self.width.clamp(1, 8)
+    }
+
+    fn height(&self) -> u8 {
+        self.height.clamp(1, 8)
+    }
+
+    // GS ! n：高4位为宽度倍数-1，低4位为高度倍数-1
+    pub fn command(&self) -> String {
+        let n = ((self.width() - 1) << 4) | (self.height() - 1);
+        format!("\x1D\x21{}", n as char)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum PrinterFont {
+    #[default]
+    A, // 12x24点
+    B, // 9x17点，同样纸宽每行字符更多
+}
+
+impl PrinterFont {
+    fn dots_per_char(self) -> u32 {
+        match self {
+            PrinterFont::A => 12,
+            PrinterFont::B => 9,
+        }
+    }
+
+    // ESC M n
+    fn command(self) -> &'static str {
+        match self {
+            PrinterFont::A => "\x1B\x4D\x00",
+            PrinterFont::B => "\x1B\x4D\x01",
+        }
+    }
+}
+
+// 常见纸宽的可打印点数，其他纸宽按两侧各留4mm估算
+fn dots_for_width(width_mm: i32) -> u32 {
+    match width_mm {
+        58 => 384,
+        80 => 576,
+        112 => 832,
+        width => ((width - 8).max(16) * 8) as u32,
+    }
+}
+
+fn columns_for_dots(dots: u32, font: PrinterFont) -> usize {
+    (dots / font.dots_per_char()) as usize
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct ReceiptLayout {
+    // 正常大小时每行的字符数
+    pub base_columns: usize,
+    font: PrinterFont,
+    default_size: TextSize,
+    sizes: SectionSizes,
+}
+
+// 每行字符数的合理范围，防止错误设置导致排版崩溃
+const MIN_COLUMNS: usize = 8;
+const MAX_COLUMNS: usize = 200;
+
+impl ReceiptLayout {
+    // 只知道纸宽时使用 (预览等)，字体A
+    pub fn new(width: i32, font_size: i32) -> Self {
+        ReceiptLayout {
+            base_columns: columns_for_dots(dots_for_width(width), PrinterFont::A).clamp(MIN_COLUMNS, MAX_COLUMNS),
+            font: PrinterFont::A,
+            default_size: TextSize::from_font_size(font_size),
+            sizes: SectionSizes::new(),
+        }
+    }
+
+    pub fn with_font(mut self, font: PrinterFont, columns: Option<usize>) -> Self {
+        self.font = font;
+        self.base_columns = columns
+            .unwrap_or_else(|| self.base_columns * PrinterFont::A.dots_per_char() as usize / font.dots_per_char() as usize)
+            .clamp(MIN_COLUMNS, MAX_COLUMNS);
+        self
+    }
+
+    pub fn for_printer(printer: &PrinterConfig) -> Self {
+        let font = printer.font;
+        let base_columns = if printer.columns > 0 {
+            printer.columns as usize
+        } else if printer.dots_per_line > 0 {
+            columns_for_dots(printer.dots_per_line, font)
+        } else {
+            let profile = printer_profiles::resolve(&printer.name).profile;
+            let profile_columns = match font {
+                PrinterFont::A => profile.chars_per_line_a,
+                PrinterFont::B => profile.chars_per_line_b,
+            } as usize;
+            if profile.thermal && profile.paper_width_mm == printer.width && profile_columns > 0 {
+                profile_columns
+            } else {
+                columns_for_dots(dots_for_width(printer.width), font)
+            }
+        };
+
+        ReceiptLayout {
+            base_columns: base_columns.clamp(MIN_COLUMNS, MAX_COLUMNS),
+            font,
+            default_size: TextSize::from_font_size(printer.font_size),
+            sizes: printer.section_sizes.clone(),
+        }
+    }
+
+    // 小票开头的初始化命令：复位、汉字模式、行间距和字体
+    pub fn init(&self, content: &mut String) {
+        content.push_str("\x1B@"); // 初始化打印机
+        content.push_str("\x1C\x26"); // 启用汉字模式 (通用命令)
+        content.push_str("\x1C\x43\x01"); // 选择汉字字符模式
+        content.push_str("\x1B\x33\x30"); // 设置行间距为48/180英寸 (比默认大)
+        content.push_str(self.font.command());
+    }
+
+    pub fn size(&self, section: Section) -> TextSize {
+        match self.sizes.get(&section) {
+            Some(size) => *size,
+            None if section == Section::Title => TextSize::DOUBLE,
+            None => self.default_size,
+        }
+    }
+
+    // 该段落放大后每行的字符数
+    pub fn columns(&self, section: Section) -> usize {
+        (self.base_columns / self.size(section).width() as usize).max(1)
+    }
+
+    // 切换到段落的字体大小，返回该段落每行的字符数
+    pub fn begin(&self, content: &mut String, section: Section) -> usize {
+        content.push_str(&self.size(section).command());
+        self.columns(section)
+    }
+}
+
+// 分隔线，长度跟随当前段落的列数
+pub(crate) fn rule(ch: char, columns: usize) -> String {
+    let mut line: String = std::iter::repeat(ch).take(columns).collect();
+    line.push('\n');
+    line
+}
@@ -0,0 +1,523 @@
+// 营业日报 (Z-report)
+//
+// 汇总一个日期范围内的订单：小计、折扣、税费、配送费、服务费、小费，按付款方式和
+// 订单类型分组统计，以及销量最高的菜品。订单来自本地订单历史，或者翻页调用订单列表接口。
+// 已取消的订单不计入营业额，单独统计。
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
+
+use crate::api_client::{self, ApiError, OrderListQuery};
+use crate::error::AppError;
+use crate::events::Ctx;
+use crate::order_changes;
+use crate::receipt_layout::{rule, ReceiptLayout, Section, TextSize};
+use crate::OrderData;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+// 一次最多统计的天数
+const MAX_RANGE_DAYS: i64 = 366;
+// 从接口翻页时的每页数量和最大页数
+const API_PER_PAGE: i32 = 50;
+const API_MAX_PAGES: i32 = 40;
+// 日报中列出的菜品数量
+const TOP_DISHES: usize = 10;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReportSource {
+    #[default]
+    History, // 本地订单历史
+    Api,     // 订单列表接口
+}
+
+// 金额以分为单位累加，序列化为两位小数
+fn cents_as_decimal<S: Serializer>(cents: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(*cents as f64 / 100.0)
+}
+
+pub(crate) fn parse_cents(value: &str) -> i64 {
+    (value.trim().parse::<f64>().unwrap_or(0.0) * 100.0).round() as i64
+}
+
+pub(crate) fn format_cents(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct GroupTotal {
+    pub code: i32,
+    pub label: String,
+    pub count: u32,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub total: i64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct DishTotal {
+    pub name: String,
+    pub quantity: i64,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub sales: i64,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub(crate) struct SalesReport {
+    pub date_from: String,
+    pub date_to: String,
+    pub source: ReportSource,
+    pub generated_at: String,
+    pub restaurant: String,
+    pub order_count: u32,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub subtotal: i64,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub discount: i64,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub exemption: i64,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub tax: i64,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub delivery_fee: i64,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub retail_delivery_fee: i64,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub service_fee: i64,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub tips: i64,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub total: i64,
+    pub cancelled_count: u32,
+    #[serde(serialize_with = "cents_as_decimal")]
+    pub cancelled_total: i64,
+    pub by_paystyle: Vec<GroupTotal>,
+    pub by_delivery_style: Vec<GroupTotal>,
+    pub top_dishes: Vec<DishTotal>,
+}
+
+// 解析日期范围 (YYYY-MM-DD，包含两端)
+fn parse_date_range(date_from: &str, date_to: &str) -> Result<(chrono::NaiveDate, chrono::NaiveDate), AppError> {
+    let parse = |value: &str| {
+        chrono::NaiveDate::parse_from_str(value.trim(), DATE_FORMAT)
+            .map_err(|_| AppError::InvalidArgument(format!("日期格式应为 YYYY-MM-DD: {}", value)))
+    };
+    let from = parse(date_from)?;
+    let to = parse(date_to)?;
+
+    if from > to {
+        return Err(AppError::InvalidArgument(format!("开始日期 {} 晚于结束日期 {}", from, to)));
+    }
+    if (to - from).num_days() >= MAX_RANGE_DAYS {
+        return Err(AppError::InvalidArgument(format!("日期范围不能超过 {} 天", MAX_RANGE_DAYS)));
+    }
+    Ok((from, to))
+}
+
+// ============= 收集订单 =============
+
+// 翻页读取订单列表 (接口按下单时间倒序返回)，直到早于开始日期
+async fn fetch_orders_from_api(ctx: &Ctx, from: chrono::NaiveDate, end: chrono::NaiveDate) -> Result<Vec<OrderData>, AppError> {
+    let state = ctx.app_state();
+    let session = state.session.lock().unwrap().clone().ok_or(AppError::NotLoggedIn)?;
+    let start = from.format(DATE_FORMAT).to_string();
+    let end = end.format(DATE_FORMAT).to_string();
+
+    let mut token = session.token.clone();
+    let mut orders: Vec<OrderData> = Vec::new();
+
+    for page in 1..=API_MAX_PAGES {
+        let query = OrderListQuery {
+            user_id: &session.user_id,
+            rd_id: session.rd_id,
+            page,
+            per_page: API_PER_PAGE,
+        };
+        let page_orders = match api_client::shared()?.get_order_list(&token, &query).await {
+            Ok(orders) => orders,
+            Err(ApiError::Unauthorized(_)) => {
+                token = crate::handle_session_expired(ctx).await?;
+                api_client::shared()?.get_order_list(&token, &query).await?
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        state.order_history.record_orders(&page_orders, "api");
+        let page_len = page_orders.len();
+        let reached_start = page_orders.iter().any(|o| !o.create_time.is_empty() && o.create_time.as_str() < start.as_str());
+
+        orders.extend(
+            page_orders
+                .into_iter()
+                .filter(|o| o.create_time.as_str() >= start.as_str() && o.create_time.as_str() < end.as_str()),
+        );
+
+        if reached_start || page_len < API_PER_PAGE as usize {
+            return Ok(orders);
+        }
+    }
+
+    warn!("⚠️ [REPORT] 订单列表超过 {} 页，日报可能不完整", API_MAX_PAGES);
+    Ok(orders)
+}
+
+// 读取日期范围 (包含两端) 内的订单，按下单时间排序，订单导出也使用
+pub(crate) async fn collect_orders(
+    ctx: &Ctx,
+    date_from: &str,
+    date_to: &str,
+    source: ReportSource,
+) -> Result<(chrono::NaiveDate, chrono::NaiveDate, Vec<OrderData>), AppError> {
+    let (from, to) = parse_date_range(date_from, date_to)?;
+    // 查询条件为 [开始日期, 结束日期的第二天)
+    let end = to.succ_opt().unwrap_or(to);
+
+    let orders = match source {
+        ReportSource::History => {
+            // 本地历史按当前登录的门店筛选 (orders.user_id 是顾客ID)
+            let rd_id = ctx.app_state().session.lock().unwrap().as_ref().map(|s| s.rd_id);
+            ctx.app_state().order_history.orders_between(
+                rd_id,
+                &from.format(DATE_FORMAT).to_string(),
+                &end.format(DATE_FORMAT).to_string(),
+            )?
+        }
+        ReportSource::Api => {
+            let mut orders = fetch_orders_from_api(ctx, from, end).await?;
+            orders.sort_by(|a, b| a.create_time.cmp(&b.create_time));
+            orders
+        }
+    };
+    Ok((from, to, orders))
+}
+
+// 生成日期范围内的营业日报
+pub(crate) async fn generate_report(
+    ctx: &Ctx,
+    date_from: &str,
+    date_to: &str,
+    source: ReportSource,
+) -> Result<SalesReport, AppError> {
+    let (from, to, orders) = collect_orders(ctx, date_from, date_to, source).await?;
+    info!("📊 [REPORT] 生成营业日报: {} ~ {}，共 {} 个订单", from, to, orders.len());
+    Ok(build_report(&orders, from, to, source))
+}
+
+// ============= 汇总 =============
+
+fn add_to_group(groups: &mut BTreeMap<i32, (u32, i64)>, code: i32, total: i64) {
+    let entry = groups.entry(code).or_insert((0, 0));
+    entry.0 += 1;
+    entry.1 += total;
+}
+
+fn build_report(orders: &[OrderData], from: chrono::NaiveDate, to: chrono::NaiveDate, source: ReportSource) -> SalesReport {
+    let mut report = SalesReport {
+        date_from: from.format(DATE_FORMAT).to_string(),
+        date_to: to.format(DATE_FORMAT).to_string(),
+        source,
+        generated_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        restaurant: orders.first().map(|o| o.rd_name.clone()).unwrap_or_default(),
+        ..Default::default()
+    };
+
+    let mut by_paystyle: BTreeMap<i32, (u32, i64)> = BTreeMap::new();
+    let mut by_delivery_style: BTreeMap<i32, (u32, i64)> = BTreeMap::new();
+    let mut dishes: BTreeMap<String, (i64, i64)> = BTreeMap::new();
+
+    for order in orders {
+        let total = parse_cents(&order.total);
+        if order_changes::is_cancelled(order) {
+            report.cancelled_count += 1;
+            report.cancelled_total += total;
+            continue;
+        }
+
+        report.order_count += 1;
+        report.subtotal += parse_cents(&order.sub_total);
+        report.discount += parse_cents(&order.discount_total);
+        report.exemption += parse_cents(&order.exemption);
+        report.tax += parse_cents(&order.tax_fee);
+        report.delivery_fee += parse_cents(&order.delivery_fee);
+        report.retail_delivery_fee += parse_cents(&order.retail_delivery_fee);
+        report.service_fee += parse_cents(&order.convenience_fee);
+        report.tips += parse_cents(&order.tip_fee);
+        report.total += total;
+
+        add_to_group(&mut by_paystyle, order.paystyle, total);
+        add_to_group(&mut by_delivery_style, order.delivery_style, total);
+
+        for item in &order.dishes_array {
+            let entry = dishes.entry(item.dishes_name.trim().to_string()).or_insert((0, 0));
+            entry.0 += i64::from(item.amount);
+            entry.1 += parse_cents(&item.price);
+        }
+    }
+
+    report.by_paystyle = by_paystyle
+        .into_iter()
+        .map(|(code, (count, total))| GroupTotal {
+            code,
+            label: crate::get_payment_method_text(code).to_string(),
+            count,
+            total,
+        })
+        .collect();
+    report.by_delivery_style = by_delivery_style
+        .into_iter()
+        .map(|(code, (count, total))| GroupTotal {
+            code,
+            label: if code == 1 { "DELIVERY" } else { "PICKUP" }.to_string(),
+            count,
+            total,
+        })
+        .collect();
+
+    let mut top_dishes: Vec<DishTotal> = dishes
+        .into_iter()
+        .map(|(name, (quantity, sales))| DishTotal { name, quantity, sales })
+        .collect();
+    top_dishes.sort_by(|a, b| b.quantity.cmp(&a.quantity).then(b.sales.cmp(&a.sales)));
+    top_dishes.truncate(TOP_DISHES);
+    report.top_dishes = top_dishes;
+
+    report
+}
+
+// ============= 打印 =============
+
+fn push_section_title(content: &mut String, title: &str, char_width: usize) {
+    content.push_str("\n");
+    content.push_str("\x1B\x45\x01"); // 加粗
+    content.push_str(&crate::center_text_mixed(title, char_width));
+    content.push_str("\x1B\x45\x00"); // 关闭加粗
+    content.push_str(&rule('-', char_width));
+}
+
+fn money_line(label: &str, cents: i64, char_width: usize) -> String {
+    crate::format_fee_line(label, cents as f64 / 100.0, char_width)
+}
+
+pub(crate) fn generate_report_content(report: &SalesReport, layout: &ReceiptLayout) -> String {
+    let mut content = String::new();
+
+    layout.init(&mut content);
+
+    // 标题
+    let char_width = layout.begin(&mut content, Section::Header);
+    content.push_str(&rule('=', char_width));
+    let title_width = layout.begin(&mut content, Section::Title);
+    content.push_str(&crate::center_text_mixed("Z-REPORT", title_width));
+    content.push_str("\n");
+    let char_width = layout.begin(&mut content, Section::Header);
+    if !report.restaurant.is_empty() {
+        content.push_str("\x1B\x45\x01");
+        content.push_str(&crate::center_text_mixed(&crate::prepare_mixed_content(&report.restaurant.to_uppercase()), char_width));
+        content.push_str("\x1B\x45\x00");
+        content.push_str("\n");
+    }
+    content.push_str(&rule('=', char_width));
+
+    let char_width = layout.begin(&mut content, Section::Info);
+    content.push_str(&crate::format_table_row("From:", &report.date_from, char_width));
+    content.push_str(&crate::format_table_row("To:", &report.date_to, char_width));
+    content.push_str(&crate::format_table_row("Source:", match report.source {
+        ReportSource::History => "Local history",
+        ReportSource::Api => "Server",
+    }, char_width));
+
+    // 营业额
+    let char_width = layout.begin(&mut content, Section::Totals);
+    push_section_title(&mut content, "SALES", char_width);
+    content.push_str(&crate::format_table_row("Orders", &report.order_count.to_string(), char_width));
+    content.push_str(&money_line("Subtotal", report.subtotal, char_width));
+    if report.discount != 0 {
+        content.push_str(&money_line("Discount", -report.discount, char_width));
+    }
+    if report.exemption != 0 {
+        content.push_str(&money_line("Exemption", -report.exemption, char_width));
+    }
+    content.push_str(&money_line("Tax", report.tax, char_width));
+    content.push_str(&money_line("Delivery Fee", report.delivery_fee, char_width));
+    if report.retail_delivery_fee != 0 {
+        content.push_str(&money_line("Retail Del. Fee", report.retail_delivery_fee, char_width));
+    }
+    content.push_str(&money_line("Service Fee", report.service_fee, char_width));
+    content.push_str(&money_line("Tips", report.tips, char_width));
+    content.push_str(&rule('=', char_width));
+    content.push_str("\x1B\x45\x01");
+    content.push_str(&money_line("TOTAL", report.total, char_width));
+    content.push_str("\x1B\x45\x00");
+    if report.cancelled_count > 0 {
+        content.push_str(&money_line(&format!("Cancelled ({})", report.cancelled_count), report.cancelled_total, char_width));
+    }
+
+    // 付款方式
+    push_section_title(&mut content, "PAYMENT", char_width);
+    for group in &report.by_paystyle {
+        content.push_str(&money_line(&format!("{} ({})", group.label, group.count), group.total, char_width));
+    }
+
+    // 订单类型
+    push_section_title(&mut content, "ORDER TYPE", char_width);
+    for group in &report.by_delivery_style {
+        content.push_str(&money_line(&format!("{} ({})", group.label, group.count), group.total, char_width));
+    }
+
+    // 热销菜品
+    if !report.top_dishes.is_empty() {
+        let char_width = layout.begin(&mut content, Section::Items);
+        push_section_title(&mut content, "TOP DISHES", char_width);
+        for dish in &report.top_dishes {
+            let amount = format!("${}", format_cents(dish.sales));
+            let prefix = format!("{} x ", dish.quantity);
+            // 菜名按剩余宽度截断，保证金额右对齐在同一行
+            let name_width = char_width.saturating_sub(prefix.len() + amount.len() + 1);
+            let name = crate::truncate_for_width(&dish.name, name_width);
+            content.push_str(&crate::format_table_row(
+                &format!("{}{}", prefix, crate::prepare_mixed_content(&name)),
+                &amount,
+                char_width,
+            ));
+        }
+    }
+
+    let char_width = layout.begin(&mut content, Section::Footer);
+    content.push_str(&rule('=', char_width));
+    content.push_str(&crate::format_table_row("Printed:", &chrono::Local::now().format("%m/%d/%Y %I:%M %p").to_string(), char_width));
+    content.push_str("\n\n\n\n"); // 为切纸预留空间
+    content.push_str(&TextSize::NORMAL.command());
+    content.push_str("\x1D\x56\x00"); // 全切
+    content
+}
+
+// ============= CSV导出 =============
+
+// CSV字段转义：包含逗号、引号或换行时加引号
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') || value.contains('\n') || value.contains('\r') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub(crate) fn report_to_csv(report: &SalesReport) -> String {
+    let mut rows: Vec<[String; 4]> = Vec::new();
+    let mut row = |section: &str, name: &str, count: Option<i64>, amount: Option<i64>| {
+        rows.push([
+            section.to_string(),
+            name.to_string(),
+            count.map(|c| c.to_string()).unwrap_or_default(),
+            amount.map(format_cents).unwrap_or_default(),
+        ]);
+    };
+
+    row("range", &format!("{} ~ {}", report.date_from, report.date_to), None, None);
+    row("summary", "Orders", Some(i64::from(report.order_count)), None);
+    row("summary", "Subtotal", None, Some(report.subtotal));
+    row("summary", "Discount", None, Some(report.discount));
+    row("summary", "Exemption", None, Some(report.exemption));
+    row("summary", "Tax", None, Some(report.tax));
+    row("summary", "Delivery Fee", None, Some(report.delivery_fee));
+    row("summary", "Retail Delivery Fee", None, Some(report.retail_delivery_fee));
+    row("summary", "Service Fee", None, Some(report.service_fee));
+    row("summary", "Tips", None, Some(report.tips));
+    row("summary", "Total", None, Some(report.total));
+    row("summary", "Cancelled", Some(i64::from(report.cancelled_count)), Some(report.cancelled_total));
+    for group in &report.by_paystyle {
+        row("paystyle", &group.label, Some(i64::from(group.count)), Some(group.total));
+    }
+    for group in &report.by_delivery_style {
+        row("delivery_style", &group.label, Some(i64::from(group.count)), Some(group.total));
+    }
+    for dish in &report.top_dishes {
+        row("top_dishes", &dish.name, Some(dish.quantity), Some(dish.sales));
+    }
+
+    // 带BOM，Excel打开中文不乱码
+    let mut csv = String::from("\u{FEFF}section,name,count,amount\r\n");
+    for fields in rows {
+        let line: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        csv.push_str(&line.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    fn order(order_id: &str, order_status: i32, paystyle: i32, delivery_style: i32) -> OrderData {
+        let mut order = test_support::order(order_id);
+        order.order_status = order_status;
+        order.paystyle = paystyle;
+        order.delivery_style = delivery_style;
+        order
+    }
+
+    #[test]
+    fn report_totals_skip_cancelled_orders() {
+        // 只点了一份麻婆豆腐的自取订单
+        let mut small = order("R2", 3, 0, 0);
+        small.dishes_array.truncate(1);
+        small.sub_total = "18.99".to_string();
+        small.discount_total = "0.00".to_string();
+        small.tax_fee = "1.57".to_string();
+        small.delivery_fee = "0.00".to_string();
+        small.convenience_fee = "0.00".to_string();
+        small.tip_fee = "2.00".to_string();
+        small.total = "22.56".to_string();
+
+        let mut small_cancelled = order("R4", 0, 0, 0);
+        small_cancelled.total = "10.00".to_string();
+
+        let orders = vec![
+            order("R1", 10, 1, 1),
+            small,
+            order("R3", 0, 1, 1),
+            small_cancelled,
+            // 需退款的订单还没有退款，仍计入营业额
+            order("R5", 5, 0, 0),
+        ];
+        let day = chrono::NaiveDate::from_ymd_opt(2025, 1, 15).unwrap();
+        let report = build_report(&orders, day, day, ReportSource::History);
+
+        assert_eq!(report.order_count, 3);
+        assert_eq!(report.subtotal, 11891);
+        assert_eq!(report.discount, 1000);
+        assert_eq!(report.tax, 1031);
+        assert_eq!(report.delivery_fee, 798);
+        assert_eq!(report.service_fee, 350);
+        assert_eq!(report.tips, 1700);
+        assert_eq!(report.total, 15420);
+        assert_eq!(report.cancelled_count, 2);
+        assert_eq!(report.cancelled_total, 7582);
+
+        let groups = |groups: &[GroupTotal]| groups.iter().map(|g| (g.code, g.count, g.total)).collect::<Vec<_>>();
+        assert_eq!(groups(&report.by_paystyle), [(0, 2, 8838), (1, 1, 6582)]);
+        assert_eq!(groups(&report.by_delivery_style), [(0, 2, 8838), (1, 1, 6582)]);
+
+        let dishes: Vec<_> = report.top_dishes.iter().map(|d| (d.name.as_str(), d.quantity, d.sales)).collect();
+        assert_eq!(
+            dishes,
+            [
+                ("宫保鸡丁 (Kung Pao Chicken)", 4, 4796),
+                ("麻婆豆腐 (Mapo Tofu)", 3, 5697),
+                ("白米饭 (Steamed Rice)", 2, 1398),
+            ]
+        );
+    }
+
+    #[test]
+    fn cents_round_trip() {
+        assert_eq!(parse_cents("65.82"), 6582);
+        assert_eq!(parse_cents(" 0.1 "), 10);
+        assert_eq!(parse_cents("abc"), 0);
+        assert_eq!(format_cents(-1050), "-10.50");
+        assert_eq!(format_cents(7), "0.07");
+    }
+}
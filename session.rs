@@ -0,0 +1,246 @@
+// 登录会话持久化
+//
+// 会话以加密形式保存在应用数据目录下的 session.dat 中：
+// Windows 使用 DPAPI (与当前用户绑定)，其他系统使用 AES-256-GCM，密钥保存在 session.key。
+
+use log::{info, warn, error};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::api_client::LoginData;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct StoredSession {
+    pub token: String,
+    pub user_id: String,
+    pub username: String,
+    pub rd_id: i32,
+    // 登录时使用的账号，用于token过期后自动重新登录
+    pub login_username: String,
+    // 前端MD5加密后的密码，仅在用户选择记住登录时保存
+    #[serde(default)]
+    pub password_md5: Option<String>,
+    pub saved_at: String,
+}
+
+impl StoredSession {
+    // 登录成功后创建会话，password_md5 为 None 时不保存密码
+    pub fn from_login(data: &LoginData, login_username: &str, password_md5: Option<String>) -> Self {
+        StoredSession {
+            token: data.token.clone(),
+            user_id: data.user_id.clone(),
+            username: data.username.clone(),
+            rd_id: data.rd_id,
+            login_username: login_username.to_string(),
+            password_md5,
+            saved_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        }
+    }
+}
+
+fn session_file_path() -> PathBuf {
+    crate::get_app_data_dir().join("session.dat")
+}
+
+// 读取并解密保存的会话，文件不存在或已损坏时返回None
+pub(crate) fn load_session() -> Option<StoredSession> {
+    let path = session_file_path();
+    if !path.exists() {
+        info!("🔐 [SESSION] 未找到保存的会话");
+        return None;
+    }
+
+    let encrypted = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) => {
+            error!("❌ [SESSION] 读取会话文件失败: {}", e);
+            return None;
+        }
+    };
+
+    let decrypted = match unprotect_data(&encrypted) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!("⚠️ [SESSION] 解密会话失败，已丢弃: {}", e);
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+    };
+
+    match serde_json::from_slice::<StoredSession>(&decrypted) {
+        Ok(session) => {
+            info!("✅ [SESSION] 已恢复用户 {} 的会话 (保存于 {})", session.username, session.saved_at);
+            Some(session)
+        }
+        Err(e) => {
+            warn!("⚠️ [SESSION] 会话数据格式错误，已丢弃: {}", e);
+            let _ = fs::remove_file(&path);
+            None
+        }
+    }
+}
+
+// 加密并保存会话
+pub(crate) fn save_session(session: &StoredSession) -> Result<(), String> {
+    let path = session_file_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("创建会话目录失败: {}", e))?;
+    }
+
+    let plain = serde_json::to_vec(session).map_err(|e| format!("序列化会话失败: {}", e))?;
+    let encrypted = protect_data(&plain)?;
+
+    fs::write(&path, encrypted).map_err(|e| format!("写入会话文件失败: {}", e))?;
+    info!("💾 [SESSION] 会话已加密保存: {}", path.display());
+    Ok(())
+}
+
+// 删除保存的会话
+pub(crate) fn clear_session() {
+    let path = session_file_path();
+    if path.exists() {
+        match fs::remove_file(&path) {
+            Ok(_) => info!("🗑️ [SESSION] 已删除保存的会话"),
+            Err(e) => error!("❌ [SESSION] 删除会话文件失败: {}", e),
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn protect_data(data: &[u8]) -> Result<Vec<u8>, String> {
+    use std::ptr;
+    use winapi::um::dpapi::CryptProtectData;
+    use winapi::um::errhandlingapi::GetLastError;
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::wincrypt::DATA_BLOB;
+
+    unsafe {
+        let mut input = DATA_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        };
+        let mut output = DATA_BLOB {
+            cbData: 0,
+            pbData: ptr::null_mut(),
+        };
+
+        let result = CryptProtectData(
+            &mut input,
+            ptr::null(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            0,
+            &mut output,
+        );
+        if result == 0 {
+            return Err(format!("CryptProtectData failed: Error {}", GetLastError()));
+        }
+
+        let encrypted = std::slice::from_raw_parts(output.pbData, output.cbData as usize).to_vec();
+        LocalFree(output.pbData as *mut _);
+        Ok(encrypted)
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn unprotect_data(data: &[u8]) -> Result<Vec<u8>, String> {
+    use std::ptr;
+    use winapi::um::dpapi::CryptUnprotectData;
+    use winapi::um::errhandlingapi::GetLastError;
+    use winapi::um::winbase::LocalFree;
+    use winapi::um::wincrypt::DATA_BLOB;
+
+    unsafe {
+        let mut input = DATA_BLOB {
+            cbData: data.len() as u32,
+            pbData: data.as_ptr() as *mut u8,
+        };
+        let mut output = DATA_BLOB {
+            cbData: 0,
+            pbData: ptr::null_mut(),
+        };
+
+        let result = CryptUnprotectData(
+            &mut input,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            0,
+            &mut output,
+        );
+        if result == 0 {
+            return Err(format!("CryptUnprotectData failed: Error {}", GetLastError()));
+        }
+
+        let decrypted = std::slice::from_raw_parts(output.pbData, output.cbData as usize).to_vec();
+        LocalFree(output.pbData as *mut _);
+        Ok(decrypted)
+    }
+}
+
+// 非Windows系统: 读取或生成本地密钥
+#[cfg(not(target_os = "windows"))]
+fn load_or_create_key() -> Result<[u8; 32], String> {
+    let key_path = crate::get_app_data_dir().join("session.key");
+
+    if let Ok(existing) = fs::read(&key_path) {
+        if existing.len() == 32 {
+            let mut key = [0u8; 32];
+            key.copy_from_slice(&existing);
+            return Ok(key);
+        }
+        warn!("⚠️ [SESSION] 会话密钥长度错误，重新生成");
+    }
+
+    let key: [u8; 32] = rand::random();
+    if let Some(dir) = key_path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("创建密钥目录失败: {}", e))?;
+    }
+    fs::write(&key_path, key).map_err(|e| format!("写入会话密钥失败: {}", e))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = fs::set_permissions(&key_path, fs::Permissions::from_mode(0o600));
+    }
+
+    Ok(key)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn protect_data(data: &[u8]) -> Result<Vec<u8>, String> {
+    use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+    use aes_gcm::{Aes256Gcm, Key};
+
+    let key = load_or_create_key()?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, data)
+        .map_err(|e| format!("加密会话失败: {}", e))?;
+
+    // 文件格式: 12字节nonce + 密文
+    let mut result = nonce.to_vec();
+    result.extend_from_slice(&ciphertext);
+    Ok(result)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn unprotect_data(data: &[u8]) -> Result<Vec<u8>, String> {
+    use aes_gcm::aead::{Aead, KeyInit};
+    use aes_gcm::{Aes256Gcm, Key, Nonce};
+
+    if data.len() < 12 {
+        return Err("会话文件长度不足".to_string());
+    }
+
+    let key = load_or_create_key()?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let (nonce, ciphertext) = data.split_at(12);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|e| format!("解密会话失败: {}", e))
+}
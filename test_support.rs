@@ -0,0 +1,74 @@
+// 测试用的应用上下文和示例数据
+//
+// 测试中 get_app_data_dir 指向临时目录，台账、订单历史和打印记录都不会写入真实的应用数据。
+// 非Windows系统上打印只输出到控制台，订单可以完整走一遍打印流程。
+
+use std::sync::{Arc, Mutex};
+
+use crate::events::{AppContext, Ctx, EventSink};
+use crate::{AppState, OrderData, PrinterConfig};
+
+// 匹配打印机配置库中的 Xprinter 80mm 小票打印机
+pub(crate) const TEST_PRINTER: &str = "XP-80C";
+
+pub(crate) struct TestContext {
+    state: AppState,
+    events: Mutex<Vec<(String, serde_json::Value)>>,
+}
+
+impl TestContext {
+    // 带一台启用的小票打印机的上下文
+    pub fn new() -> Arc<Self> {
+        let context = TestContext {
+            state: AppState::default(),
+            events: Mutex::new(Vec::new()),
+        };
+        *context.state.printers.lock().unwrap() = vec![printer(TEST_PRINTER)];
+        Arc::new(context)
+    }
+
+    pub fn ctx(self: &Arc<Self>) -> Ctx {
+        self.clone()
+    }
+
+    // 发送过的某个事件的内容 (按发送顺序)
+    pub fn events_named(&self, event: &str) -> Vec<serde_json::Value> {
+        self.events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| name == event)
+            .map(|(_, payload)| payload.clone())
+            .collect()
+    }
+}
+
+impl EventSink for TestContext {
+    fn emit_value(&self, event: &str, payload: serde_json::Value) {
+        self.events.lock().unwrap().push((event.to_string(), payload));
+    }
+}
+
+impl AppContext for TestContext {
+    fn app_state(&self) -> &AppState {
+        &self.state
+    }
+}
+
+pub(crate) fn printer(name: &str) -> PrinterConfig {
+    serde_json::from_value(serde_json::json!({
+        "name": name,
+        "width": 80,
+        "is_default": true,
+        "is_enabled": true,
+        "font_size": 0
+    }))
+    .unwrap()
+}
+
+// 示例订单，订单号不同的测试互不影响
+pub(crate) fn order(order_id: &str) -> OrderData {
+    let mut order = crate::sample_test_order();
+    order.order_id = order_id.to_string();
+    order
+}
@@ -0,0 +1,259 @@
+// WebSocket 消息协议
+//
+// 服务器推送的消息统一解析为 WsMessage。消息格式:
+//   {"type": "order_new", "v": 1, "data": {"order_id": "...", "order": {...}}}
+// 兼容旧格式: {"type": "order", "data": {"order_id": "..."}}、
+// {"type": "new_order", "order_id": "..."} 以及没有type、只有 order_id 或完整订单数据的消息
+// (和以前一样只通知前端，不自动打印)。
+// 无法识别的消息不再猜测订单ID，而是保存到隔离文件中供事后检查。
+
+use log::{info, warn, error};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::OrderData;
+
+// 当前支持的最高协议版本，没有 v 字段的消息视为版本1
+pub(crate) const PROTOCOL_VERSION: u64 = 1;
+
+// 隔离文件超过这个大小时改名为 .old，只保留一份旧文件
+const QUARANTINE_MAX_BYTES: u64 = 1024 * 1024;
+
+#[derive(Clone, Debug)]
+pub(crate) enum WsMessage {
+    // 新订单: 只有订单ID时由前端获取详情，附带完整订单时直接打印
+    OrderNew { order_id: String, order: Option<Box<OrderData>> },
+    // 没有type的旧格式订单消息: 只通知前端，附带完整订单时保存到本地历史
+    LegacyOrder { order_id: String, order: Option<Box<OrderData>> },
+    // 订单内容被修改
+    OrderUpdated { order_id: String, order: Option<Box<OrderData>> },
+    // 订单被取消
+    OrderCancelled { order_id: String, reason: Option<String> },
+    // 服务器的应用层心跳 (ping/pong)
+    Ping,
+    // 系统通知
+    System { level: String, message: String },
+}
+
+impl WsMessage {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WsMessage::OrderNew { .. } => "order_new",
+            WsMessage::LegacyOrder { .. } => "legacy_order",
+            WsMessage::OrderUpdated { .. } => "order_updated",
+            WsMessage::OrderCancelled { .. } => "order_cancelled",
+            WsMessage::Ping => "ping",
+            WsMessage::System { .. } => "system",
+        }
+    }
+}
+
+// 解析服务器消息，无法识别时返回原因
+pub(crate) fn parse_message(text: &str) -> Result<WsMessage, String> {
+    let msg: serde_json::Value = serde_json::from_str(text)
+        .map_err(|e| format!("不是有效的JSON: {}", e))?;
+
+    let Some(object) = msg.as_object() else {
+        return Err("消息不是JSON对象".to_string());
+    };
+
+    let version = match object.get("v").or_else(|| object.get("version")) {
+        None => 1,
+        Some(v) => v.as_u64().ok_or_else(|| format!("协议版本格式错误: {}", v))?,
+    };
+    if version == 0 || version > PROTOCOL_VERSION {
+        return Err(format!("不支持的协议版本: {} (最高支持 {})", version, PROTOCOL_VERSION));
+    }
+
+    let kind = match object.get("type") {
+        Some(serde_json::Value::String(kind)) => kind.replace('-', "_"),
+        Some(other) => return Err(format!("type字段格式错误: {}", other)),
+        None => {
+            // 旧格式: 没有type的完整订单数据或只有 order_id 的通知
+            if let Ok(order) = serde_json::from_value::<OrderData>(msg.clone()) {
+                return Ok(WsMessage::LegacyOrder {
+                    order_id: order.order_id.clone(),
+                    order: Some(Box::new(order)),
+                });
+            }
+            return order_id_of(&msg, &serde_json::Value::Null)
+                .map(|order_id| WsMessage::LegacyOrder { order_id, order: None })
+                .map_err(|e| format!("缺少type字段，且不是订单消息: {}", e));
+        }
+    };
+
+    let data = object.get("data").unwrap_or(&serde_json::Value::Null);
+
+    match kind.as_str() {
+        "order_new" | "order" | "new_order" => Ok(WsMessage::OrderNew {
+            order_id: order_id_of(&msg, data)?,
+            order: embedded_order(data)?,
+        }),
+        "order_updated" => Ok(WsMessage::OrderUpdated {
+            order_id: order_id_of(&msg, data)?,
+            order: embedded_order(data)?,
+        }),
+        "order_cancelled" | "order_canceled" => Ok(WsMessage::OrderCancelled {
+            order_id: order_id_of(&msg, data)?,
+            reason: data.get("reason").and_then(|r| r.as_str()).map(str::to_string),
+        }),
+        "ping" | "pong" | "heartbeat" => Ok(WsMessage::Ping),
+        "system" => {
+            let message = data.get("message")
+                .or_else(|| msg.get("message"))
+                .and_then(|m| m.as_str())
+                .ok_or_else(|| "系统消息缺少message字段".to_string())?;
+            let level = data.get("level")
+                .or_else(|| msg.get("level"))
+                .and_then(|l| l.as_str())
+                .unwrap_or("info");
+            Ok(WsMessage::System {
+                level: level.to_string(),
+                message: message.to_string(),
+            })
+        }
+        other => Err(format!("未知的消息类型: {}", other)),
+    }
+}
+
+// 订单ID在 data.order_id (新格式) 或顶层 order_id (旧格式)，必须是非空字符串或整数
+fn order_id_of(msg: &serde_json::Value, data: &serde_json::Value) -> Result<String, String> {
+    let value = data.get("order_id")
+        .or_else(|| msg.get("order_id"))
+        .ok_or_else(|| "缺少order_id".to_string())?;
+
+    let order_id = match value {
+        serde_json::Value::String(id) => id.trim().to_string(),
+        serde_json::Value::Number(id) if id.is_u64() => id.to_string(),
+        other => return Err(format!("order_id格式错误: {}", other)),
+    };
+
+    if order_id.is_empty() {
+        return Err("order_id为空".to_string());
+    }
+    Ok(order_id)
+}
+
+// data.order 中附带的完整订单 (可选)，存在但格式错误时整条消息视为无法识别
+fn embedded_order(data: &serde_json::Value) -> Result<Option<Box<OrderData>>, String> {
+    match data.get("order") {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(order) => serde_json::from_value::<OrderData>(order.clone())
+            .map(|order| Some(Box::new(order)))
+            .map_err(|e| format!("附带的订单数据格式错误: {}", e)),
+    }
+}
+
+fn quarantine_file_path() -> PathBuf {
+    crate::get_app_data_dir().join("ws_quarantine.jsonl")
+}
+
+// 把无法识别的消息保存到隔离文件 (每行一条JSON，内容已脱敏)
+pub(crate) fn quarantine_message(text: &str, reason: &str) {
+    warn!("🚧 [WS] 无法识别的消息已隔离: {}", reason);
+
+    let path = quarantine_file_path();
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+
+    if fs::metadata(&path).map(|m| m.len() > QUARANTINE_MAX_BYTES).unwrap_or(false) {
+        let old_path = path.with_extension("jsonl.old");
+        if let Err(e) = fs::rename(&path, &old_path) {
+            error!("❌ [WS] 轮换隔离文件失败: {}", e);
+        } else {
+            info!("🔄 [WS] 隔离文件已轮换: {}", old_path.display());
+        }
+    }
+
+    let entry = serde_json::json!({
+        "received_at": chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        "reason": reason,
+        "raw": crate::redact::redact(text),
+    });
+
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", entry));
+
+    if let Err(e) = result {
+        error!("❌ [WS] 写入隔离文件失败: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn full_order() -> serde_json::Value {
+        serde_json::to_value(crate::sample_test_order()).unwrap()
+    }
+
+    #[test]
+    fn typed_new_order_with_and_without_embedded_order() {
+        let message = parse_message(r#"{"type": "order_new", "v": 1, "data": {"order_id": "A1"}}"#).unwrap();
+        assert!(matches!(message, WsMessage::OrderNew { ref order_id, order: None } if order_id == "A1"));
+
+        let text = json!({ "type": "order_new", "data": { "order_id": "A1", "order": full_order() } }).to_string();
+        assert!(matches!(parse_message(&text).unwrap(), WsMessage::OrderNew { order: Some(_), .. }));
+    }
+
+    #[test]
+    fn legacy_typed_formats() {
+        let message = parse_message(r#"{"type": "order", "data": {"order_id": "A2"}}"#).unwrap();
+        assert!(matches!(message, WsMessage::OrderNew { ref order_id, order: None } if order_id == "A2"));
+
+        let message = parse_message(r#"{"type": "new-order", "order_id": 12345}"#).unwrap();
+        assert!(matches!(message, WsMessage::OrderNew { ref order_id, .. } if order_id == "12345"));
+    }
+
+    #[test]
+    fn typeless_bare_id_is_a_notification() {
+        let message = parse_message(r#"{"order_id": "A3"}"#).unwrap();
+        assert!(matches!(message, WsMessage::LegacyOrder { ref order_id, order: None } if order_id == "A3"));
+    }
+
+    #[test]
+    fn typeless_full_order_is_not_auto_printed() {
+        match parse_message(&full_order().to_string()).unwrap() {
+            WsMessage::LegacyOrder { order_id, order: Some(order) } => {
+                assert_eq!(order_id, "23410121749595834");
+                assert_eq!(order.order_id, order_id);
+            }
+            other => panic!("unexpected message: {}", other.kind()),
+        }
+    }
+
+    #[test]
+    fn typeless_without_order_id_is_rejected() {
+        assert!(parse_message(r#"{"hello": "world"}"#).is_err());
+        assert!(parse_message(r#"{"order_id": ""}"#).is_err());
+        assert!(parse_message(r#"{"order_id": {"nested": 1}}"#).is_err());
+    }
+
+    #[test]
+    fn cancellation_updates_and_control_messages() {
+        let message = parse_message(r#"{"type": "order_canceled", "data": {"order_id": "A4", "reason": "customer"}}"#).unwrap();
+        assert!(matches!(message, WsMessage::OrderCancelled { ref reason, .. } if reason.as_deref() == Some("customer")));
+
+        let message = parse_message(r#"{"type": "order_updated", "data": {"order_id": "A5"}}"#).unwrap();
+        assert!(matches!(message, WsMessage::OrderUpdated { order: None, .. }));
+
+        assert!(matches!(parse_message(r#"{"type": "pong"}"#).unwrap(), WsMessage::Ping));
+        let message = parse_message(r#"{"type": "system", "data": {"message": "维护通知"}}"#).unwrap();
+        assert!(matches!(message, WsMessage::System { ref level, .. } if level == "info"));
+    }
+
+    #[test]
+    fn malformed_messages_are_rejected() {
+        assert!(parse_message("order 123").is_err());
+        assert!(parse_message("[1, 2]").is_err());
+        assert!(parse_message(r#"{"type": "order_new", "v": 2, "data": {"order_id": "A6"}}"#).is_err());
+        assert!(parse_message(r#"{"type": "surprise", "data": {"order_id": "A7"}}"#).is_err());
+        assert!(parse_message(r#"{"type": "order_new", "data": {"order_id": "A8", "order": {"bad": true}}}"#).is_err());
+    }
+}